@@ -119,22 +119,6 @@ fn main() {
             gen_rs_content.push_str(&buf);
         }
 
-        '_target_volume: {
-            let target_volume = std::env::var("TARGET_VOLUME")
-                .unwrap_or("0.1".to_string())
-                .parse::<f32>()
-                .expect("TARGET_VOLUME could not be parsed as a float");
-
-            if target_volume < -1.0 || target_volume > 1.0 {
-                panic!("TARGET_VOLUME must be in range [-1, 1].");
-            }
-
-            gen_rs_content.push_str(&format!(
-                "/// The target loudness of the normalized songs\npub const TARGET_VOLUME: f32 = {};\n\n",
-                target_volume
-            ));
-        }
-
         '_encoding: {
             gen_rs_content.push_str(&format!(
                 "/// The encoding of the embedded files\npub const ENCODING: Encoding = Encoding::{};\n\n",
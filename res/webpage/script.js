@@ -7,12 +7,65 @@ const disableSongs = document.getElementById('disable-selected');
 const enableSongs = document.getElementById('enable-selected');
 const deleteSongs = document.getElementById('delete-selected');
 const playSongs = document.getElementById('play-selected');
+const trimStart = document.getElementById('trim-start-number');
+const trimEnd = document.getElementById('trim-end-number');
+const trimSongs = document.getElementById('trim-selected');
+const resetTrimSongs = document.getElementById('reset-trim-selected');
+const cueSongs = document.getElementById('cue-selected');
+const resetCueSongs = document.getElementById('reset-cue-selected');
+// The start and end of each song's part without silence and excerpt by filename
+let songTrims = {};
+let songCues = {};
+
+const nowPlayingTitle = document.getElementById("now-playing-title");
+const nowPlayingPosition = document.getElementById("now-playing-position");
+const pausePlayback = document.getElementById("pause-playback");
+const skipPlayback = document.getElementById("skip-playback");
+const stopPlayback = document.getElementById("stop-playback");
+const stopAllPlayback = document.getElementById("stop-all-playback");
+let playbackPaused = false;
 
 const timetableForm = document.getElementById('timetable');
+const timetableDaysTable = document.getElementById('timetable-days-table');
+const timetableBreaksTable = document.getElementById('timetable-breaks-table');
+const addBreak = document.getElementById('add-break');
+const removeBreak = document.getElementById('remove-break');
+// The prefixes of the IDs of each day's checkboxes, from Monday to Sunday
+const dayIds = ["mon", "tue", "wed", "thu", "fri", "sat", "sun"];
+const timetableSelect = document.getElementById('timetable-select');
+const addTimetable = document.getElementById('add-timetable');
+const renameTimetable = document.getElementById('rename-timetable');
+const removeTimetable = document.getElementById('remove-timetable');
+// The name of the edited timetable, kept in the hash of the URL over reloads, or empty for the first one
+const currentTimetable = decodeURIComponent(location.hash.slice(1));
+
+const timetableToday = document.getElementById('timetable-today');
+const weekdayTimetablesRow = document.getElementById('weekday-timetables-row');
+const saveWeekdayTimetables = document.getElementById('save-weekday-timetables');
+const calendarTable = document.getElementById('calendar-table');
+const calendarDate = document.getElementById('calendar-date');
+const calendarTimetable = document.getElementById('calendar-timetable');
+const setCalendarTimetable = document.getElementById('set-calendar-timetable');
+const holidaysTable = document.getElementById('holidays-table');
+const holidayFirst = document.getElementById('holiday-first');
+const holidayLast = document.getElementById('holiday-last');
+const holidayName = document.getElementById('holiday-name');
+const addHoliday = document.getElementById('add-holiday');
+const holidayFile = document.getElementById('holiday-file');
+// The rows of the first day, the last day and the name of each range of days off
+let holidays = [];
 
 const addSongForm = document.getElementById('add-song-form');
 
 const utcOffset = document.getElementById("utc-offset-number");
+const timeZone = document.getElementById("time-zone-input");
+const timeZoneList = document.getElementById("time-zone-list");
+const targetLoudness = document.getElementById("target-loudness-number");
+const fadeIn = document.getElementById("fade-in-number");
+const fadeOut = document.getElementById("fade-out-number");
+const crossfade = document.getElementById("crossfade-number");
+const gapless = document.getElementById("gapless-checkbox");
+const outputDevice = document.getElementById("output-device-select");
 
 const timeDisplay = document.getElementById("server-time");
 
@@ -26,6 +79,9 @@ const eventTriggerTime = document.getElementById("event-trigger-time");
 const eventRepeatTime = document.getElementById("event-repeat-time");
 const eventRepeatAmount = document.getElementById("event-repeat-amount");
 const eventAutodeleteSwitch = document.getElementById("event-autodelete-switch");
+const eventLoudness = document.getElementById("event-loudness");
+const eventPriority = document.getElementById("event-priority");
+const eventZones = document.getElementById("event-zones");
 const addEvent = document.getElementById("add-event");
 
 // Songs to be disabled or deleted
@@ -94,6 +150,12 @@ songListTable.addEventListener('click', function (e) {
     } else {
         selectedSongs.splice(arrayIndex, 1);
     }
+
+    // Show the played part of the last selected song, so it can be adjusted
+    const last = selectedSongs[selectedSongs.length - 1];
+    const trim = songCues[last] || songTrims[last];
+    trimStart.value = trim ? trim[0] : "";
+    trimEnd.value = trim ? trim[1] : "";
 });
 
 // Select event from table
@@ -202,6 +264,296 @@ playSongs.addEventListener('click', function (e) {
     if (!noRefresh) { location.reload(); }
 });
 
+// Set the played part of the selected songs
+trimSongs.addEventListener('click', function (e) {
+    if (selectedSongs.length === 0 || trimStart.value === "" || trimEnd.value === "") {
+        return;
+    }
+
+    postSongParts("/api/set-song-trims", selectedSongs.map(filename => [filename, Number(trimStart.value), Number(trimEnd.value)]));
+});
+
+// Trim the selected songs at their detected silence
+resetTrimSongs.addEventListener('click', function (e) {
+    if (selectedSongs.length === 0) {
+        return;
+    }
+
+    postSongParts("/api/set-song-trims", selectedSongs.map(filename => [filename, null, null]));
+});
+
+// Play only the excerpt between the cue points of the selected songs
+cueSongs.addEventListener('click', function (e) {
+    if (selectedSongs.length === 0 || trimStart.value === "" || trimEnd.value === "") {
+        return;
+    }
+
+    postSongParts("/api/set-song-cues", selectedSongs.map(filename => [filename, Number(trimStart.value), Number(trimEnd.value)]));
+});
+
+// Remove the cue points of the selected songs
+resetCueSongs.addEventListener('click', function (e) {
+    if (selectedSongs.length === 0) {
+        return;
+    }
+
+    postSongParts("/api/set-song-cues", selectedSongs.map(filename => [filename, null, null]));
+});
+
+function postSongParts(url, rows) {
+    fetch(url, {
+        method: 'POST',
+        headers: {
+            "Content-Type": "application/csv"
+        },
+        body: rows.map(arrayToCsv).join('\r\n')
+    }).then(response => {
+        if (!response.ok) {
+            throw new Error(`HTTP error! Status: ${response.status}`);
+        }
+
+        if (!noRefresh) { location.reload(); }
+    }).catch(err => console.error("Song part error:", err));
+}
+
+// Posts rows to a timetable or calendar endpoint, then reloads with `timetable` being edited
+function postTimetableRows(url, rows, timetable) {
+    fetch(url, {
+        method: 'POST',
+        headers: {
+            "Content-Type": "application/csv"
+        },
+        body: rows.map(arrayToCsv).join('\r\n')
+    }).then(response => {
+        if (!response.ok) {
+            throw new Error(`HTTP error! Status: ${response.status}`);
+        }
+
+        location.hash = encodeURIComponent(timetable);
+        if (!noRefresh) { location.reload(); }
+    }).catch(err => {
+        console.error("Timetable error:", err);
+        alert("The timetables could not be changed. Names must be unique and the last timetable cannot be deleted.");
+    });
+}
+
+// Returns the query selecting the edited timetable for the timetable endpoints
+function timetableQuery() {
+    return currentTimetable === "" ? "" : "?timetable=" + encodeURIComponent(currentTimetable);
+}
+
+// Returns the options of a select of the timetables, which selects `selected`
+function timetableOptions(names, selected) {
+    return names.map(name => {
+        const option = document.createElement("option");
+        option.value = name;
+        option.textContent = name;
+        option.selected = name === selected;
+        return option;
+    });
+}
+
+function postPlayback(url, body = "") {
+    fetch(url, { method: 'POST', body: body })
+        .then(response => response.text())
+        .then(data => {
+            console.log("Server response:", data);
+        })
+        .catch(error => {
+            console.error("Error:", error);
+        })
+        .then(updateNowPlaying);
+}
+
+pausePlayback.addEventListener('click', () => {
+    postPlayback(playbackPaused ? "/api/resume" : "/api/pause");
+});
+
+skipPlayback.addEventListener('click', () => postPlayback("/api/skip"));
+
+stopPlayback.addEventListener('click', () => postPlayback("/api/stop"));
+
+stopAllPlayback.addEventListener('click', () => postPlayback("/api/stop-all"));
+
+nowPlayingPosition.addEventListener('change', () => {
+    postPlayback("/api/seek", String(nowPlayingPosition.value));
+});
+
+function formatSeconds(secs) {
+    const m = Math.floor(secs / 60);
+    const s = String(Math.floor(secs % 60)).padStart(2, '0');
+    return `${m}:${s}`;
+}
+
+// Labels a song with its artist and title if it is tagged, or its filename otherwise,
+// followed by its duration if it is known
+function songLabel(filename, duration, title, artist) {
+    let label = filename;
+
+    if (title) {
+        label = artist ? `${artist} – ${title}` : title;
+    }
+
+    if (typeof duration === 'number') {
+        label += ` (${formatSeconds(duration)})`;
+    }
+
+    return label;
+}
+
+// Show the currently playing track
+function updateNowPlaying() {
+    fetchText("data/now-playing")
+        .then(text => {
+            const [name, position, duration, paused] = csvToValue(text.trim(), defaultSeparator, defaultStrMarker);
+            const playing = name !== undefined;
+
+            for (const button of [pausePlayback, skipPlayback, stopPlayback]) {
+                button.disabled = !playing;
+            }
+
+            playbackPaused = playing && paused;
+            pausePlayback.innerHTML = playbackPaused ? "Resume" : "Pause";
+
+            if (!playing) {
+                nowPlayingTitle.innerHTML = "Nothing is playing";
+                nowPlayingPosition.disabled = true;
+                nowPlayingPosition.max = 0;
+                nowPlayingPosition.value = 0;
+                return;
+            }
+
+            const length = duration === null ? "" : " / " + formatSeconds(duration);
+            nowPlayingTitle.innerText = `${name} (${formatSeconds(position)}${length})${paused ? " – paused" : ""}`;
+
+            nowPlayingPosition.disabled = duration === null;
+            nowPlayingPosition.max = duration === null ? 0 : Math.floor(duration);
+            if (document.activeElement !== nowPlayingPosition) {
+                nowPlayingPosition.value = Math.floor(position);
+            }
+        })
+        .catch(err => console.error("Fetch error:", err));
+}
+
+addBreak.addEventListener("click", () => addBreakRow());
+
+removeBreak.addEventListener("click", () => {
+    if (breakCount() > 0) {
+        timetableDaysTable.deleteRow(-1);
+        timetableBreaksTable.deleteRow(-1);
+    }
+});
+
+// Edit another timetable
+timetableSelect.addEventListener("change", () => {
+    location.hash = encodeURIComponent(timetableSelect.value);
+    location.reload();
+});
+
+// Add a timetable as a copy of the edited one
+addTimetable.addEventListener("click", () => {
+    const name = prompt("Name of the new timetable:");
+    if (!name) {
+        return;
+    }
+
+    postTimetableRows("/api/add-timetable", [[name, timetableSelect.value]], name);
+});
+
+renameTimetable.addEventListener("click", () => {
+    const name = prompt("New name of the timetable:", timetableSelect.value);
+    if (!name || name === timetableSelect.value) {
+        return;
+    }
+
+    postTimetableRows("/api/rename-timetable", [[timetableSelect.value, name]], name);
+});
+
+removeTimetable.addEventListener("click", () => {
+    if (!confirm(`Delete the timetable "${timetableSelect.value}"?`)) {
+        return;
+    }
+
+    postTimetableRows("/api/remove-timetable", [[timetableSelect.value]], "");
+});
+
+saveWeekdayTimetables.addEventListener("click", () => {
+    postTimetableRows(
+        "/api/set-weekday-timetables",
+        [dayIds.map(day => document.getElementById("weekday-timetable-" + day).value)],
+        currentTimetable
+    );
+});
+
+// Use a timetable on the chosen date instead of the one of its weekday
+setCalendarTimetable.addEventListener("click", () => {
+    if (calendarDate.value === "") {
+        return;
+    }
+
+    postTimetableRows("/api/switch-timetable", [[calendarDate.value, calendarTimetable.value]], currentTimetable);
+});
+
+// Use the timetable of its weekday again on a date of the calendar
+calendarTable.addEventListener("click", e => {
+    const button = e.target.closest("button");
+    if (button === null) {
+        return;
+    }
+
+    postTimetableRows("/api/switch-timetable", [[button.dataset.date]], currentTimetable);
+});
+
+addHoliday.addEventListener("click", () => {
+    if (holidayFirst.value === "") {
+        return;
+    }
+
+    postTimetableRows(
+        "/api/add-holiday",
+        [[holidayFirst.value, holidayLast.value || holidayFirst.value, holidayName.value]],
+        currentTimetable
+    );
+});
+
+// Remove a range of days off by sending the other ones
+holidaysTable.addEventListener("click", e => {
+    const button = e.target.closest("button");
+    if (button === null) {
+        return;
+    }
+
+    const index = Number(button.dataset.index);
+    postTimetableRows("/api/set-holidays", holidays.filter((_, i) => i !== index), currentTimetable);
+});
+
+// Add the events of an iCalendar file as days off
+holidayFile.addEventListener("change", async () => {
+    const file = holidayFile.files[0];
+    if (file === undefined) {
+        return;
+    }
+
+    fetch("/api/import-holidays", {
+        method: 'POST',
+        headers: {
+            "Content-Type": "text/calendar"
+        },
+        body: await file.text()
+    })
+        .then(response => response.text())
+        .then(data => {
+            alert(data.trim());
+            if (!noRefresh) { location.reload(); }
+        })
+        .catch(err => console.error("Import error:", err));
+});
+
+// The UTC offset only applies without a time zone
+timeZone.addEventListener("input", () => {
+    utcOffset.disabled = timeZone.value.trim() !== "";
+});
+
 // Submit timetable
 timetableForm.addEventListener("submit", e => {
     timetableSubmit(e, () => { if (!noRefresh) { location.reload(); } })
@@ -303,27 +655,24 @@ function timetableSubmit(event, callback = () => {}) {
     // Prevent default form submission
     event.preventDefault();
 
-    const checkboxes = document.querySelectorAll("#timetable input[type=checkbox]");
-    const times = document.querySelectorAll("#timetable input[type=time]");
     const offset = document.getElementById("utc-offset-number");
+    const breaks = breakCount();
 
     const checkboxLines = [];
-    for (let row = 0; row < 8; row++) {
-        const rowArr = [];
-        for (let col = 0; col < 5; col++) {
-            const index = row * 5 + col; // row-major index
-            rowArr.push(checkboxes[index].checked);
-        }
-        checkboxLines.push(arrayToCsv(rowArr));
+    for (let row = 0; row < breaks; row++) {
+        checkboxLines.push(arrayToCsv(dayIds.map(day => document.getElementById(day + row).checked)));
     }
 
     const timeLines = [];
-    for (let row = 0; row < 8; row++) {
+    for (let row = 0; row < breaks; row++) {
         const rowArr = [];
-        for (let col = 0; col < 2; col++) {
-            const index = row * 2 + col; // row-major index
-            rowArr.push(times[index].value);
-        }
+        rowArr.push(document.getElementById("break-start" + row).value);
+        rowArr.push(document.getElementById("break-end" + row).value);
+        // An empty loudness is sent as an empty string, so the break uses the default
+        // and the strategy stays in its column
+        rowArr.push(loudnessOrNull(document.getElementById("break-loudness" + row)) ?? "");
+        rowArr.push(document.getElementById("break-strategy" + row).value);
+        rowArr.push(...zoneNames(document.getElementById("break-zones" + row)));
         timeLines.push(arrayToCsv(rowArr));
     }
 
@@ -332,9 +681,10 @@ function timetableSubmit(event, callback = () => {}) {
 
     let finished = 0;
 
-    fetch("/api/set-timetable", {
+    // The days are set after the breaks, as the server only accepts them for the current number of breaks
+    fetch("/api/set-breaks" + timetableQuery(), {
         method: form.method,
-        body: checkboxLines.join('\r\n')
+        body: timeLines.join('\r\n')
     })
         .then(response => response.text())
         .then(data => {
@@ -345,14 +695,32 @@ function timetableSubmit(event, callback = () => {}) {
         })
         .then(() => {
             finished++;
-            if (finished === 3) {
+            if (finished === 6) {
+                callback();
+            }
+
+            return fetch("/api/set-timetable" + timetableQuery(), {
+                method: form.method,
+                body: checkboxLines.join('\r\n')
+            });
+        })
+        .then(response => response.text())
+        .then(data => {
+            console.log("Server response:", data);
+        })
+        .catch(error => {
+            console.error("Error:", error);
+        })
+        .then(() => {
+            finished++;
+            if (finished === 6) {
                 callback();
             }
         });
 
-    fetch("/api/set-breaks", {
+    fetch("/api/set-target-loudness", {
         method: form.method,
-        body: timeLines.join('\r\n')
+        body: String(targetLoudness.value)
     })
         .then(response => response.text())
         .then(data => {
@@ -363,14 +731,14 @@ function timetableSubmit(event, callback = () => {}) {
         })
         .then(() => {
             finished++;
-            if (finished === 3) {
+            if (finished === 6) {
                 callback();
             }
         });
 
-    fetch("/api/set-utc-offset", {
+    fetch("/api/set-fades", {
         method: form.method,
-        body: String(offset.value)
+        body: arrayToCsv([Number(fadeIn.value), Number(fadeOut.value), Number(crossfade.value), gapless.checked])
     })
         .then(response => response.text())
         .then(data => {
@@ -381,7 +749,46 @@ function timetableSubmit(event, callback = () => {}) {
         })
         .then(() => {
             finished++;
-            if (finished === 3) {
+            if (finished === 6) {
+                callback();
+            }
+        });
+
+    // The host and the name of a device are stored as CSV in the value of its option
+    fetch("/api/set-output-device", {
+        method: form.method,
+        body: outputDevice.value
+    })
+        .then(response => response.text())
+        .then(data => {
+            console.log("Server response:", data);
+        })
+        .catch(error => {
+            console.error("Error:", error);
+        })
+        .then(() => {
+            finished++;
+            if (finished === 6) {
+                callback();
+            }
+        });
+
+    // A named time zone follows its daylight saving time, so it replaces the fixed offset
+    const zone = timeZone.value.trim();
+    fetch(zone === "" ? "/api/set-utc-offset" : "/api/set-time-zone", {
+        method: form.method,
+        body: zone === "" ? String(offset.value) : zone
+    })
+        .then(response => response.text())
+        .then(data => {
+            console.log("Server response:", data);
+        })
+        .catch(error => {
+            console.error("Error:", error);
+        })
+        .then(() => {
+            finished++;
+            if (finished === 6) {
                 callback();
             }
         });
@@ -397,22 +804,54 @@ function submitMultipleFiles(event, callback = () => {}) {
     for (const file of files) {
         const prefix = new TextEncoder().encode(file.name + ':');
         const payload = new Blob([prefix, file], { type: "application/octet-stream" });
-
-        fetch("/api/add-song", {
+        const post = url => fetch(url, {
             method: 'POST',
             headers: {
                 "Content-Type": "application/octet-stream"
             },
             body: payload
-        }).then(() => {
-            finished++
-            if (finished === files.length) {
-                callback();
-            }
         });
+
+        // The server lists the songs a new one is likely a copy of after analyzing it, and only adds it if asked again
+        post("/api/add-song")
+            .then(() => waitForUpload(file.name))
+            .then(row => {
+                if (row !== null && row[1] === "duplicate" &&
+                    confirm(`"${file.name}" sounds like:\n${row.slice(2).join("\n")}\n\nUpload it anyway?`)) {
+                    return post("/api/add-song?allow-duplicates=true").then(() => waitForUpload(file.name));
+                }
+            })
+            .catch(error => {
+                console.error("Error:", error);
+            })
+            .then(() => {
+                finished++
+                if (finished === files.length) {
+                    callback();
+                }
+            });
     }
 }
 
+// Resolves with the row of an uploaded song in the uploads once the server has analyzed it,
+// or with null if it was added
+function waitForUpload(name) {
+    return fetchText("/data/uploads.csv").then(csvText => {
+        const row = csvText
+            .trim()
+            .split("\r\n")
+            .filter(line => line !== "")
+            .map(line => csvToValue(line, defaultSeparator, defaultStrMarker))
+            .find(row => row[0] === name);
+
+        if (row !== undefined && row[1] === "analyzing") {
+            return new Promise(resolve => setTimeout(resolve, 1000)).then(() => waitForUpload(name));
+        }
+
+        return row ?? null;
+    });
+}
+
 // Submits a new event
 function addEventSubmit(event, callback = () => {}) {
     event.preventDefault();
@@ -438,6 +877,10 @@ function addEventSubmit(event, callback = () => {}) {
     const prefix = new TextEncoder().encode(serializedUtf8);
     const payload = new Blob([prefix, eventSound.files[0]], { type: "application/octet-stream" });
 
+    const loudness = loudnessOrNull(eventLoudness);
+    const priority = eventPriority.value;
+    const zones = zoneNames(eventZones);
+
     // ## Submitting ##
     fetch("/api/add-event", {
         method: 'POST',
@@ -445,6 +888,42 @@ function addEventSubmit(event, callback = () => {}) {
             "Content-Type": "application/octet-stream"
         },
         body: payload
+    }).then(() => {
+        if (loudness === null) {
+            return;
+        }
+
+        return fetch("/api/set-event-loudness", {
+            method: 'POST',
+            headers: {
+                "Content-Type": "application/csv"
+            },
+            body: arrayToCsv([eventName.value, loudness])
+        });
+    }).then(() => {
+        if (priority === "normal") {
+            return;
+        }
+
+        return fetch("/api/set-event-priority", {
+            method: 'POST',
+            headers: {
+                "Content-Type": "application/csv"
+            },
+            body: arrayToCsv([eventName.value, priority])
+        });
+    }).then(() => {
+        if (zones.length === 0) {
+            return;
+        }
+
+        return fetch("/api/set-event-zones", {
+            method: 'POST',
+            headers: {
+                "Content-Type": "application/csv"
+            },
+            body: arrayToCsv([eventName.value, ...zones])
+        });
     }).then(callback);
 }
 
@@ -464,6 +943,56 @@ function removeEventSubmit(event, callback = () => {}) {
     }).then(callback);
 }
 
+// Returns the comma-separated zone names in a text input, without empty ones
+function zoneNames(input) {
+    return input.value.split(",").map(name => name.trim()).filter(name => name !== "");
+}
+
+// Returns the number of breaks in the timetable form
+function breakCount() {
+    return timetableBreaksTable.rows.length - 1;
+}
+
+// Appends a break to the timetable form, which is disabled on every day
+function addBreakRow() {
+    const i = breakCount();
+
+    const dayRow = timetableDaysTable.insertRow(-1);
+    dayRow.insertCell(-1).textContent = i + ".";
+    for (const day of dayIds) {
+        dayRow.insertCell(-1).innerHTML = `
+            <input type="checkbox" id="${day}${i}" name="${day}${i}">
+            <label for="${day}${i}"></label>`;
+    }
+
+    const breakRow = timetableBreaksTable.insertRow(-1);
+    breakRow.insertCell(-1).textContent = i + ".";
+    breakRow.insertCell(-1).innerHTML = `
+        <input type="time" class="break-timer no-picker" step="1" id="break-start${i}" name="break-start${i}" required>
+        <label for="break-start${i}"></label>`;
+    breakRow.insertCell(-1).innerHTML = `
+        <input type="time" class="break-timer no-picker" step="1" id="break-end${i}" name="break-end${i}" required>
+        <label for="break-end${i}"></label>`;
+    breakRow.insertCell(-1).innerHTML = `
+        <input type="number" class="break-loudness no-arrows" step="0.1" min="-40" max="-5" id="break-loudness${i}" name="break-loudness${i}" placeholder="Default">
+        <label for="break-loudness${i}"></label>`;
+    breakRow.insertCell(-1).innerHTML = `
+        <select class="break-strategy" id="break-strategy${i}" name="break-strategy${i}">
+            <option value="single">Single song</option>
+            <option value="fill" selected>Fill</option>
+            <option value="fill-with-fade">Fill with fade</option>
+        </select>
+        <label for="break-strategy${i}"></label>`;
+    breakRow.insertCell(-1).innerHTML = `
+        <input type="text" class="break-zones" id="break-zones${i}" name="break-zones${i}" placeholder="Default">
+        <label for="break-zones${i}"></label>`;
+}
+
+// Returns the number in a loudness input, or `null` if it is empty
+function loudnessOrNull(input) {
+    return input.value === "" ? null : Number(input.value);
+}
+
 // Split a string at the given separator values, while skipping splitting inside the markers
 function splitWithMarker(input, sep, str_mkr) {
     const result = [];
@@ -537,6 +1066,9 @@ async function main() {
     // Query server time
     let serverTime = Number(await fetchText("/data/server-time-seconds"));
 
+    updateNowPlaying();
+    setInterval(updateNowPlaying, 1000);
+
     setInterval(() => {
         serverTime++;
         const h = String(Math.floor(serverTime / 3600)).padStart(2, '0');
@@ -546,7 +1078,7 @@ async function main() {
     }, 1000);
 
     // Fetch the timetable
-    fetch("data/timetable.csv")
+    fetch("data/timetable.csv" + timetableQuery())
         .then(res => {
             if (!res.ok) {
                 throw new Error("HTTP Error" + res.status);
@@ -555,19 +1087,20 @@ async function main() {
         })
         .then(csvText => {
             // Simple CSV parsing (splitting by newlines and commas)
-            const csvRows = csvText.trim().split("\r\n").map(line => csvToValue(line, defaultSeparator, defaultStrMarker));
-            const days = ["mon", "tue", "wed", "thu", "fri"];
+            const csvRows = csvText.trim().split("\r\n").filter(line => line !== "").map(line => csvToValue(line, defaultSeparator, defaultStrMarker));
+
+            for (let j = 0; j < csvRows.length; j++) {
+                while (breakCount() <= j) { addBreakRow(); }
 
-            for (let i = 0; i < 5; i++) {
-                for (let j = 0; j < 8; j++) {
-                    document.getElementById(days[i] + j).checked = csvRows[j][i];
+                for (let i = 0; i < dayIds.length; i++) {
+                    document.getElementById(dayIds[i] + j).checked = csvRows[j][i];
                 }
             }
         })
         .catch(err => console.error("Fetch error:", err));
 
     // Fetch the breaks
-    fetch("data/breaks.csv")
+    fetch("data/breaks.csv" + timetableQuery())
         .then(res => {
             if (!res.ok) {
                 throw new Error("HTTP Error" + res.status);
@@ -576,33 +1109,149 @@ async function main() {
         })
         .then(csvText => {
             // Simple CSV parsing (splitting by newlines and commas)
-            const csvRows = csvText.trim().split("\r\n").map(line => csvToValue(line, defaultSeparator, defaultStrMarker));
+            const csvRows = csvText.trim().split("\r\n").filter(line => line !== "").map(line => csvToValue(line, defaultSeparator, defaultStrMarker));
+
+            for (let i = 0; i < csvRows.length; i++) {
+                while (breakCount() <= i) { addBreakRow(); }
 
-            for (let i = 0; i < 8; i++) {
                 document.getElementById("break-start" + i).value = csvRows[i][0]
                 document.getElementById("break-end" + i).value = csvRows[i][1]
+                document.getElementById("break-loudness" + i).value = csvRows[i][2] ?? ""
+                document.getElementById("break-strategy" + i).value = csvRows[i][3] ?? "fill"
+                document.getElementById("break-zones" + i).value = csvRows[i].slice(4).join(", ")
             }
         })
         .catch(err => console.error("Fetch error:", err));
 
-    // Fetch UTC offset
-    fetch("data/utc-offset.bin")
-        .then(res => {
-            if (!res.ok) {
-                throw new Error("HTTP Error" + res.status);
+    // Fetch the timetables, the ones used on each weekday and on the dates of the calendar
+    Promise.all([
+        fetchText("data/timetables.csv"),
+        fetchText("data/weekday-timetables.csv"),
+        fetchText("data/calendar.csv"),
+        fetchText("data/timetable-on.csv"),
+        fetchText("data/holidays.csv")
+    ])
+        .then(([timetablesText, weekdaysText, calendarText, todayText, holidaysText]) => {
+            const rows = text => text.trim().split("\r\n").filter(line => line !== "").map(line => csvToValue(line, defaultSeparator, defaultStrMarker));
+            const names = rows(timetablesText).map(row => row[0]);
+
+            timetableSelect.replaceChildren(...timetableOptions(names, currentTimetable || names[0]));
+            calendarTimetable.replaceChildren(...timetableOptions(names, currentTimetable || names[0]));
+
+            const weekdays = rows(weekdaysText)[0] ?? [];
+            weekdayTimetablesRow.replaceChildren(...dayIds.map((day, i) => {
+                const cell = document.createElement("td");
+                const select = document.createElement("select");
+                select.id = "weekday-timetable-" + day;
+                select.replaceChildren(...timetableOptions(names, weekdays[i]));
+                cell.appendChild(select);
+                return cell;
+            }));
+
+            for (const [date, name] of rows(calendarText)) {
+                const row = calendarTable.insertRow(-1);
+                row.insertCell(-1).textContent = date;
+                row.insertCell(-1).textContent = name;
+
+                const button = document.createElement("button");
+                button.type = "button";
+                button.className = "dangerous";
+                button.textContent = "Remove";
+                button.dataset.date = date;
+                row.insertCell(-1).appendChild(button);
+            }
+
+            holidays = rows(holidaysText);
+            holidays.forEach(([first, last, holiday], i) => {
+                const row = holidaysTable.insertRow(-1);
+                row.insertCell(-1).textContent = first;
+                row.insertCell(-1).textContent = last;
+                row.insertCell(-1).textContent = holiday ?? "";
+
+                const button = document.createElement("button");
+                button.type = "button";
+                button.className = "dangerous";
+                button.textContent = "Remove";
+                button.dataset.index = i;
+                row.insertCell(-1).appendChild(button);
+            });
+
+            const [date, name, source, holiday] = rows(todayText)[0];
+            calendarDate.value = date;
+            holidayFirst.value = date;
+            if (holiday != null) {
+                timetableToday.textContent = `Today: no breaks${holiday === "" ? "" : ` (${holiday})`}`;
+            } else {
+                timetableToday.textContent = `Today: ${name}${source === "calendar" ? " (from the calendar)" : ""}`;
             }
-            return res.arrayBuffer();
         })
-        .then(buf => {
-            const int8View = new Int8Array(buf);
+        .catch(err => console.error("Fetch error:", err));
 
-            const num = int8View[0];
+    // Fetch the time zone and its current UTC offset
+    fetchText("data/time-zone.csv")
+        .then(text => {
+            const [name, offsetMinutes] = csvToValue(text.trim(), defaultSeparator, defaultStrMarker);
+            timeZone.value = name ?? "";
+            utcOffset.value = offsetMinutes / 60;
+            utcOffset.disabled = name != null;
+        })
+        .catch(err => console.error("Fetch error:", err));
 
-            if (buf.byteLength !== 1 || num < -24 || num > 24) {
-                throw new Error("Invalid response")
+    // Fetch the time zones offered for the time zone input
+    fetchText("data/time-zones.csv")
+        .then(text => {
+            for (const line of text.trim().split("\r\n").filter(line => line !== "")) {
+                const option = document.createElement("option");
+                option.value = csvToValue(line, defaultSeparator, defaultStrMarker)[0];
+                timeZoneList.appendChild(option);
             }
+        })
+        .catch(err => console.error("Fetch error:", err));
 
-            utcOffset.value = num
+    // Fetch the target loudness
+    fetchText("data/target-loudness")
+        .then(text => { targetLoudness.value = Number(text) })
+        .catch(err => console.error("Fetch error:", err));
+
+    // Fetch the fade-in, fade-out and crossfade times
+    fetchText("data/fades.csv")
+        .then(text => {
+            const [fadeInSecs, fadeOutSecs, crossfadeSecs, isGapless] = csvToValue(text.trim(), defaultSeparator, defaultStrMarker);
+            fadeIn.value = fadeInSecs;
+            fadeOut.value = fadeOutSecs;
+            crossfade.value = crossfadeSecs ?? 0;
+            gapless.checked = isGapless ?? true;
+        })
+        .catch(err => console.error("Fetch error:", err));
+
+    // Fetch the available output devices and the chosen one
+    Promise.all([fetchText("data/devices"), fetchText("data/output-device.csv")])
+        .then(([devicesText, chosenText]) => {
+            const options = new Set([""]);
+            const addOption = (host, name, label) => {
+                const value = arrayToCsv([host, name]);
+                if (options.has(value)) { return; }
+
+                const option = document.createElement("option");
+                option.value = value;
+                option.textContent = label;
+                outputDevice.appendChild(option);
+                options.add(value);
+            };
+
+            for (const line of devicesText.trim().split("\r\n")) {
+                const [host, name, isDefault] = csvToValue(line, defaultSeparator, defaultStrMarker);
+                if (host == null || name == null) { continue; }
+
+                addOption(host, name, `${name} (${host}${isDefault ? ", default" : ""})`);
+            }
+
+            const [host, name] = csvToValue(chosenText.trim(), defaultSeparator, defaultStrMarker);
+            if (name != null) {
+                // Keep a chosen device selectable while it is unavailable
+                addOption(host ?? "", name, `${name} (unavailable)`);
+                outputDevice.value = arrayToCsv([host ?? "", name]);
+            }
         })
         .catch(err => console.error("Fetch error:", err));
 
@@ -625,8 +1274,11 @@ async function main() {
                 row.className = "song-list-row";
                 row.id = "song-" + csvRow[0];
 
+                const [filename, , , , duration, title, artist, trimFrom, trimTo, cueFrom, cueTo] = csvRow;
+
                 const filenameCell = row.insertCell(0);
-                filenameCell.innerHTML = csvRow[0];
+                filenameCell.textContent = songLabel(filename, duration, title, artist);
+                filenameCell.title = filename;
                 filenameCell.className = "filename-field";
 
                 const enabledCell = row.insertCell(1);
@@ -636,6 +1288,20 @@ async function main() {
                     enabledCell.innerHTML = "✘";
                 }
                 enabledCell.className = "enabled-field";
+
+                const trimCell = row.insertCell(2);
+                if (typeof trimFrom === 'number' && typeof trimTo === 'number') {
+                    songTrims[filename] = [trimFrom, trimTo];
+                    trimCell.textContent = `${formatSeconds(trimFrom)}–${formatSeconds(trimTo)}`;
+                }
+                trimCell.className = "trim-field";
+
+                const cueCell = row.insertCell(3);
+                if (typeof cueFrom === 'number' && typeof cueTo === 'number') {
+                    songCues[filename] = [cueFrom, cueTo];
+                    cueCell.textContent = `${formatSeconds(cueFrom)}–${formatSeconds(cueTo)}`;
+                }
+                cueCell.className = "cue-field";
             }
         })
         .catch(err => console.error("Fetch error:", err));
@@ -666,6 +1332,18 @@ async function main() {
                 const timeCell = row.insertCell(1);
                 timeCell.innerHTML = csvRow[1];
                 timeCell.className = "time-field";
+
+                const loudnessCell = row.insertCell(2);
+                loudnessCell.innerHTML = csvRow[2] === null || csvRow[2] === undefined ? "Default" : csvRow[2] + " LUFS";
+                loudnessCell.className = "loudness-field";
+
+                const priorityCell = row.insertCell(3);
+                priorityCell.innerHTML = csvRow[3] ?? "normal";
+                priorityCell.className = "priority-field";
+
+                const zonesCell = row.insertCell(4);
+                zonesCell.innerHTML = csvRow.length > 4 ? csvRow.slice(4).join(", ") : "Default";
+                zonesCell.className = "zones-field";
             }
         })
         .catch(err => console.error("Fetch error:", err));
@@ -0,0 +1,282 @@
+use std::collections::HashSet;
+use std::ffi::OsStr;
+use std::path::Path;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread::JoinHandle;
+
+use crate::database::SongDatabase;
+use crate::fingerprint::{Fingerprint, Fingerprinter};
+use crate::loudness::{db_to_gain, tagged_loudness, LoudnessMeter};
+use crate::songs::{Song, Trim};
+use crate::tags::SongTags;
+use crate::{decoders, logln, or_return, Error};
+
+/// The sample peak level in dBFS, below which the start and the end of a song are trimmed as silence
+pub(crate) const SILENCE_THRESHOLD: f32 = -60.0;
+
+/// A step of analyzing a song, which is named when it fails
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnalysisStep {
+    /// Decoding the audio, which the length, the trim and the fingerprint are found from
+    Decoding,
+    ReadingTags,
+    MeasuringLoudness
+}
+
+impl std::fmt::Display for AnalysisStep {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            AnalysisStep::Decoding => "decode",
+            AnalysisStep::ReadingTags => "read the tags of",
+            AnalysisStep::MeasuringLoudness => "measure the loudness of"
+        })
+    }
+}
+
+/// The properties of an audio file found by decoding it
+#[derive(Debug, Clone)]
+pub struct Analysis {
+    /// The integrated loudness in LUFS
+    pub loudness: f32,
+    /// The length in seconds
+    pub duration: f64,
+    /// The title and the artist from the tags
+    pub tags: SongTags,
+    /// The part between the leading and the trailing silence
+    pub trim: Trim,
+    /// The acoustic fingerprint of the start
+    pub fingerprint: Fingerprint
+}
+
+impl Analysis {
+    /// Returns the known properties of a song, with empty tags in place of the ones read from its file
+    fn of_song(song: &Song) -> Option<Analysis> {
+        Some(Analysis {
+            loudness: song.loudness()?,
+            duration: song.duration()?,
+            tags: SongTags::default(),
+            trim: song.trim()?,
+            fingerprint: song.fingerprint()?.clone()
+        })
+    }
+}
+
+/// Returns the integrated loudness (in LUFS), the length, the tags, the part without leading
+/// and trailing silence, and the fingerprint of an audio file, or the step that failed.\
+/// If the file has ReplayGain or R128 tags, they are used instead of measuring its loudness.
+pub fn analyze_file(file_path: impl AsRef<Path>) -> Result<Analysis, (AnalysisStep, Error)> {
+    let mut decoder = decoders::open(file_path).map_err(|e| (AnalysisStep::Decoding, e))?;
+    let comments = decoder.comments();
+    let tagged = tagged_loudness(&comments);
+    let tags = SongTags::from_comments(&comments);
+
+    let mut meter = None;
+    let mut fingerprinter = None;
+    let mut duration = 0.0;
+    let threshold = db_to_gain(SILENCE_THRESHOLD);
+    let mut sound: Option<(f64, f64)> = None;
+
+    while let Some(frame) = decoder.next_frame() {
+        let frame = frame.map_err(|e| (AnalysisStep::Decoding, e))?;
+        let seconds_at = |index: usize| duration + (index / frame.channels as usize) as f64 / frame.sample_rate as f64;
+
+        let first = frame.samples.iter().position(|s| s.abs() > threshold);
+        let last = frame.samples.iter().rposition(|s| s.abs() > threshold);
+
+        if let (Some(first), Some(last)) = (first, last) {
+            let end = seconds_at(last + frame.channels as usize);
+            sound = Some((sound.map_or(seconds_at(first), |(start, _)| start), end));
+        }
+
+        duration += frame.samples.len() as f64 / (frame.channels as f64 * frame.sample_rate as f64);
+
+        fingerprinter
+            .get_or_insert_with(|| Fingerprinter::new(frame.sample_rate, frame.channels))
+            .feed(&frame.samples, frame.channels);
+
+        if tagged.is_some() { continue; }
+
+        meter
+            .get_or_insert_with(|| LoudnessMeter::new(frame.sample_rate, frame.channels))
+            .feed(&frame.samples);
+    }
+
+    // Without a single frame, there is nothing to analyze
+    let fingerprint = or_return!(fingerprinter, Err((AnalysisStep::Decoding, Error::CannotDecodeAudio))).finish();
+
+    let loudness = or_return!(
+        tagged.or_else(|| meter.and_then(|m| m.integrated())),
+        Err((AnalysisStep::MeasuringLoudness, Error::CannotMeasureLoudness))
+    );
+
+    // Silent files are played whole
+    let (start, end) = sound.unwrap_or((0.0, duration));
+    let trim = Trim { start, end: end.min(duration) };
+
+    Ok(Analysis { loudness, duration, tags, trim, fingerprint })
+}
+
+/// The result of analyzing a song, which is sent from the worker thread
+type AnalysisResult = (Box<OsStr>, Result<Analysis, (AnalysisStep, Error)>);
+
+/// An uploaded song, which is added to the database once it is analyzed,
+/// unless it sounds like a song already in it
+#[derive(Debug, Clone)]
+pub struct Upload {
+    filename: Box<OsStr>,
+    /// Whether the song is added even if it sounds like one in the database
+    allow_duplicates: bool,
+    /// The songs it sounds like if it was rejected for them, or `None` while it is analyzed
+    duplicates: Option<Vec<Box<OsStr>>>
+}
+
+impl Upload {
+    #[inline]
+    pub fn filename(&self) -> &OsStr {
+        &self.filename
+    }
+
+    #[inline]
+    pub fn duplicates(&self) -> Option<&[Box<OsStr>]> {
+        self.duplicates.as_deref()
+    }
+}
+
+/// Measures the loudness and the length, detects the silence, computes the fingerprint, and reads the tags
+/// of the songs without known ones in a background thread, so they do not need to be found on every play.\
+/// Uploaded songs are analyzed before they are added to the database, so they are never played untrimmed.
+pub struct SongAnalyzer {
+    worker: Option<JoinHandle<()>>,
+    sender: Sender<AnalysisResult>,
+    results: Receiver<AnalysisResult>,
+    /// The songs, which could not be analyzed, so they are not retried
+    failed: HashSet<Box<OsStr>>,
+    /// The uploaded songs being analyzed, and the ones rejected as duplicates
+    uploads: Vec<Upload>
+}
+
+impl SongAnalyzer {
+    pub fn new() -> SongAnalyzer {
+        let (sender, results) = channel();
+
+        SongAnalyzer { worker: None, sender, results, failed: HashSet::new(), uploads: Vec::new() }
+    }
+
+    /// Analyzes an uploaded song file in a thread of its own, so it is added to the database by `update`
+    /// once its analysis finishes, unless it sounds like a song in it and `allow_duplicates` is not set
+    pub fn add_upload(&mut self, file_path: &Path, allow_duplicates: bool) {
+        let filename: Box<OsStr> = or_return!(file_path.file_name()).into();
+        let file_path = file_path.to_path_buf();
+        let sender = self.sender.clone();
+
+        // An upload rejected before is replaced by its retry
+        self.uploads.retain(|u| u.filename != filename);
+        self.uploads.push(Upload { filename: filename.clone(), allow_duplicates, duplicates: None });
+
+        std::thread::spawn(move || {
+            let _ = sender.send((filename, analyze_file(file_path)));
+        });
+    }
+
+    /// Returns the uploaded songs, which are still being analyzed or were rejected as duplicates
+    pub fn uploads(&self) -> &[Upload] {
+        &self.uploads
+    }
+
+    /// Adds the analyzed uploads to a database unless they are duplicates, and stores the finished analyses in it,
+    /// then starts analyzing its songs without a known loudness, length, trim, fingerprint or tags
+    /// if no analysis is running. Returns whether the database was changed.
+    pub fn update(&mut self, database: &mut SongDatabase) -> bool {
+        // Every result is sent before the worker finishes, so none are left behind
+        let idle = self.worker.as_ref().is_none_or(|w| w.is_finished());
+        let mut changed = false;
+
+        while let Ok((filename, result)) = self.results.try_recv() {
+            // Uploads that cannot be analyzed are still added, and played whole
+            if let Some(index) = self.uploads.iter().position(|u| u.filename == filename && u.duplicates.is_none()) {
+                if let Ok(analysis) = &result && !self.uploads[index].allow_duplicates {
+                    let duplicates: Vec<Box<OsStr>> = database
+                        .duplicates_of(&analysis.fingerprint)
+                        .map(|s| s.filename().into())
+                        .collect();
+
+                    if !duplicates.is_empty() {
+                        let _ = std::fs::remove_file(database.root_dir().join(filename.as_ref()));
+                        self.uploads[index].duplicates = Some(duplicates);
+                        continue;
+                    }
+                }
+
+                self.uploads.swap_remove(index);
+
+                let file_path = database.root_dir().join(filename.as_ref());
+                if let Err((_, e)) = database.add_file(file_path).realize(database, false) {
+                    logln!("Cannot add \"{}\": {e}", filename.display());
+                    continue;
+                }
+
+                changed = true;
+            }
+
+            match result {
+                Ok(analysis) => {
+                    if let Some(song) = database.inner_mut().iter_mut().find(|s| s.filename() == filename.as_ref()) {
+                        song.set_loudness(Some(analysis.loudness));
+                        song.set_duration(Some(analysis.duration));
+                        song.set_tags(Some(analysis.tags));
+                        song.set_trim(song.trim().or(Some(analysis.trim)));
+                        song.set_fingerprint(Some(analysis.fingerprint));
+                        changed = true;
+                    }
+                },
+                Err((step, e)) => {
+                    logln!("Cannot {step} \"{}\": {e}", filename.display());
+                    self.failed.insert(filename);
+                }
+            }
+        }
+
+        if !idle { return changed; }
+
+        // The songs only missing their tags are not decoded again
+        let pending = database
+            .inner()
+            .iter()
+            .filter(|s| !self.failed.contains(s.filename()))
+            .filter_map(|s| match (Analysis::of_song(s), s.tags()) {
+                (Some(_), Some(_)) => None,
+                (known, _) => Some((s.filename().into(), known))
+            })
+            .collect::<Vec<(Box<OsStr>, _)>>();
+
+        if pending.is_empty() {
+            self.worker = None;
+            return changed;
+        }
+
+        let root_dir = database.root_dir().to_path_buf();
+        let sender = self.sender.clone();
+
+        self.worker = Some(std::thread::spawn(move || {
+            for (filename, known) in pending {
+                let file_path = root_dir.join(filename.as_ref());
+                let result = match known {
+                    Some(known) => decoders::open(file_path)
+                        .map(|d| Analysis { tags: SongTags::from_comments(&d.comments()), ..known })
+                        .map_err(|e| (AnalysisStep::ReadingTags, e)),
+                    None => analyze_file(file_path)
+                };
+
+                if sender.send((filename, result)).is_err() { break; }
+            }
+        }));
+
+        changed
+    }
+}
+
+impl Default for SongAnalyzer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
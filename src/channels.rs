@@ -0,0 +1,148 @@
+use std::f32::consts::FRAC_1_SQRT_2;
+
+/// The maximal number of fallbacks followed when a speaker is missing from the target layout
+const MAX_FOLD_DEPTH: u8 = 3;
+
+/// A speaker position of a channel layout
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Speaker {
+    FrontLeft,
+    FrontRight,
+    FrontCenter,
+    LowFrequency,
+    BackLeft,
+    BackRight,
+    BackCenter,
+    SideLeft,
+    SideRight,
+    /// A channel without a known position, identified by its index
+    Discrete(u16)
+}
+
+/// Returns the speaker positions of the channels in the WAVE (SMPTE) channel order,
+/// which the decoders provide their samples in.
+pub fn layout(channels: u16) -> Vec<Speaker> {
+    use Speaker::*;
+
+    let known: &[Speaker] = match channels {
+        1 => &[FrontCenter],
+        2 => &[FrontLeft, FrontRight],
+        3 => &[FrontLeft, FrontRight, FrontCenter],
+        4 => &[FrontLeft, FrontRight, BackLeft, BackRight],
+        5 => &[FrontLeft, FrontRight, FrontCenter, BackLeft, BackRight],
+        6 => &[FrontLeft, FrontRight, FrontCenter, LowFrequency, BackLeft, BackRight],
+        7 => &[FrontLeft, FrontRight, FrontCenter, LowFrequency, BackCenter, SideLeft, SideRight],
+        _ => &[FrontLeft, FrontRight, FrontCenter, LowFrequency, BackLeft, BackRight, SideLeft, SideRight]
+    };
+
+    known
+        .iter()
+        .copied()
+        .chain((known.len() as u16..channels).map(Discrete))
+        .take(channels as usize)
+        .collect()
+}
+
+/// Returns how a speaker's signal is distributed over a target layout,
+/// as pairs of target speakers and gains.
+fn fold(speaker: Speaker, target: &[Speaker], depth: u8) -> Vec<(Speaker, f32)> {
+    use Speaker::*;
+
+    if target.contains(&speaker) {
+        return vec![(speaker, 1.0)];
+    }
+
+    if depth == 0 {
+        return Vec::new();
+    }
+
+    let has = |s: Speaker| target.contains(&s);
+
+    let fallbacks: Vec<(Speaker, f32)> = match speaker {
+        FrontLeft | FrontRight => vec![(FrontCenter, FRAC_1_SQRT_2)],
+        FrontCenter => vec![(FrontLeft, FRAC_1_SQRT_2), (FrontRight, FRAC_1_SQRT_2)],
+        BackLeft if has(SideLeft) => vec![(SideLeft, 1.0)],
+        BackRight if has(SideRight) => vec![(SideRight, 1.0)],
+        SideLeft if has(BackLeft) => vec![(BackLeft, 1.0)],
+        SideRight if has(BackRight) => vec![(BackRight, 1.0)],
+        BackLeft | SideLeft => vec![(FrontLeft, FRAC_1_SQRT_2)],
+        BackRight | SideRight => vec![(FrontRight, FRAC_1_SQRT_2)],
+        BackCenter => vec![(BackLeft, FRAC_1_SQRT_2), (BackRight, FRAC_1_SQRT_2)],
+        // The low frequency effects are left out of downmixes, as most speakers cannot reproduce them
+        LowFrequency | Discrete(_) => Vec::new()
+    };
+
+    fallbacks
+        .into_iter()
+        .flat_map(|(s, gain)| {
+            fold(s, target, depth - 1).into_iter().map(move |(t, g)| (t, g * gain))
+        })
+        .collect()
+}
+
+/// Converts interleaved samples between channel layouts with a mixing matrix
+pub struct ChannelMixer {
+    source_channels: u16,
+    target_channels: u16,
+    /// The gains of the source channels for each target channel in row-major order
+    matrix: Box<[f32]>
+}
+
+impl ChannelMixer {
+    /// Creates a mixer from `source_channels` to `target_channels`
+    pub fn new(source_channels: u16, target_channels: u16) -> ChannelMixer {
+        let source = layout(source_channels);
+        let target = layout(target_channels);
+
+        let (s_len, t_len) = (source.len(), target.len());
+        let mut matrix = vec![0.0; s_len * t_len].into_boxed_slice();
+
+        for (s, &speaker) in source.iter().enumerate() {
+            for (to, gain) in fold(speaker, &target, MAX_FOLD_DEPTH) {
+                let t = target.iter().position(|&x| x == to).expect("Folded into a missing speaker");
+                matrix[t * s_len + s] += gain;
+            }
+        }
+
+        // Scale down the rows that would be able to clip
+        for row in matrix.chunks_exact_mut(s_len.max(1)) {
+            let sum: f32 = row.iter().sum();
+
+            if sum > 1.0 {
+                row.iter_mut().for_each(|g| *g /= sum);
+            }
+        }
+
+        ChannelMixer { source_channels, target_channels, matrix }
+    }
+
+    /// Returns the number of channels this mixer produces
+    pub fn target_channels(&self) -> u16 {
+        self.target_channels
+    }
+
+    /// Mixes interleaved samples with the given number of channels into the target layout.
+    /// The matrix is rebuilt if the number of source channels changes.
+    pub fn process(&mut self, samples: &[f32], source_channels: u16) -> Vec<f32> {
+        if source_channels != self.source_channels {
+            *self = Self::new(source_channels, self.target_channels);
+        }
+
+        if self.source_channels == self.target_channels {
+            return samples.to_vec();
+        }
+
+        let s_len = self.source_channels as usize;
+        let t_len = self.target_channels as usize;
+
+        let mut output = Vec::with_capacity(samples.len() / s_len.max(1) * t_len);
+
+        for frame in samples.chunks_exact(s_len.max(1)) {
+            for row in self.matrix.chunks_exact(s_len.max(1)) {
+                output.push(row.iter().zip(frame).map(|(g, s)| g * s).sum());
+            }
+        }
+
+        output
+    }
+}
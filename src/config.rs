@@ -1,14 +1,179 @@
 use std::path::Path;
+use std::time::Duration;
 use crate::Error;
 use crate::csv::CsvObject;
-use crate::{int_to_bool, or_return, return_unless};
-use crate::time::{Day, Time};
+use crate::ical;
+use crate::{logln, or_return, return_unless};
+use crate::time::{unix_time, Date, Day, Time};
+use crate::timezone::{TimeZone, MAX_UTC_OFFSET, MIN_UTC_OFFSET};
+use crate::loudness::DEFAULT_TARGET_LOUDNESS;
+use crate::playback::PlaybackSettings;
+use crate::sink::{DeviceSelection, Sink, SinkKind, Zone};
+
+/// The range of accepted target loudness values in LUFS
+pub const TARGET_LOUDNESS_RANGE: std::ops::RangeInclusive<f32> = -40.0..=-5.0;
+
+/// The longest accepted fade-in or fade-out time in seconds
+pub const MAX_FADE_SECS: f32 = 30.0;
+
+/// The default time songs fade in over at their start
+const DEFAULT_FADE_IN: Duration = Duration::from_millis(1000);
+
+/// The default time the playback fades out over when a break ends
+const DEFAULT_FADE_OUT: Duration = Duration::from_millis(3000);
+
+/// The default time consecutive songs overlap over
+const DEFAULT_CROSSFADE: Duration = Duration::ZERO;
+
+/// The gain music is lowered to while a ducking event plays over it (about -15 dB)
+const DUCKED_MUSIC_GAIN: f32 = 0.18;
+
+/// The stored value of a missing target loudness override
+const NO_LOUDNESS_OVERRIDE: i16 = i16::MIN;
+
+/// Seconds per day, which breaks spanning midnight wrap around
+const SECS_PER_DAY: u32 = 86400;
+
+/// The days of a week, each of which has its own schedule
+const DAYS_PER_WEEK: usize = 7;
+
+/// The version of the config file format written by this version of the program.\
+/// Files without a version record are of version 1.
+const CONFIG_VERSION: u8 = 5;
+
+/// The record following the header, which holds the version of the config file format
+const VERSION_RECORD: u8 = b'#';
+
+/// The number of breaks of the fixed timetables of version 1 config files
+const V1_BREAKS: usize = 8;
+
+/// The number of days of the fixed timetables of version 1 config files, which are Monday to Friday
+const V1_DAYS: usize = 5;
+
+/// The name of the timetable of config files before version 3, which held a single unnamed one
+const DEFAULT_TIMETABLE_NAME: &str = "Default";
+
+/// Encodes an optional target loudness in tenths of LUFS
+fn loudness_to_bytes(loudness: Option<f32>) -> [u8; 2] {
+    loudness
+        .map(|l| (l * 10.0).round() as i16)
+        .unwrap_or(NO_LOUDNESS_OVERRIDE)
+        .to_be_bytes()
+}
+
+/// Decodes an optional target loudness from tenths of LUFS
+fn loudness_from_bytes(bytes: [u8; 2]) -> Option<f32> {
+    match i16::from_be_bytes(bytes) {
+        NO_LOUDNESS_OVERRIDE => None,
+        tenths => Some(tenths as f32 / 10.0)
+    }
+}
+
+/// Appends a string prefixed with its length in a byte
+fn push_name(contents: &mut Vec<u8>, name: &str) {
+    contents.push(name.len() as u8);
+    contents.extend(name.as_bytes());
+}
+
+/// Reads a string prefixed with its length in a byte at `i`, and moves `i` past it
+fn read_name<'a>(bytes: &'a [u8], i: &mut usize) -> Result<&'a str, Error> {
+    let len = *bytes.get(*i).ok_or(Error::InvalidConfigFile)? as usize;
+    let name = or_return!(
+        bytes.get((*i + 1)..(*i + 1 + len)).and_then(|n| str::from_utf8(n).ok()),
+        Err(Error::InvalidConfigFile)
+    );
+
+    *i += 1 + len;
+
+    Ok(name)
+}
+
+/// Appends strings prefixed with their count in a byte
+fn push_names<S: AsRef<str>>(contents: &mut Vec<u8>, names: &[S]) {
+    contents.push(names.len() as u8);
+
+    for name in names {
+        push_name(contents, name.as_ref());
+    }
+}
+
+/// Reads strings prefixed with their count in a byte at `i`, and moves `i` past them
+fn read_names<'a>(bytes: &'a [u8], i: &mut usize) -> Result<Vec<&'a str>, Error> {
+    let count = *bytes.get(*i).ok_or(Error::InvalidConfigFile)?;
+    *i += 1;
+
+    (0..count).map(|_| read_name(bytes, i)).collect()
+}
+
+/// Appends an output device choice as its host (empty for the default one) and the names of its devices
+fn push_device_selection(contents: &mut Vec<u8>, device: &DeviceSelection) {
+    push_name(contents, device.host.as_deref().unwrap_or_default());
+    push_names(contents, &device.devices);
+}
+
+/// Reads an output device choice at `i`, and moves `i` past it
+fn read_device_selection(bytes: &[u8], i: &mut usize) -> Result<DeviceSelection, Error> {
+    let host = read_name(bytes, i)?;
+
+    Ok(DeviceSelection {
+        host: if host.is_empty() { None } else { Some(host.to_string()) },
+        devices: read_names(bytes, i)?.into_iter().map(str::to_string).collect()
+    })
+}
+
+/// Returns whether a list of names fits into the config file
+fn names_fit<S: AsRef<str>>(names: &[S]) -> bool {
+    names.len() <= u8::MAX as usize && names.iter().all(|n| n.as_ref().len() <= u8::MAX as usize)
+}
+
+/// Shortens a name to the longest prefix that fits into the config file
+fn truncate_name(name: &str) -> &str {
+    let mut end = name.len().min(u8::MAX as usize);
+    while !name.is_char_boundary(end) {
+        end -= 1;
+    }
+
+    &name[..end]
+}
+
+/// The stored value of a zone playing on every channel of its device
+const ALL_CHANNELS: u8 = u8::MAX;
 
 #[derive(Debug)]
 pub struct Configs {
-    timetable: Timetable,
+    /// The timetables, the first of which is used when none is named
+    timetables: Vec<Timetable>,
+    /// The names of the timetables used on each day of the week from Monday to Sunday
+    weekday_timetables: [Box<str>; DAYS_PER_WEEK],
+    /// The names of the timetables used on given dates instead of the ones of their weekdays, sorted by date
+    calendar: Vec<(Date, Box<str>)>,
+    /// The ranges of days without breaks, sorted by their first days
+    holidays: Vec<Holiday>,
     file_path: Box<Path>,
-    utc_offset: i8
+    /// The time zone the timetables, the calendar and the events follow
+    time_zone: TimeZone,
+    /// The loudness (in LUFS) songs and events are normalized to
+    target_loudness: f32,
+    /// The target loudness overrides of events by their names
+    event_target_loudness: Vec<(Box<str>, f32)>,
+    /// The time songs fade in over at their start
+    fade_in: Duration,
+    /// The time the playback fades out over when a break ends
+    fade_out: Duration,
+    /// The time consecutive songs overlap over
+    crossfade: Duration,
+    /// Whether consecutive songs follow each other without a pause
+    gapless: bool,
+    /// Where the audio is played unless `SINK_ENV_VAR` selects otherwise
+    sink: SinkKind,
+    /// The output device played on by device sinks
+    output_device: DeviceSelection,
+    /// The named outputs, which play independently of each other and of the default output
+    zones: Vec<Zone>,
+    /// The zones events play in by their names, if they do not play on the default output
+    event_zones: Vec<(Box<str>, Vec<Box<str>>)>,
+    /// The priorities of events by their names, if they are not `EventPriority::Normal`
+    event_priority: Vec<(Box<str>, EventPriority)>
 }
 
 #[allow(unreachable_code)]
@@ -24,23 +189,241 @@ impl Configs {
             return Err(Error::InvalidConfigFile);
         }
 
-        let mut timetable: Option<Timetable> = None;
-        let mut utc_offset: Option<i8> = None;
-
-        let mut i = 6;
+        let (version, start) = match bytes.get(6..=7) {
+            Some(&[VERSION_RECORD, version]) => (version, 8),
+            _ => (1, 6)
+        };
+        return_unless!(version != 0, Err(Error::InvalidConfigFile));
+        return_unless!(version <= CONFIG_VERSION, Err(Error::UnsupportedConfigVersion));
+
+        let mut timetables: Vec<Timetable> = Vec::new();
+        let mut weekday_timetables: Option<[Box<str>; DAYS_PER_WEEK]> = None;
+        let mut calendar: Vec<(Date, Box<str>)> = Vec::new();
+        let mut holidays = Vec::new();
+        let mut utc_offset: Option<i32> = None;
+        let mut time_zone_name = None;
+        let mut target_loudness = DEFAULT_TARGET_LOUDNESS;
+        let mut event_target_loudness = Vec::new();
+        let mut fade_in = DEFAULT_FADE_IN;
+        let mut fade_out = DEFAULT_FADE_OUT;
+        let mut crossfade = DEFAULT_CROSSFADE;
+        let mut gapless = true;
+        let mut sink = SinkKind::default();
+        let mut output_device = DeviceSelection::default();
+        let mut zones = Vec::new();
+        let mut event_zones = Vec::new();
+        let mut event_priority = Vec::new();
+
+        let mut i = start;
         '_search: while i < bytes.len() {
             match bytes.get(i) {
-                Some(b'T') => {
-                    timetable = Timetable::from_bytes(bytes
+                // The records of the settings of breaks apply to the timetable before them
+                Some(b'T') if version == 1 => {
+                    timetables.push(Timetable::from_v1_bytes(bytes
                         .get((i + 1)..=(i + 45))
                         .ok_or(Error::InvalidConfigFile)?
-                    );
+                    ).ok_or(Error::InvalidConfigFile)?);
                     i += 45;
                 },
+                Some(b'T') => {
+                    // Timetables are only named from version 3
+                    let mut j = i + 1;
+                    let name = match version {
+                        2 => DEFAULT_TIMETABLE_NAME,
+                        _ => read_name(bytes, &mut j)?
+                    };
+                    let breaks = *bytes.get(j).ok_or(Error::InvalidConfigFile)? as usize;
+                    let len = Timetable::encoded_len(breaks);
+
+                    let mut timetable = Timetable::from_bytes(bytes
+                        .get(j..(j + len))
+                        .ok_or(Error::InvalidConfigFile)?
+                    ).ok_or(Error::InvalidConfigFile)?;
+                    timetable.name = name.into();
+
+                    timetables.push(timetable);
+                    i = j + len - 1;
+                },
+                Some(b'W') => {
+                    let mut j = i + 1;
+                    let names = read_names(bytes, &mut j)?;
+
+                    weekday_timetables = Some(
+                        names.into_iter().map(Box::from).collect::<Vec<_>>().try_into().map_err(|_| Error::InvalidConfigFile)?
+                    );
+                    i = j - 1;
+                },
+                Some(b'A') => {
+                    let date = bytes
+                        .get((i + 1)..(i + 5))
+                        .and_then(|d| Date::from_bytes(d.try_into().ok()?))
+                        .ok_or(Error::InvalidConfigFile)?;
+                    let mut j = i + 5;
+                    let name = read_name(bytes, &mut j)?;
+
+                    calendar.push((date, name.into()));
+                    i = j - 1;
+                },
+                Some(b'H') => {
+                    let dates = bytes.get((i + 1)..(i + 9)).ok_or(Error::InvalidConfigFile)?;
+                    let [first, last] = [&dates[0..4], &dates[4..8]]
+                        .map(|d| Date::from_bytes(d.try_into().expect("The dates are 4 bytes long.")));
+                    let mut j = i + 9;
+                    let name = read_name(bytes, &mut j)?;
+
+                    holidays.push(
+                        Holiday::new(name, first.ok_or(Error::InvalidConfigFile)?, last.ok_or(Error::InvalidConfigFile)?)
+                            .ok_or(Error::InvalidConfigFile)?
+                    );
+                    i = j - 1;
+                },
                 Some(b'O') => {
-                    utc_offset = Some(*bytes.get(i + 1)
-                        .ok_or(Error::InvalidConfigFile)? as i8);
-                    i += 1;
+                    // Offsets are stored in whole hours before version 5, and in minutes from it
+                    match version {
+                        ..=4 => {
+                            utc_offset = Some(*bytes.get(i + 1).ok_or(Error::InvalidConfigFile)? as i8 as i32 * 3600);
+                            i += 1;
+                        },
+                        _ => {
+                            let minutes = bytes.get((i + 1)..=(i + 2)).ok_or(Error::InvalidConfigFile)?;
+                            utc_offset = Some(i16::from_be_bytes([minutes[0], minutes[1]]) as i32 * 60);
+                            i += 2;
+                        }
+                    }
+                },
+                Some(b'N') => {
+                    let mut j = i + 1;
+                    time_zone_name = Some(read_name(bytes, &mut j)?);
+                    i = j - 1;
+                },
+                Some(b'L') => {
+                    target_loudness = loudness_from_bytes([
+                        *bytes.get(i + 1).ok_or(Error::InvalidConfigFile)?,
+                        *bytes.get(i + 2).ok_or(Error::InvalidConfigFile)?
+                    ]).ok_or(Error::InvalidConfigFile)?;
+                    i += 2;
+                },
+                Some(b'B') => {
+                    // Version 1 files hold exactly 8 overrides, later ones are prefixed with their count
+                    let (count, first) = match version {
+                        1 => (V1_BREAKS, i + 1),
+                        _ => (*bytes.get(i + 1).ok_or(Error::InvalidConfigFile)? as usize, i + 2)
+                    };
+
+                    timetables.last_mut().ok_or(Error::InvalidConfigFile)?.break_target_loudness = bytes
+                        .get(first..(first + 2 * count))
+                        .ok_or(Error::InvalidConfigFile)?
+                        .chunks_exact(2)
+                        .map(|b| loudness_from_bytes([b[0], b[1]]))
+                        .collect();
+                    i = first + 2 * count - 1;
+                },
+                Some(b'E') => {
+                    let len = *bytes.get(i + 1).ok_or(Error::InvalidConfigFile)? as usize;
+                    let name = or_return!(
+                        bytes.get((i + 2)..(i + 2 + len)).and_then(|n| str::from_utf8(n).ok()),
+                        Err(Error::InvalidConfigFile)
+                    );
+                    let loudness = loudness_from_bytes([
+                        *bytes.get(i + 2 + len).ok_or(Error::InvalidConfigFile)?,
+                        *bytes.get(i + 3 + len).ok_or(Error::InvalidConfigFile)?
+                    ]).ok_or(Error::InvalidConfigFile)?;
+
+                    event_target_loudness.push((name.into(), loudness));
+                    i += 3 + len;
+                },
+                Some(b'F') => {
+                    let millis = bytes
+                        .get((i + 1)..=(i + 4))
+                        .ok_or(Error::InvalidConfigFile)?;
+
+                    fade_in = Duration::from_millis(u16::from_be_bytes([millis[0], millis[1]]) as u64);
+                    fade_out = Duration::from_millis(u16::from_be_bytes([millis[2], millis[3]]) as u64);
+                    i += 4;
+                },
+                Some(b'C') => {
+                    let join = bytes
+                        .get((i + 1)..=(i + 3))
+                        .ok_or(Error::InvalidConfigFile)?;
+
+                    crossfade = Duration::from_millis(u16::from_be_bytes([join[0], join[1]]) as u64);
+                    gapless = join[2] != 0;
+                    i += 3;
+                },
+                Some(b'K') => {
+                    let len = *bytes.get(i + 1).ok_or(Error::InvalidConfigFile)? as usize;
+                    let spec = or_return!(
+                        bytes.get((i + 2)..(i + 2 + len)).and_then(|s| str::from_utf8(s).ok()),
+                        Err(Error::InvalidConfigFile)
+                    );
+
+                    sink = SinkKind::from_spec(spec).ok_or(Error::InvalidConfigFile)?;
+                    i += 1 + len;
+                },
+                Some(b'D') => {
+                    let mut j = i + 1;
+                    output_device = read_device_selection(bytes, &mut j)?;
+                    i = j - 1;
+                },
+                Some(b'Z') => {
+                    let mut j = i + 1;
+                    let name = read_name(bytes, &mut j)?.into();
+                    let device = read_device_selection(bytes, &mut j)?;
+                    let channels = match *bytes.get(j..(j + 2)).ok_or(Error::InvalidConfigFile)? {
+                        [ALL_CHANNELS, ALL_CHANNELS] => None,
+                        [left, right] => Some([left as u16, right as u16]),
+                        _ => return Err(Error::InvalidConfigFile)
+                    };
+
+                    zones.push(Zone { name, device, channels });
+                    i = j + 1;
+                },
+                Some(b'R') => {
+                    let index = *bytes.get(i + 1).ok_or(Error::InvalidConfigFile)? as usize;
+                    let mut j = i + 2;
+                    let names = read_names(bytes, &mut j)?;
+
+                    let break_zones = &mut timetables.last_mut().ok_or(Error::InvalidConfigFile)?.break_zones;
+                    if break_zones.len() <= index {
+                        break_zones.resize(index + 1, Vec::new());
+                    }
+                    break_zones[index] = names.into_iter().map(Box::from).collect();
+                    i = j - 1;
+                },
+                Some(b'V') => {
+                    let mut j = i + 1;
+                    let name = read_name(bytes, &mut j)?.into();
+                    let names = read_names(bytes, &mut j)?;
+
+                    event_zones.push((name, names.into_iter().map(Box::from).collect()));
+                    i = j - 1;
+                },
+                Some(b'P') => {
+                    let mut j = i + 1;
+                    let name = read_name(bytes, &mut j)?.into();
+                    let priority = bytes
+                        .get(j)
+                        .and_then(|b| EventPriority::from_byte(*b))
+                        .ok_or(Error::InvalidConfigFile)?;
+
+                    event_priority.push((name, priority));
+                    i = j;
+                },
+                Some(b'S') => {
+                    // Version 1 files hold exactly 8 strategies, later ones are prefixed with their count
+                    let (count, first) = match version {
+                        1 => (V1_BREAKS, i + 1),
+                        _ => (*bytes.get(i + 1).ok_or(Error::InvalidConfigFile)? as usize, i + 2)
+                    };
+
+                    timetables.last_mut().ok_or(Error::InvalidConfigFile)?.break_strategy = bytes
+                        .get(first..(first + count))
+                        .ok_or(Error::InvalidConfigFile)?
+                        .iter()
+                        .map(|b| BreakStrategy::from_byte(*b))
+                        .collect::<Option<_>>()
+                        .ok_or(Error::InvalidConfigFile)?;
+                    i = first + count - 1;
                 },
                 Some(_) => return Err(Error::InvalidConfigFile),
                 None => return Err(Error::InvalidConfigFile),
@@ -49,60 +432,397 @@ impl Configs {
             i += 1;
         }
 
-        let timetable = timetable.ok_or(Error::NoTimetableFound)?;
+        return_unless!(!timetables.is_empty(), Err(Error::NoTimetableFound));
         let utc_offset = utc_offset.ok_or(Error::NoTimetableFound)?;
 
-        Ok(Configs { timetable, utc_offset, file_path: Box::from(file_path.as_ref()) })
+        // A zone that cannot be loaded keeps its name, so it is loaded again on the next start
+        let time_zone = match time_zone_name {
+            Some(name) => TimeZone::load(name).unwrap_or_else(|e| {
+                logln!("Cannot load the time zone {name} ({e}), using the UTC offset saved with it instead.");
+                TimeZone::unavailable(name, utc_offset)
+            }),
+            None => TimeZone::fixed(utc_offset)
+        };
+
+        // Every break has its settings, even if the file holds fewer of them than breaks
+        for timetable in &mut timetables {
+            timetable.resize_break_settings();
+        }
+
+        // Files before version 3 use their only timetable on every day
+        let weekday_timetables = weekday_timetables
+            .unwrap_or_else(|| std::array::from_fn(|_| timetables[0].name.clone()));
+        calendar.sort_by_key(|(date, _)| *date);
+        holidays.sort_by_key(|h: &Holiday| h.first);
+
+        Ok(Configs {
+            timetables,
+            weekday_timetables,
+            calendar,
+            holidays,
+            time_zone,
+            file_path: Box::from(file_path.as_ref()),
+            target_loudness,
+            event_target_loudness,
+            fade_in,
+            fade_out,
+            crossfade,
+            gapless,
+            sink,
+            output_device,
+            zones,
+            event_zones,
+            event_priority
+        })
     }
 
     pub fn save_to_file<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
-        let mut contents = b"MUSIQ\nT".to_vec();
+        let mut contents = b"MUSIQ\n".to_vec();
+
+        contents.push(VERSION_RECORD);
+        contents.push(CONFIG_VERSION);
+
+        for timetable in &self.timetables {
+            contents.push(b'T');
+            push_name(&mut contents, &timetable.name);
+            contents.append(&mut timetable.to_bytes());
+
+            contents.push(b'B');
+            contents.push(timetable.break_target_loudness.len() as u8);
+            for &loudness in &timetable.break_target_loudness {
+                contents.extend(loudness_to_bytes(loudness));
+            }
+
+            contents.push(b'S');
+            contents.push(timetable.break_strategy.len() as u8);
+            contents.extend(timetable.break_strategy.iter().map(|s| s.to_byte()));
+
+            for (index, zones) in timetable.break_zones.iter().enumerate().filter(|(_, z)| !z.is_empty()) {
+                contents.push(b'R');
+                contents.push(index as u8);
+                push_names(&mut contents, zones);
+            }
+        }
+
+        contents.push(b'W');
+        push_names(&mut contents, &self.weekday_timetables);
 
-        contents.append(&mut self.timetable.to_bytes());
+        for (date, name) in &self.calendar {
+            contents.push(b'A');
+            contents.extend(date.to_bytes());
+            push_name(&mut contents, name);
+        }
+
+        for holiday in &self.holidays {
+            contents.push(b'H');
+            contents.extend(holiday.first.to_bytes());
+            contents.extend(holiday.last.to_bytes());
+            push_name(&mut contents, &holiday.name);
+        }
 
+        // The current offset of a named zone is kept for when the zone cannot be loaded
         contents.push(b'O');
-        contents.push(self.utc_offset as u8);
+        contents.extend(((self.time_zone.offset_at(unix_time()) / 60) as i16).to_be_bytes());
+
+        if let Some(name) = self.time_zone.name() {
+            contents.push(b'N');
+            push_name(&mut contents, name);
+        }
+
+        contents.push(b'L');
+        contents.extend(loudness_to_bytes(Some(self.target_loudness)));
+
+        contents.push(b'F');
+        contents.extend((self.fade_in.as_millis() as u16).to_be_bytes());
+        contents.extend((self.fade_out.as_millis() as u16).to_be_bytes());
+
+        contents.push(b'C');
+        contents.extend((self.crossfade.as_millis() as u16).to_be_bytes());
+        contents.push(self.gapless as u8);
+
+        let sink = self.sink.to_spec();
+        contents.push(b'K');
+        contents.push(sink.len() as u8);
+        contents.extend(sink.as_bytes());
+
+        contents.push(b'D');
+        push_device_selection(&mut contents, &self.output_device);
+
+        for zone in &self.zones {
+            contents.push(b'Z');
+            push_name(&mut contents, &zone.name);
+            push_device_selection(&mut contents, &zone.device);
+            contents.extend(zone.channels.map_or([ALL_CHANNELS; 2], |pair| pair.map(|c| c as u8)));
+        }
+
+        for (name, zones) in &self.event_zones {
+            contents.push(b'V');
+            push_name(&mut contents, name);
+            push_names(&mut contents, zones);
+        }
+
+        for (name, priority) in &self.event_priority {
+            contents.push(b'P');
+            push_name(&mut contents, name);
+            contents.push(priority.to_byte());
+        }
+
+        for (name, loudness) in &self.event_target_loudness {
+            contents.push(b'E');
+            contents.push(name.len() as u8);
+            contents.extend(name.as_bytes());
+            contents.extend(loudness_to_bytes(Some(*loudness)));
+        }
 
         or_return!(std::fs::write(path, contents).ok(), Err(Error::CannotWriteFile));
 
         Ok(())
     }
 
-    pub fn timetable(&self) -> &Timetable {
-        &self.timetable
+    /// Returns the timetable with the given name, or with `None` the first one
+    pub fn timetable(&self, name: Option<&str>) -> Option<&Timetable> {
+        match name {
+            Some(name) => self.timetables.iter().find(|t| t.name.as_ref() == name),
+            None => self.timetables.first()
+        }
+    }
+
+    fn timetable_mut(&mut self, name: Option<&str>) -> Option<&mut Timetable> {
+        match name {
+            Some(name) => self.timetables.iter_mut().find(|t| t.name.as_ref() == name),
+            None => self.timetables.first_mut()
+        }
+    }
+
+    pub fn timetables(&self) -> &[Timetable] {
+        &self.timetables
     }
 
-    pub fn get_timetable_csv(&self) -> Vec<Vec<CsvObject>> {
-        let data = self.timetable.days.iter().map(|d| d.to_csv()).collect::<Vec<Vec<CsvObject>>>();
-        let mut result: Vec<Vec<CsvObject>> = Vec::new();
+    /// Returns the timetable used on a date, which is the one set for it in the calendar if there is one,
+    /// or else the one of its weekday
+    pub fn timetable_on(&self, date: Date) -> &Timetable {
+        let name = self.calendar_timetable(date).unwrap_or(&self.weekday_timetables[date.weekday().as_day_number() as usize]);
 
-        for i in 0..8usize {
-            let mut result_row = Vec::with_capacity(5);
-            for row in data.iter() {
-                result_row.push(row[i].clone());
+        // The names are kept up to date with the timetables, but the first one is a safe fallback
+        self.timetable(Some(name)).unwrap_or(&self.timetables[0])
+    }
+
+    /// Returns the name of the timetable set for a date in the calendar
+    pub fn calendar_timetable(&self, date: Date) -> Option<&str> {
+        self.calendar.iter().find(|(d, _)| *d == date).map(|(_, name)| name.as_ref())
+    }
+
+    /// Returns the name of each timetable
+    pub fn get_timetables_csv(&self) -> Vec<Vec<CsvObject>> {
+        self.timetables.iter().map(|t| vec![t.name.as_ref().into()]).collect()
+    }
+
+    /// Adds a timetable, which is a copy of the one named `copy_of`, or of the first one.
+    /// Fails if the name is empty, taken or too long, or the copied timetable does not exist.
+    pub fn add_timetable(&mut self, name: &str, copy_of: Option<&str>) -> Option<()> {
+        return_unless!(!name.is_empty() && names_fit(&[name]), None);
+        return_unless!(self.timetable(Some(name)).is_none(), None);
+
+        let timetable = Timetable { name: name.into(), ..self.timetable(copy_of)?.clone() };
+        self.timetables.push(timetable);
+
+        Some(())
+    }
+
+    /// Renames a timetable, and the uses of it on weekdays and in the calendar.
+    /// Fails if the timetable does not exist, or the new name is empty, taken or too long.
+    pub fn rename_timetable(&mut self, name: &str, new_name: &str) -> Option<()> {
+        return_unless!(!new_name.is_empty() && names_fit(&[new_name]), None);
+        return_unless!(self.timetable(Some(new_name)).is_none(), None);
+
+        self.timetable_mut(Some(name))?.name = new_name.into();
+
+        for used in self.weekday_timetables.iter_mut().chain(self.calendar.iter_mut().map(|(_, n)| n)) {
+            if used.as_ref() == name {
+                *used = new_name.into();
             }
-            result.push(result_row);
         }
 
-        result
+        Some(())
     }
 
-    pub fn get_breaks_csv(&self) -> Vec<Vec<CsvObject>> {
-        self.timetable.breaks.iter().map(|b| b.to_csv()).collect::<Vec<Vec<CsvObject>>>()
+    /// Removes a timetable and the dates it is used on from the calendar,
+    /// and uses the first remaining one on the weekdays it was used on.
+    /// Fails if the timetable does not exist or it is the last one.
+    pub fn remove_timetable(&mut self, name: &str) -> Option<()> {
+        return_unless!(self.timetables.len() > 1, None);
+
+        let index = self.timetables.iter().position(|t| t.name.as_ref() == name)?;
+        self.timetables.remove(index);
+
+        let fallback = &self.timetables[0].name;
+        for used in self.weekday_timetables.iter_mut().filter(|n| n.as_ref() == name) {
+            *used = fallback.clone();
+        }
+        self.calendar.retain(|(_, n)| n.as_ref() != name);
+
+        Some(())
     }
 
-    pub fn set_timetable_from_csv(&mut self, data: Vec<Vec<CsvObject>>) -> Option<()> {
-        let mut result: Vec<DailySchedule> = Vec::new();
+    /// Returns the names of the timetables used from Monday to Sunday in a row
+    pub fn get_weekday_timetables_csv(&self) -> Vec<Vec<CsvObject>> {
+        vec![self.weekday_timetables.iter().map(|n| CsvObject::from(n.as_ref())).collect()]
+    }
 
-        for i in 0..5 {
-            let mut tmp = Vec::new();
-            for row in data.iter() {
-                tmp.push(row.get(i)?);
-            }
-            result.push(DailySchedule::from_csv(tmp)?);
+    /// Sets the timetables used on each weekday from a row of their names from Monday to Sunday.
+    /// Fails unless every timetable exists.
+    pub fn set_weekday_timetables_from_csv(&mut self, data: Vec<Vec<CsvObject>>) -> Option<()> {
+        let [row]: [Vec<CsvObject>; 1] = data.try_into().ok()?;
+        let names: [&str; DAYS_PER_WEEK] = row.iter().map(|n| n.as_string()).collect::<Option<Vec<_>>>()?.try_into().ok()?;
+        return_unless!(names.iter().all(|&n| self.timetable(Some(n)).is_some()), None);
+
+        self.weekday_timetables = names.map(Box::from);
+
+        Some(())
+    }
+
+    /// Returns the dates with their own timetable in rows of the date (`YYYY-MM-DD`) and the name of the timetable
+    pub fn get_calendar_csv(&self) -> Vec<Vec<CsvObject>> {
+        self.calendar
+            .iter()
+            .map(|(date, name)| vec![date.display().into(), name.as_ref().into()])
+            .collect()
+    }
+
+    /// Replaces the calendar with rows of a date (`YYYY-MM-DD`) and the name of the timetable used on it.
+    /// Fails if a date is invalid or repeated, or a timetable does not exist.
+    pub fn set_calendar_from_csv(&mut self, data: Vec<Vec<CsvObject>>) -> Option<()> {
+        let mut calendar = Vec::with_capacity(data.len());
+
+        for row in data {
+            let date = Date::from_iso(row.first()?.as_string()?)?;
+            let name = row.get(1)?.as_string()?;
+            return_unless!(self.timetable(Some(name)).is_some(), None);
+
+            calendar.push((date, Box::from(name)));
         }
 
-        self.timetable.days = result.try_into().ok()?;
+        calendar.sort_by_key(|(date, _)| *date);
+        return_unless!(calendar.windows(2).all(|w| w[0].0 != w[1].0), None);
+        self.calendar = calendar;
+
+        Some(())
+    }
+
+    /// Sets the timetable used on a date, or with `None` makes it use the one of its weekday.
+    /// Fails if the timetable does not exist.
+    pub fn set_calendar_timetable(&mut self, date: Date, name: Option<&str>) -> Option<()> {
+        return_unless!(name.is_none_or(|n| self.timetable(Some(n)).is_some()), None);
+
+        self.calendar.retain(|(d, _)| *d != date);
+
+        if let Some(name) = name {
+            let index = self.calendar.partition_point(|(d, _)| *d < date);
+            self.calendar.insert(index, (date, name.into()));
+        }
+
+        Some(())
+    }
+
+    /// Returns the range of days off a date is in, on which no breaks are played
+    pub fn holiday_on(&self, date: Date) -> Option<&Holiday> {
+        self.holidays.iter().find(|h| h.contains(date))
+    }
+
+    /// Returns the ranges of days off in rows of their first and last dates (`YYYY-MM-DD`) and their names
+    pub fn get_holidays_csv(&self) -> Vec<Vec<CsvObject>> {
+        self.holidays
+            .iter()
+            .map(|h| vec![h.first.display().into(), h.last.display().into(), h.name.as_ref().into()])
+            .collect()
+    }
+
+    /// Replaces the days off with rows of a first date, a last date and a name,
+    /// the last two of which may be omitted for a single unnamed day.
+    /// Fails if a date is invalid, a range ends before it starts, or a name is too long.
+    pub fn set_holidays_from_csv(&mut self, data: Vec<Vec<CsvObject>>) -> Option<()> {
+        let mut holidays = data.iter().map(|row| Holiday::from_csv(row)).collect::<Option<Vec<_>>>()?;
+
+        holidays.sort_by_key(|h| h.first);
+        self.holidays = holidays;
+
+        Some(())
+    }
+
+    /// Adds a range of days off from a row like the ones of `set_holidays_from_csv`,
+    /// unless the same range with the same name is already there.
+    /// Fails if a date is invalid, the range ends before it starts, or the name is too long.
+    pub fn add_holiday_from_csv(&mut self, row: &[CsvObject]) -> Option<()> {
+        self.add_holiday(Holiday::from_csv(row)?);
+
+        Some(())
+    }
+
+    /// Adds the events of an iCalendar file as days off, and returns the number of the ones not already there
+    pub fn import_holidays_from_ics(&mut self, text: &str) -> usize {
+        ical::read_events(text, &self.time_zone)
+            .into_iter()
+            .filter_map(|e| Holiday::new(truncate_name(&e.summary), e.first, e.last))
+            .filter(|h| self.add_holiday(h.clone()))
+            .count()
+    }
+
+    /// Inserts a range of days off in order, and returns whether it was not already there
+    fn add_holiday(&mut self, holiday: Holiday) -> bool {
+        return_unless!(!self.holidays.contains(&holiday), false);
+
+        let index = self.holidays.partition_point(|h| h.first <= holiday.first);
+        self.holidays.insert(index, holiday);
+
+        true
+    }
+
+    /// Returns whether each break of a timetable is enabled on each day from Monday to Sunday, in a row per break
+    pub fn get_timetable_csv(&self, timetable: Option<&str>) -> Option<Vec<Vec<CsvObject>>> {
+        let timetable = self.timetable(timetable)?;
+
+        Some((0..timetable.breaks.len())
+            .map(|i| timetable.days.iter().map(|d| CsvObject::from(d.breaks[i])).collect())
+            .collect())
+    }
+
+    /// Returns the start, end, target loudness override, strategy and zones of each break of a timetable
+    pub fn get_breaks_csv(&self, timetable: Option<&str>) -> Option<Vec<Vec<CsvObject>>> {
+        let timetable = self.timetable(timetable)?;
+
+        Some(timetable.breaks
+            .iter()
+            .zip(&timetable.break_target_loudness)
+            .zip(&timetable.break_strategy)
+            .zip(&timetable.break_zones)
+            .map(|(((b, &loudness), strategy), zones)| {
+                let mut row = b.to_csv();
+                row.push(loudness.map_or(CsvObject::Null, CsvObject::from));
+                row.push(strategy.name().into());
+                row.extend(zones.iter().map(|zone| CsvObject::from(zone.as_ref())));
+                row
+            })
+            .collect::<Vec<Vec<CsvObject>>>())
+    }
+
+    /// Sets the days each break of a timetable is enabled on from a row per break, which holds the days
+    /// from Monday to Sunday, or only Monday to Friday to disable the break on weekends.
+    /// Fails unless there is a row for every break.
+    pub fn set_timetable_from_csv(&mut self, timetable: Option<&str>, data: Vec<Vec<CsvObject>>) -> Option<()> {
+        let timetable = self.timetable_mut(timetable)?;
+        return_unless!(data.len() == timetable.breaks.len(), None);
+        return_unless!(data.iter().all(|row| row.len() == V1_DAYS || row.len() == DAYS_PER_WEEK), None);
+
+        let mut days: [DailySchedule; DAYS_PER_WEEK] = Default::default();
+
+        for (i, day) in days.iter_mut().enumerate() {
+            day.breaks = data
+                .iter()
+                .map(|row| row.get(i).map_or(Some(false), CsvObject::as_bool))
+                .collect::<Option<_>>()?;
+        }
+
+        timetable.days = days;
 
         match self.save_to_file(self.file_path.as_ref()) {
             Ok(_) => (),
@@ -112,15 +832,47 @@ impl Configs {
         Some(())
     }
 
-    pub fn set_breaks_from_csv(&mut self, data: Vec<Vec<CsvObject>>) -> Option<()> {
-        let breaks: [Break; 8] = data
-            .into_iter()
-            .filter_map(|v| Break::from_csv(v))
-            .collect::<Vec<Break>>()
-            .try_into()
-            .ok()?;
+    /// Sets the breaks of a timetable from rows of their start, end and optionally their target loudness
+    /// override, strategy and the zones they play in. The breaks without a strategy keep their current one
+    /// and their zones.\
+    /// The breaks keep the days they are enabled on by their position, and added ones are disabled on every day.
+    pub fn set_breaks_from_csv(&mut self, timetable: Option<&str>, data: Vec<Vec<CsvObject>>) -> Option<()> {
+        let timetable = self.timetable_mut(timetable)?;
+        return_unless!(data.len() <= u8::MAX as usize, None);
+
+        let mut breaks = Vec::with_capacity(data.len());
+        let mut loudness = Vec::with_capacity(data.len());
+        let mut strategies = Vec::with_capacity(data.len());
+        let mut zones = Vec::with_capacity(data.len());
+
+        for (i, mut v) in data.into_iter().enumerate() {
+            let break_loudness = v.get(2).and_then(|l| l.as_float()).map(|l| l as f32);
+            return_unless!(break_loudness.is_none_or(|l| TARGET_LOUDNESS_RANGE.contains(&l)), None);
+
+            let (strategy, break_zones) = match v.get(3).and_then(|s| s.as_string()) {
+                Some(name) => (
+                    BreakStrategy::from_name(name)?,
+                    v.iter().skip(4).filter_map(|z| z.as_string()).map(Box::from).collect::<Vec<_>>()
+                ),
+                None => (timetable.break_strategy(i), timetable.break_zones(i).to_vec())
+            };
+            return_unless!(names_fit(&break_zones), None);
+
+            v.truncate(2);
+            breaks.push(Break::from_csv(v)?);
+            loudness.push(break_loudness);
+            strategies.push(strategy);
+            zones.push(break_zones);
+        }
+
+        for day in &mut timetable.days {
+            day.breaks.resize(breaks.len(), false);
+        }
 
-        self.timetable.breaks = breaks;
+        timetable.breaks = breaks;
+        timetable.break_target_loudness = loudness;
+        timetable.break_strategy = strategies;
+        timetable.break_zones = zones;
 
         match self.save_to_file(self.file_path.as_ref()) {
             Ok(_) => (),
@@ -130,24 +882,390 @@ impl Configs {
         Some(())
     }
 
-    pub fn utc_offset(&self) -> i8 {
-        self.utc_offset
+    /// Returns the time zone the timetables, the calendar and the events follow
+    pub fn time_zone(&self) -> &TimeZone {
+        &self.time_zone
+    }
+
+    /// Sets a fixed offset (in seconds) from UTC instead of a time zone.
+    /// Fails unless the offset is in whole minutes from -12:00 to +14:00.
+    pub fn set_utc_offset(&mut self, offset: i32) -> Option<()> {
+        return_unless!((MIN_UTC_OFFSET..=MAX_UTC_OFFSET).contains(&offset) && offset % 60 == 0, None);
+
+        self.time_zone = TimeZone::fixed(offset);
+
+        Some(())
+    }
+
+    /// Sets a time zone of the system's tz database, such as `Europe/Budapest`,
+    /// whose daylight saving time is followed. Fails if the zone cannot be loaded.
+    pub fn set_time_zone(&mut self, name: &str) -> Option<()> {
+        self.time_zone = TimeZone::load(name).ok()?;
+
+        Some(())
+    }
+
+    /// Returns the loudness (in LUFS) songs and events are normalized to
+    pub fn target_loudness(&self) -> f32 {
+        self.target_loudness
+    }
+
+    /// Sets the target loudness if it is in `TARGET_LOUDNESS_RANGE`
+    pub fn set_target_loudness(&mut self, loudness: f32) -> Option<()> {
+        return_unless!(TARGET_LOUDNESS_RANGE.contains(&loudness), None);
+
+        self.target_loudness = loudness;
+
+        Some(())
+    }
+
+    /// Returns the target loudness override of an event if it has one
+    pub fn event_target_loudness_override(&self, name: &str) -> Option<f32> {
+        self.event_target_loudness
+            .iter()
+            .find(|(n, _)| n.as_ref() == name)
+            .map(|(_, l)| *l)
+    }
+
+    /// Returns the target loudness of an event, which is its override if it has one
+    pub fn event_target_loudness(&self, name: &str) -> f32 {
+        self.event_target_loudness_override(name).unwrap_or(self.target_loudness)
+    }
+
+    /// Sets or with `None` removes the target loudness override of an event.
+    /// Fails if the loudness is not in `TARGET_LOUDNESS_RANGE` or the name is too long to be stored.
+    pub fn set_event_target_loudness(&mut self, name: &str, loudness: Option<f32>) -> Option<()> {
+        return_unless!(name.len() <= u8::MAX as usize, None);
+        return_unless!(loudness.is_none_or(|l| TARGET_LOUDNESS_RANGE.contains(&l)), None);
+
+        self.event_target_loudness.retain(|(n, _)| n.as_ref() != name);
+
+        if let Some(loudness) = loudness {
+            self.event_target_loudness.push((name.into(), loudness));
+        }
+
+        Some(())
+    }
+
+    /// Returns the time songs fade in over at their start
+    pub fn fade_in(&self) -> Duration {
+        self.fade_in
     }
 
-    pub unsafe fn set_utc_offset_unchecked(&mut self, utc_offset: i8) {
-        self.utc_offset = utc_offset;
+    /// Returns the time the playback fades out over when a break ends
+    pub fn fade_out(&self) -> Duration {
+        self.fade_out
+    }
+
+    /// Sets the fade-in and fade-out times (in seconds) if both are between 0 and `MAX_FADE_SECS`
+    pub fn set_fades(&mut self, fade_in: f32, fade_out: f32) -> Option<()> {
+        return_unless!((0.0..=MAX_FADE_SECS).contains(&fade_in), None);
+        return_unless!((0.0..=MAX_FADE_SECS).contains(&fade_out), None);
+
+        self.fade_in = Duration::from_secs_f32(fade_in);
+        self.fade_out = Duration::from_secs_f32(fade_out);
+
+        Some(())
+    }
+
+    /// Returns the time consecutive songs overlap over
+    pub fn crossfade(&self) -> Duration {
+        self.crossfade
+    }
+
+    /// Sets the time (in seconds) consecutive songs overlap over if it is between 0 and `MAX_FADE_SECS`
+    pub fn set_crossfade(&mut self, crossfade: f32) -> Option<()> {
+        return_unless!((0.0..=MAX_FADE_SECS).contains(&crossfade), None);
+
+        self.crossfade = Duration::from_secs_f32(crossfade);
+
+        Some(())
+    }
+
+    /// Returns whether consecutive songs follow each other without a pause
+    pub fn gapless(&self) -> bool {
+        self.gapless
+    }
+
+    pub fn set_gapless(&mut self, gapless: bool) {
+        self.gapless = gapless;
+    }
+
+    /// Returns the fade-in, fade-out and crossfade times in seconds, and whether songs are joined gaplessly
+    pub fn get_fades_csv(&self) -> Vec<Vec<CsvObject>> {
+        vec![vec![
+            CsvObject::from(self.fade_in.as_secs_f32()),
+            CsvObject::from(self.fade_out.as_secs_f32()),
+            CsvObject::from(self.crossfade.as_secs_f32()),
+            CsvObject::from(self.gapless)
+        ]]
+    }
+
+    /// Returns the sink set in the config file
+    pub fn sink(&self) -> &SinkKind {
+        &self.sink
+    }
+
+    /// Sets the sink if its description fits into the config file
+    pub fn set_sink(&mut self, sink: SinkKind) -> Option<()> {
+        return_unless!(sink.to_spec().len() <= u8::MAX as usize, None);
+
+        self.sink = sink;
+
+        Some(())
+    }
+
+    /// Returns the output device chosen in the config file
+    pub fn output_device(&self) -> &DeviceSelection {
+        &self.output_device
+    }
+
+    /// Chooses the output device if its names fit into the config file
+    pub fn set_output_device(&mut self, device: DeviceSelection) -> Option<()> {
+        return_unless!(device.host.as_deref().is_none_or(|host| host.len() <= u8::MAX as usize), None);
+        return_unless!(names_fit(&device.devices), None);
+
+        self.output_device = device;
+
+        Some(())
+    }
+
+    /// Returns the chosen output host and the devices in order of preference
+    pub fn get_output_device_csv(&self) -> Vec<Vec<CsvObject>> {
+        let mut row = vec![self.output_device.host.as_deref().map_or(CsvObject::Null, CsvObject::from)];
+
+        row.extend(self.output_device.devices.iter().map(|name| CsvObject::from(name.as_str())));
+
+        vec![row]
+    }
+
+    /// Returns the named output zones
+    pub fn zones(&self) -> &[Zone] {
+        &self.zones
+    }
+
+    /// Returns the name, output host, channel pair and devices in order of preference of each zone
+    pub fn get_zones_csv(&self) -> Vec<Vec<CsvObject>> {
+        self.zones
+            .iter()
+            .map(|zone| {
+                let channel = |i: usize| zone.channels.map_or(CsvObject::Null, |pair| CsvObject::from(pair[i] as i32));
+                let mut row = vec![
+                    zone.name.as_ref().into(),
+                    zone.device.host.as_deref().map_or(CsvObject::Null, CsvObject::from),
+                    channel(0),
+                    channel(1)
+                ];
+
+                row.extend(zone.device.devices.iter().map(|name| CsvObject::from(name.as_str())));
+                row
+            })
+            .collect()
+    }
+
+    /// Sets the zones from rows of their name, output host, channel pair and devices.
+    /// Fails if a name is empty or repeated, a channel is out of range or a name does not fit
+    /// into the config file.
+    pub fn set_zones_from_csv(&mut self, data: Vec<Vec<CsvObject>>) -> Option<()> {
+        let mut zones: Vec<Zone> = Vec::with_capacity(data.len());
+
+        for row in data {
+            let name = row.first()?.as_string()?;
+            return_unless!(!name.is_empty() && zones.iter().all(|z| z.name.as_ref() != name), None);
+
+            let channel = |c: f64| (0.0..ALL_CHANNELS as f64).contains(&c).then_some(c as u16);
+            let channels = match (row.get(2).and_then(|c| c.as_float()), row.get(3).and_then(|c| c.as_float())) {
+                (Some(left), Some(right)) => Some([channel(left)?, channel(right)?]),
+                (None, None) => None,
+                _ => return None
+            };
+
+            let zone = Zone {
+                name: name.into(),
+                device: DeviceSelection {
+                    host: row.get(1).and_then(|h| h.as_string()).filter(|h| !h.is_empty()).map(str::to_string),
+                    devices: row.iter().skip(4).filter_map(|d| d.as_string()).map(str::to_string).collect()
+                },
+                channels
+            };
+
+            return_unless!(names_fit(&[&zone.name]), None);
+            return_unless!(zone.device.host.as_deref().is_none_or(|host| host.len() <= u8::MAX as usize), None);
+            return_unless!(names_fit(&zone.device.devices), None);
+            zones.push(zone);
+        }
+
+        return_unless!(zones.len() <= u8::MAX as usize, None);
+        self.zones = zones;
+
+        Some(())
+    }
+
+    /// Returns the zones an event plays in, which are none if it plays on the default output
+    pub fn event_zones(&self, name: &str) -> &[Box<str>] {
+        self.event_zones
+            .iter()
+            .find(|(n, _)| n.as_ref() == name)
+            .map_or(&[], |(_, zones)| zones.as_slice())
+    }
+
+    /// Sets the zones an event plays in, or with none makes it play on the default output.
+    /// Fails if a name is too long to be stored.
+    pub fn set_event_zones(&mut self, name: &str, zones: Vec<Box<str>>) -> Option<()> {
+        return_unless!(name.len() <= u8::MAX as usize && names_fit(&zones), None);
+
+        self.event_zones.retain(|(n, _)| n.as_ref() != name);
+
+        if !zones.is_empty() {
+            self.event_zones.push((name.into(), zones));
+        }
+
+        Some(())
+    }
+
+    /// Returns the priority an event plays with
+    pub fn event_priority(&self, name: &str) -> EventPriority {
+        self.event_priority
+            .iter()
+            .find(|(n, _)| n.as_ref() == name)
+            .map_or(EventPriority::Normal, |(_, p)| *p)
+    }
+
+    /// Sets the priority of an event. Fails if the name is too long to be stored.
+    pub fn set_event_priority(&mut self, name: &str, priority: EventPriority) -> Option<()> {
+        return_unless!(name.len() <= u8::MAX as usize, None);
+
+        self.event_priority.retain(|(n, _)| n.as_ref() != name);
+
+        if priority != EventPriority::Normal {
+            self.event_priority.push((name.into(), priority));
+        }
+
+        Some(())
+    }
+
+    /// Opens the sink selected by `SINK_ENV_VAR`, or if it is not set, the one set in the config file
+    pub fn open_sink(&self) -> Result<Sink, Error> {
+        SinkKind::from_env()?.unwrap_or_else(|| self.sink.clone()).open(&self.output_device)
+    }
+
+    /// Opens the sink of a zone the way `open_sink` does, or with `None` the default output
+    pub fn open_zone_sink(&self, zone: Option<&str>) -> Result<Sink, Error> {
+        let Some(name) = zone else {
+            return self.open_sink();
+        };
+        let zone = self.zones.iter().find(|z| z.name.as_ref() == name).ok_or(Error::UnknownZone)?;
+
+        SinkKind::from_env()?.unwrap_or_else(|| self.sink.clone()).open_zone(zone)
+    }
+
+    /// Returns the settings songs are played with outside of breaks
+    pub fn playback_settings(&self) -> PlaybackSettings {
+        PlaybackSettings {
+            target_loudness: self.target_loudness,
+            fade_in: self.fade_in,
+            fade_out: self.fade_out,
+            gapless: self.gapless,
+            crossfade: self.crossfade
+        }
+    }
+
+    /// Returns the settings the songs of a break of a timetable are played with,
+    /// whose target loudness is the override of the break if it has one
+    pub fn break_playback_settings(&self, timetable: &Timetable, break_index: usize) -> PlaybackSettings {
+        PlaybackSettings {
+            target_loudness: timetable.break_target_loudness(break_index).unwrap_or(self.target_loudness),
+            ..self.playback_settings()
+        }
     }
 }
 
-#[derive(Debug)]
+/// A named set of breaks, the days of the week each of them is enabled on, and how their music is played
+#[derive(Debug, Clone)]
 pub struct Timetable {
-    days: [DailySchedule; 5],
-    breaks: [Break; 8],
+    name: Box<str>,
+    days: [DailySchedule; DAYS_PER_WEEK],
+    breaks: Vec<Break>,
+    /// The target loudness overrides of each break
+    break_target_loudness: Vec<Option<f32>>,
+    /// How the music of each break is chosen
+    break_strategy: Vec<BreakStrategy>,
+    /// The zones each break plays in, or none for the default output
+    break_zones: Vec<Vec<Box<str>>>,
 }
 
 impl Timetable {
+    /// Creates an unnamed timetable with the default settings for each break
+    fn new(days: [DailySchedule; DAYS_PER_WEEK], breaks: Vec<Break>) -> Timetable {
+        let mut timetable = Timetable {
+            name: DEFAULT_TIMETABLE_NAME.into(),
+            days,
+            breaks,
+            break_target_loudness: Vec::new(),
+            break_strategy: Vec::new(),
+            break_zones: Vec::new()
+        };
+        timetable.resize_break_settings();
+
+        timetable
+    }
+
+    /// Gives every break its settings, which are the default ones for the breaks without them
+    fn resize_break_settings(&mut self) {
+        let breaks = self.breaks.len();
+
+        self.break_target_loudness.resize(breaks, None);
+        self.break_strategy.resize(breaks, BreakStrategy::default());
+        self.break_zones.resize(breaks, Vec::new());
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the target loudness override of a break if it has one
+    pub fn break_target_loudness(&self, break_index: usize) -> Option<f32> {
+        self.break_target_loudness.get(break_index).copied().flatten()
+    }
+
+    /// Returns how the music of a break is chosen
+    pub fn break_strategy(&self, break_index: usize) -> BreakStrategy {
+        self.break_strategy.get(break_index).copied().unwrap_or_default()
+    }
+
+    /// Returns the zones a break plays in, which are none if it plays on the default output
+    pub fn break_zones(&self, break_index: usize) -> &[Box<str>] {
+        self.break_zones.get(break_index).map_or(&[], Vec::as_slice)
+    }
+
+    /// Returns the length of an encoded timetable with the given number of breaks
+    pub const fn encoded_len(breaks: usize) -> usize {
+        1 + breaks * 5 + DAYS_PER_WEEK * breaks.div_ceil(8)
+    }
+
+    /// Decodes a timetable from the number of its breaks, their times,
+    /// and the breaks enabled on each day from Monday to Sunday
     pub fn from_bytes(bytes: &[u8]) -> Option<Timetable> {
-        return_unless!(bytes.len() == 45, None);
+        let count = *bytes.first()? as usize;
+        return_unless!(bytes.len() == Self::encoded_len(count), None);
+
+        let (breaks, days) = bytes[1..].split_at(count * 5);
+
+        let breaks = breaks
+            .chunks_exact(5)
+            .map(|x| Break::from_bytes(x.try_into().expect("This should not fail")))
+            .collect::<Option<Vec<Break>>>()?;
+
+        let day_len = count.div_ceil(8);
+        let days = std::array::from_fn(|d| DailySchedule::from_bytes(&days[d * day_len..(d + 1) * day_len], count));
+
+        Some(Timetable::new(days, breaks))
+    }
+
+    /// Decodes the fixed timetable of version 1 config files, which has 8 breaks
+    /// and no breaks on weekends
+    pub fn from_v1_bytes(bytes: &[u8]) -> Option<Timetable> {
+        return_unless!(bytes.len() == V1_BREAKS * 5 + V1_DAYS, None);
 
         let breaks = bytes
             .get(0..40)?
@@ -155,84 +1273,251 @@ impl Timetable {
             .filter_map(|x| {
                 Break::from_bytes(x.try_into().expect("This should not fail"))
             })
-            .collect::<Vec<Break>>()
-            .try_into()
-            .ok()?;
+            .collect::<Vec<Break>>();
 
-        let days = bytes
-            .get(40..45)?
-            .iter()
-            .map(DailySchedule::from_byte)
-            .collect::<Vec<_>>()
-            .try_into()
-            .ok()?;
+        let mut days: [DailySchedule; DAYS_PER_WEEK] = Default::default();
 
-        Some(Timetable { days, breaks })
+        for (day, byte) in days.iter_mut().zip(bytes.get(40..45)?) {
+            *day = DailySchedule::from_bytes(&[*byte], V1_BREAKS);
+        }
+        for day in &mut days[V1_DAYS..] {
+            day.breaks = vec![false; V1_BREAKS];
+        }
+
+        Some(Timetable::new(days, breaks))
     }
 
     pub fn to_bytes(&self) -> Vec<u8> {
-        let mut result = Vec::new();
+        let mut result = vec![self.breaks.len() as u8];
 
         for b in &self.breaks {
             result.append(b.to_bytes().to_vec().as_mut());
         }
 
         for d in &self.days {
-            result.push(d.to_byte());
+            result.append(&mut d.to_bytes());
         }
 
         result
     }
 
     pub fn display(&self) -> String {
-        let conv = |d| {
-            format!("{}", d)
-                .chars()
-                .map(|c| match c {
-                    '0'..'9' => 'X',
-                    'X' => 'O',
-                    _ => unreachable!()
-                })
-                .collect::<Vec<char>>()
-        };
+        let mut buf: String = "                 | M T W T F S S\n".into();
 
-        let days = self.days.iter().map(conv).collect::<Vec<_>>();
-
-        let mut buf: String = "                 | M T W T F\n".into();
+        for (br, b) in self.breaks.iter().enumerate() {
+            // eg.: `1. (08:35-08:40) | X X O O X O O`
+            let days = self.days
+                .iter()
+                .map(|d| if d.breaks[br] { "X" } else { "O" })
+                .collect::<Vec<_>>()
+                .join(" ");
 
-        for br in 0..8 {
-            buf.push_str(
-                format!(
-                    "{}. ({}) | {} {} {} {} {}\n", // eg.: `1. (08:35-08:40) | X X O O X`
-                    br,
-                    self.breaks[br],
-                    days[0][br],
-                    days[1][br],
-                    days[2][br],
-                    days[3][br],
-                    days[4][br]
-                ).as_str()
-            );
+            buf.push_str(format!("{}. ({}) | {}\n", br, b, days).as_str());
         }
 
-        format!("{}", buf)
+        buf
     }
 
     /// Returns `Option<true>` when a break should start and `Option<false>` when it should end.
     pub fn action(&self, time: &Time, day: &Day) -> Option<bool> {
-        let break_enabled = self.days.get(day.as_day_number() as usize)?.to_bools();
+        self.break_action(time, day).map(|(_, action)| action)
+    }
 
-        for i in 0..8 {
+    /// Returns the index of the break, which starts (`true`) or ends (`false`) at the given time.
+    pub fn break_action(&self, time: &Time, day: &Day) -> Option<(usize, bool)> {
+        let break_enabled = &self.days.get(day.as_day_number() as usize)?.breaks;
+
+        for (i, b) in self.breaks.iter().enumerate() {
             if !break_enabled[i] { continue; }
 
-            if &self.breaks[i].start == time { return Some(true); }
-            if &self.breaks[i].end == time { return Some(false); }
+            if &b.start == time { return Some((i, true)); }
+            if &b.end == time { return Some((i, false)); }
         }
 
         None
     }
+
+    /// Returns the length of a break
+    pub fn break_duration(&self, break_index: usize) -> Option<Duration> {
+        let b = self.breaks.get(break_index)?;
+        let secs = (b.end.elapsed_seconds() + SECS_PER_DAY - b.start.elapsed_seconds()) % SECS_PER_DAY;
+
+        Some(Duration::from_secs(secs as u64))
+    }
+}
+
+/// A range of days without breaks, such as a school holiday
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Holiday {
+    name: Box<str>,
+    /// The first day off
+    first: Date,
+    /// The last day off, which is the first one for a single day
+    last: Date
+}
+
+impl Holiday {
+    /// Returns a range of days off, unless it ends before it starts or its name does not fit into the config file
+    pub fn new(name: &str, first: Date, last: Date) -> Option<Holiday> {
+        return_unless!(first <= last && names_fit(&[name]), None);
+
+        Some(Holiday { name: name.into(), first, last })
+    }
+
+    /// Parses a row of a first date, and an optional last date and name
+    fn from_csv(row: &[CsvObject]) -> Option<Holiday> {
+        let first = Date::from_iso(row.first()?.as_string()?)?;
+        let last = match row.get(1) {
+            Some(CsvObject::Null) | None => first,
+            Some(last) => Date::from_iso(last.as_string()?)?
+        };
+        let name = match row.get(2) {
+            Some(CsvObject::Null) | None => "",
+            Some(name) => name.as_string()?
+        };
+
+        Self::new(name, first, last)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub const fn first(&self) -> Date {
+        self.first
+    }
+
+    pub const fn last(&self) -> Date {
+        self.last
+    }
+
+    /// Returns whether a date is one of the days off
+    pub fn contains(&self, date: Date) -> bool {
+        (self.first..=self.last).contains(&date)
+    }
 }
 
+/// How the music of a break is chosen
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum BreakStrategy {
+    /// A single song, which fades out if the break ends before it
+    Single,
+    /// As many songs as fit into the break, so the music ends before the bell
+    #[default]
+    Fill,
+    /// Songs until the end of the break, where the last one fades out
+    FillWithFade
+}
+
+impl BreakStrategy {
+    const fn to_byte(self) -> u8 {
+        match self {
+            BreakStrategy::Single => 0,
+            BreakStrategy::Fill => 1,
+            BreakStrategy::FillWithFade => 2
+        }
+    }
+
+    const fn from_byte(byte: u8) -> Option<BreakStrategy> {
+        match byte {
+            0 => Some(BreakStrategy::Single),
+            1 => Some(BreakStrategy::Fill),
+            2 => Some(BreakStrategy::FillWithFade),
+            _ => None
+        }
+    }
+
+    /// Returns the name of the strategy used in CSV
+    pub const fn name(self) -> &'static str {
+        match self {
+            BreakStrategy::Single => "single",
+            BreakStrategy::Fill => "fill",
+            BreakStrategy::FillWithFade => "fill-with-fade"
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<BreakStrategy> {
+        match name {
+            "single" => Some(BreakStrategy::Single),
+            "fill" => Some(BreakStrategy::Fill),
+            "fill-with-fade" => Some(BreakStrategy::FillWithFade),
+            _ => None
+        }
+    }
+}
+
+/// How an event plays when other audio is playing
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum EventPriority {
+    /// Waits until the music has finished
+    #[default]
+    Normal,
+    /// Plays over the music of its zones, mixed into the same output
+    Overlay,
+    /// Plays over the music of its zones, which is lowered meanwhile
+    Duck,
+    /// Holds the music of its zones, which continues after the event
+    Interrupt,
+    /// Stops every playback in every zone, and plays right away
+    Emergency
+}
+
+impl EventPriority {
+    const fn to_byte(self) -> u8 {
+        match self {
+            EventPriority::Normal => 0,
+            EventPriority::Duck => 1,
+            EventPriority::Interrupt => 2,
+            EventPriority::Emergency => 3,
+            EventPriority::Overlay => 4
+        }
+    }
+
+    const fn from_byte(byte: u8) -> Option<EventPriority> {
+        match byte {
+            0 => Some(EventPriority::Normal),
+            1 => Some(EventPriority::Duck),
+            2 => Some(EventPriority::Interrupt),
+            3 => Some(EventPriority::Emergency),
+            4 => Some(EventPriority::Overlay),
+            _ => None
+        }
+    }
+
+    /// Returns the name of the priority used in CSV
+    pub const fn name(self) -> &'static str {
+        match self {
+            EventPriority::Normal => "normal",
+            EventPriority::Overlay => "overlay",
+            EventPriority::Duck => "duck",
+            EventPriority::Interrupt => "interrupt",
+            EventPriority::Emergency => "emergency"
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<EventPriority> {
+        match name {
+            "normal" => Some(EventPriority::Normal),
+            "overlay" => Some(EventPriority::Overlay),
+            "duck" => Some(EventPriority::Duck),
+            "interrupt" => Some(EventPriority::Interrupt),
+            "emergency" => Some(EventPriority::Emergency),
+            _ => None
+        }
+    }
+
+    /// Returns the gain the music playing in the zones of the event is lowered to while it plays,
+    /// or `None` if the music is not lowered
+    pub const fn music_gain(self) -> Option<f32> {
+        match self {
+            EventPriority::Duck => Some(DUCKED_MUSIC_GAIN),
+            EventPriority::Interrupt => Some(0.0),
+            EventPriority::Normal | EventPriority::Overlay | EventPriority::Emergency => None
+        }
+    }
+}
+
+#[derive(Clone)]
 struct Break {
     start: Time,
     end: Time
@@ -261,9 +1546,7 @@ impl Break {
     }
 
     fn to_csv(&self) -> Vec<CsvObject> {
-        let ((sh, sm, ss), (eh, em, es)) = self.to_hms_pair();
-
-        vec![format!("{sh:02}:{sm:02}:{ss:02}").into(), format!("{eh:02}:{em:02}:{es:02}").into()]
+        vec![self.start.display().into(), self.end.display().into()]
     }
 
     fn from_csv(csv: Vec<CsvObject>) -> Option<Break> {
@@ -271,8 +1554,7 @@ impl Break {
 
         match (start, end) {
             (CsvObject::String(start), CsvObject::String(end)) => {
-                let ((sh, sm, ss), (eh, em, es)) = (Self::time_from_str(start.as_ref())?, Self::time_from_str(end.as_ref())?);
-                Self::new(sh, sm, ss, eh, em, es)
+                Some(Break { start: Time::from_iso(&start)?, end: Time::from_iso(&end)? })
             },
             _ => None
         }
@@ -295,13 +1577,6 @@ impl Break {
 
         Some(Break { start: Time::from_seconds(start), end: Time::from_seconds(end) })
     }
-
-    fn time_from_str(string: &str) -> Option<(u8, u8, u8)> {
-        let (h, m_s) = string.split_once(':')?;
-        let (m, s) = m_s.split_once(':')?;
-
-        Some((h.parse().ok()?, m.parse().ok()?, s.parse().ok()?))
-    }
 }
 
 impl std::fmt::Debug for Break {
@@ -317,66 +1592,34 @@ impl std::fmt::Display for Break {
     }
 }
 
+/// The breaks enabled on a day
+#[derive(Default, Clone)]
 struct DailySchedule {
-    data: u8
+    breaks: Vec<bool>
 }
 
 impl DailySchedule {
-    const fn from_byte(byte: &u8) -> DailySchedule {
-        DailySchedule { data: *byte }
-    }
-
-    fn to_byte(&self) -> u8 {
-        self.data
-    }
-
-    fn to_bools(&self) -> [bool; 8] {
-        let mut res: [bool; 8] = [false; 8];
-        let mut data = *&self.data;
-
-        for i in (0..8).rev() {
-            *res.get_mut(i).unwrap() = int_to_bool!(data & 1).unwrap();
-            data >>= 1;
+    /// Decodes the enabled breaks from bits, the first break being the highest bit of the first byte
+    fn from_bytes(bytes: &[u8], breaks: usize) -> DailySchedule {
+        DailySchedule {
+            breaks: (0..breaks).map(|i| bytes[i / 8] & (0x80 >> (i % 8)) != 0).collect()
         }
-
-        res
     }
 
-    fn from_bools(bools: [bool; 8]) -> DailySchedule {
-        let mut result = bools[0] as u8;
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut result = vec![0; self.breaks.len().div_ceil(8)];
 
-        for i in 1..8 {
-            result <<= 1;
-            result |= bools[i] as u8;
+        for (i, &enabled) in self.breaks.iter().enumerate() {
+            if enabled { result[i / 8] |= 0x80 >> (i % 8); }
         }
 
-        DailySchedule { data: result }
-    }
-
-    fn to_csv(&self) -> Vec<CsvObject> {
-        self.to_bools().to_vec().iter().map(|b| CsvObject::from(*b)).collect::<Vec<CsvObject>>()
-    }
-
-    fn from_csv(csv: Vec<&CsvObject>) -> Option<DailySchedule> {
-        Some(Self::from_bools(
-            csv
-                .into_iter()
-                .filter_map(|v| v.as_bool())
-                .collect::<Vec<_>>()
-                .try_into()
-                .ok()?
-        ))
+        result
     }
 }
 
 impl std::fmt::Debug for DailySchedule {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let bools = self.to_bools();
-        let mut buf = String::with_capacity(8);
-
-        for (i, &v) in bools.iter().enumerate() {
-            buf.push(if v { (b'0' + i as u8) as char } else { 'X' });
-        }
+        let buf = self.breaks.iter().map(|&v| if v { 'X' } else { 'O' }).collect::<String>();
 
         write!(f, "{}", buf)
     }
@@ -388,10 +1631,13 @@ impl std::fmt::Display for DailySchedule {
     }
 }
 
-pub const fn default_config_bytes() -> [u8; 54] {
-    let mut result = [0; 54];
+pub const fn default_config_bytes() -> [u8; 68] {
+    let mut result = [0; 68];
 
-    let header = b"MUSIQ\nT";
+    let header = [
+        b'M', b'U', b'S', b'I', b'Q', b'\n', VERSION_RECORD, CONFIG_VERSION,
+        b'T', 7, b'D', b'e', b'f', b'a', b'u', b'l', b't', V1_BREAKS as u8
+    ];
 
     let breaks = [
         Break::new(07, 41, 00, 07, 50, 00).unwrap().to_bytes(),
@@ -406,33 +1652,35 @@ pub const fn default_config_bytes() -> [u8; 54] {
 
     let breaks = breaks.as_flattened(); // &[u8; 40]
 
-    let days = [0b01111100; 5];
+    // Monday to Friday, then no breaks on the weekend
+    let days = [0b01111100, 0b01111100, 0b01111100, 0b01111100, 0b01111100, 0, 0];
 
     let mut i = 0;
 
-    // 0..7
+    // 0..18
     while i < header.len() {
         result[i] = header[i];
         i += 1;
     }
 
-    // 7..47
+    // 18..58
     while i < header.len() + breaks.len() {
         result[i] = breaks[i-header.len()];
         i += 1;
     }
 
-    // 47..52
+    // 58..65
     while i < breaks.len() + header.len() + days.len() {
         result[i] = days[i-header.len()-breaks.len()];
         i += 1;
     }
 
-    // 52
-    result[52] = b'O';
+    // 65
+    result[65] = b'O';
 
-    // 53
-    result[53] = 0x02;
+    // 66..68, two hours in minutes
+    result[66] = 0x00;
+    result[67] = 0x78;
 
     result
-}
\ No newline at end of file
+}
@@ -105,6 +105,14 @@ impl CsvObject {
             _ => None
         }
     }
+
+    pub fn as_float(&self) -> Option<f64> {
+        match self {
+            CsvObject::Float(f) => Some(*f),
+            CsvObject::Int(i) => Some(*i as f64),
+            _ => None
+        }
+    }
 }
 
 /// Implements `From<T>` for `CsvObject` variant `String`.
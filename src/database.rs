@@ -2,7 +2,9 @@ use std::ffi::OsStr;
 use std::fs::read_dir;
 use std::path::Path;
 
-use crate::songs::Song;
+use crate::fingerprint::Fingerprint;
+use crate::songs::{Song, Trim};
+use crate::tags::SongTags;
 use crate::csv::CsvObject;
 use crate::{csv, or_continue, or_return, return_unless, DATABASE_FILE_NAME};
 use crate::Error;
@@ -91,15 +93,36 @@ impl SongDatabase {
     pub fn get_songs_csv(&self) -> Vec<Vec<CsvObject>> {
         let mut result = Vec::new();
 
-        let mut entries = self.songs.iter().map(|s| (s.filename(), s.enabled(), s.was_played())).collect::<Vec<_>>();
-        entries.sort_unstable_by(|(f0, ..), (f1, ..)| f0.cmp(f1));
+        let mut entries = self.songs.iter().collect::<Vec<_>>();
+        entries.sort_unstable_by(|s0, s1| s0.filename().cmp(s1.filename()));
 
-        for (filename, enabled, was_played) in entries {
-            let filename = or_continue!(filename.to_str()).into();
-            let enabled = enabled.into();
-            let was_played = was_played.into();
+        let part = |part: Option<Trim>| match part {
+            Some(part) => (part.start.into(), part.end.into()),
+            None => (CsvObject::Null, CsvObject::Null)
+        };
 
-            result.push(vec![filename, enabled, was_played]);
+        for song in entries {
+            let filename = or_continue!(song.filename().to_str()).into();
+            let enabled = song.enabled().into();
+            let was_played = song.was_played().into();
+            let loudness = song.loudness().map_or(CsvObject::Null, CsvObject::from);
+            let duration = song.duration().map_or(CsvObject::Null, CsvObject::from);
+
+            // Missing tags of read files are empty strings, so they are not read again
+            let tag = |value: Option<&str>| value.unwrap_or("").into();
+            let (title, artist) = match song.tags() {
+                Some(tags) => (tag(tags.title.as_deref()), tag(tags.artist.as_deref())),
+                None => (CsvObject::Null, CsvObject::Null)
+            };
+
+            let (trim_start, trim_end) = part(song.trim());
+            let (cue_start, cue_end) = part(song.cue());
+            let fingerprint = song.fingerprint().map_or(CsvObject::Null, |f| f.to_hex().into());
+
+            result.push(vec![
+                filename, enabled, was_played, loudness, duration, title, artist, trim_start, trim_end, cue_start, cue_end,
+                fingerprint
+            ]);
         }
 
         result
@@ -111,7 +134,32 @@ impl SongDatabase {
         let mut added: usize = 0;
 
         for entry in entries {
-            let [filename, enabled, was_played]: [CsvObject; 3] = or_return!(entry.try_into().ok(), Err(Error::InvalidCSV));
+            let mut entry = entry.into_iter();
+
+            let [filename, enabled, was_played]: [CsvObject; 3] = or_return!(
+                entry.by_ref().take(3).collect::<Vec<_>>().try_into().ok(),
+                Err(Error::InvalidCSV)
+            );
+
+            // The columns added in later versions are optional, so older files can still be read
+            let loudness = entry.next().and_then(|l| l.as_float()).map(|l| l as f32);
+            let duration = entry.next().and_then(|d| d.as_float());
+            let tags = match (entry.next(), entry.next()) {
+                (Some(CsvObject::Null) | None, Some(CsvObject::Null) | None) => None,
+                (title, artist) => {
+                    let tag = |value: Option<CsvObject>| value
+                        .and_then(|v| v.as_string().filter(|v| !v.is_empty()).map(Box::from));
+
+                    Some(SongTags { title: tag(title), artist: tag(artist) })
+                }
+            };
+            let mut part = || match (entry.next().and_then(|s| s.as_float()), entry.next().and_then(|e| e.as_float())) {
+                (Some(start), Some(end)) => Some(Trim { start, end }).filter(|t| t.is_valid(duration)),
+                _ => None
+            };
+            let trim = part();
+            let cue = part();
+            let fingerprint = entry.next().and_then(|f| f.as_string().and_then(Fingerprint::from_hex));
 
             let filename = Path::new( or_return!(
                 filename.as_string(),
@@ -131,6 +179,12 @@ impl SongDatabase {
 
             for s in self.songs.iter_mut() { // HashMap::replace
                 if *s == song {
+                    song.set_loudness(loudness.or(s.loudness()));
+                    song.set_duration(duration.or(s.duration()));
+                    song.set_tags(tags.or(s.tags().cloned()));
+                    song.set_trim(trim.or(s.trim()));
+                    song.set_cue(cue.or(s.cue()));
+                    song.set_fingerprint(fingerprint.or(s.fingerprint().cloned()));
                     *s = song;
                     break
                 }
@@ -142,6 +196,48 @@ impl SongDatabase {
         Ok(added)
     }
 
+    /// Returns the songs with a fingerprint close to the given one
+    pub fn duplicates_of<'a>(&'a self, fingerprint: &'a Fingerprint) -> impl Iterator<Item = &'a Song> {
+        self.songs
+            .iter()
+            .filter(|song| song.fingerprint().is_some_and(|f| f.is_duplicate_of(fingerprint)))
+    }
+
+    /// Returns the groups of songs that are likely the same recording, each sorted by filename.\
+    /// Songs are grouped transitively, so a group may also hold songs that are only close to another one in it.
+    pub fn duplicate_clusters(&self) -> Vec<Vec<&Song>> {
+        let mut songs = self.songs.iter().filter(|s| s.fingerprint().is_some()).collect::<Vec<_>>();
+        songs.sort_unstable_by(|s0, s1| s0.filename().cmp(s1.filename()));
+
+        // The first song of the group of each song, found by following the links to it
+        let mut roots = (0..songs.len()).collect::<Vec<_>>();
+        fn root(roots: &mut [usize], mut i: usize) -> usize {
+            while roots[i] != i {
+                roots[i] = roots[roots[i]];
+                i = roots[i];
+            }
+            i
+        }
+
+        for i in 0..songs.len() {
+            for j in i + 1..songs.len() {
+                let (a, b) = (songs[i].fingerprint(), songs[j].fingerprint());
+                if a.zip(b).is_some_and(|(a, b)| a.is_duplicate_of(b)) {
+                    let (ri, rj) = (root(&mut roots, i), root(&mut roots, j));
+                    roots[ri.max(rj)] = ri.min(rj);
+                }
+            }
+        }
+
+        let mut clusters: Vec<Vec<&Song>> = vec![Vec::new(); songs.len()];
+        for (i, song) in songs.into_iter().enumerate() {
+            clusters[root(&mut roots, i)].push(song);
+        }
+        clusters.retain(|c| c.len() > 1);
+
+        clusters
+    }
+
     /// Resets all songs' played state to 'not played'
     pub fn reset_played(&mut self) {
         self
@@ -231,7 +327,7 @@ impl DatabaseTransaction {
                         Song::new(new_path.as_ref()),
                         Err((self, EntryCreationFailed))
                     )),*/
-                    database.songs.iter().find(|&x| *x == new_song).is_some(),
+                    !database.songs.contains(&new_song),
                     Err((self, EntryAlreadyExists))
                 );
 
@@ -0,0 +1,448 @@
+extern crate minimp3;
+extern crate hound;
+extern crate claxon;
+extern crate lewton;
+#[cfg(feature = "opus")]
+extern crate ogg;
+#[cfg(feature = "opus")]
+extern crate audiopus;
+
+use std::fs::File;
+use std::io::{BufReader, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use crate::{or_return, tags, Error};
+#[cfg(feature = "opus")]
+use crate::return_unless;
+
+/// The number of bytes read from the start of a file to detect its format
+const MAGIC_BYTES_LEN: usize = 64;
+
+/// The number of samples per channel requested from decoders that do not have a natural frame size
+const SAMPLES_PER_FRAME: usize = 4096;
+
+/// A chunk of decoded audio
+#[derive(Debug, Clone)]
+pub struct Frame {
+    /// The interleaved samples of this frame in range `[-1, 1]` in the WAVE (SMPTE) channel order
+    pub samples: Vec<f32>,
+    /// The sample rate of this frame
+    pub sample_rate: u32,
+    /// The number of interleaved channels in `samples`
+    pub channels: u16
+}
+
+/// A decoder of an audio file, which yields its contents frame by frame
+pub trait AudioDecoder: Send {
+    /// Returns the next decoded frame, an error if it could not be decoded,
+    /// or `None` if the end of the stream is reached.
+    fn next_frame(&mut self) -> Option<Result<Frame, Error>>;
+
+    /// Returns the total duration of the stream in seconds if it is known without decoding it
+    fn duration(&self) -> Option<f64> {
+        None
+    }
+
+    /// Returns the metadata tags of the stream as pairs of uppercase keys and values
+    fn comments(&self) -> Vec<(String, String)> {
+        Vec::new()
+    }
+}
+
+/// A registered audio format, which can be decoded
+pub struct Backend {
+    /// The display name of the format
+    pub name: &'static str,
+    /// The lowercase file extensions associated with this format
+    pub extensions: &'static [&'static str],
+    /// Checks whether the first bytes of a file belong to this format
+    matches: fn(&[u8]) -> bool,
+    /// Creates a decoder reading from the start of a file
+    open: fn(File) -> Result<Box<dyn AudioDecoder>, Error>
+}
+
+/// The registered decoder backends in the order of their precedence
+pub static BACKENDS: &[Backend] = &[
+    Backend { name: "MP3", extensions: &["mp3"], matches: is_mp3, open: Mp3Decoder::open },
+    Backend { name: "WAV", extensions: &["wav", "wave"], matches: is_wav, open: WavDecoder::open },
+    Backend { name: "FLAC", extensions: &["flac"], matches: is_flac, open: FlacDecoder::open },
+    Backend { name: "Ogg Vorbis", extensions: &["ogg", "oga"], matches: is_vorbis, open: VorbisDecoder::open },
+    #[cfg(feature = "opus")]
+    Backend { name: "Opus", extensions: &["opus"], matches: is_opus, open: OpusDecoder::open },
+];
+
+/// Returns whether a file extension (without the dot) belongs to a registered backend
+pub fn is_supported_extension(extension: &str) -> bool {
+    let extension = extension.to_lowercase();
+
+    BACKENDS.iter().any(|b| b.extensions.contains(&extension.as_str()))
+}
+
+/// Returns the file extensions of every registered backend
+pub fn supported_extensions() -> impl Iterator<Item = &'static str> {
+    BACKENDS.iter().flat_map(|b| b.extensions.iter().copied())
+}
+
+/// Returns the backend whose format the first bytes of a file belong to
+pub fn detect(header: &[u8]) -> Option<&'static Backend> {
+    BACKENDS.iter().find(|b| (b.matches)(header))
+}
+
+/// Opens an audio file with the backend matching its contents,
+/// or if none match, the one matching its extension.
+pub fn open(file_path: impl AsRef<Path>) -> Result<Box<dyn AudioDecoder>, Error> {
+    let file_path = file_path.as_ref();
+    let mut file = or_return!(File::open(file_path).ok(), Err(Error::CannotReadFile));
+
+    let mut header = Vec::with_capacity(MAGIC_BYTES_LEN);
+    or_return!(
+        file.by_ref().take(MAGIC_BYTES_LEN as u64).read_to_end(&mut header).ok(),
+        Err(Error::CannotReadFile)
+    );
+    or_return!(file.seek(SeekFrom::Start(0)).ok(), Err(Error::CannotReadFile));
+
+    let extension = file_path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_lowercase());
+
+    let backend = or_return!(
+        detect(&header).or_else(|| {
+            let extension = extension.as_deref()?;
+            BACKENDS.iter().find(|b| b.extensions.contains(&extension))
+        }),
+        Err(Error::UnsupportedAudioFormat)
+    );
+
+    (backend.open)(file)
+}
+
+fn is_mp3(header: &[u8]) -> bool {
+    header.starts_with(b"ID3") ||
+        matches!(header, [0xFF, b, ..] if b & 0xE0 == 0xE0)
+}
+
+fn is_wav(header: &[u8]) -> bool {
+    header.starts_with(b"RIFF") && header.get(8..12) == Some(b"WAVE")
+}
+
+fn is_flac(header: &[u8]) -> bool {
+    header.starts_with(b"fLaC")
+}
+
+/// The offset of the first packet in an Ogg stream with a single-segment first page
+const OGG_FIRST_PACKET_OFFSET: usize = 28;
+
+fn is_vorbis(header: &[u8]) -> bool {
+    header.starts_with(b"OggS") &&
+        header.get(OGG_FIRST_PACKET_OFFSET..OGG_FIRST_PACKET_OFFSET + 7) == Some(b"\x01vorbis")
+}
+
+#[cfg(feature = "opus")]
+fn is_opus(header: &[u8]) -> bool {
+    header.starts_with(b"OggS") &&
+        header.get(OGG_FIRST_PACKET_OFFSET..OGG_FIRST_PACKET_OFFSET + 8) == Some(b"OpusHead")
+}
+
+/// Decodes MP3 files using the `minimp3` crate
+struct Mp3Decoder {
+    decoder: minimp3::Decoder<BufReader<File>>,
+    comments: Vec<(String, String)>
+}
+
+impl Mp3Decoder {
+    fn open(mut file: File) -> Result<Box<dyn AudioDecoder>, Error> {
+        // The ID3v2 frames come first, so they are preferred over the ID3v1 tag
+        let mut comments = tags::read_id3v2(BufReader::new(&mut file));
+        comments.extend(tags::read_id3v1(&mut file));
+        or_return!(file.seek(SeekFrom::Start(0)).ok(), Err(Error::CannotReadFile));
+
+        Ok(Box::new(Self { decoder: minimp3::Decoder::new(BufReader::new(file)), comments }))
+    }
+}
+
+impl AudioDecoder for Mp3Decoder {
+    fn next_frame(&mut self) -> Option<Result<Frame, Error>> {
+        loop {
+            return match self.decoder.next_frame() {
+                Ok(minimp3::Frame { data, sample_rate, channels, .. }) => Some(Ok(Frame {
+                    samples: data.into_iter().map(|s| s as f32 / i16::MAX as f32).collect(),
+                    sample_rate: sample_rate as u32,
+                    channels: channels as u16
+                })),
+                Err(minimp3::Error::SkippedData) => continue,
+                Err(minimp3::Error::Eof) => None,
+                Err(_) => Some(Err(Error::CannotDecodeAudio))
+            }
+        }
+    }
+
+    fn comments(&self) -> Vec<(String, String)> {
+        self.comments.clone()
+    }
+}
+
+/// Decodes uncompressed WAV files using the `hound` crate
+struct WavDecoder {
+    reader: hound::WavReader<BufReader<File>>
+}
+
+impl WavDecoder {
+    fn open(file: File) -> Result<Box<dyn AudioDecoder>, Error> {
+        Ok(Box::new(Self {
+            reader: or_return!(
+                hound::WavReader::new(BufReader::new(file)).ok(),
+                Err(Error::CannotDecodeAudio)
+            )
+        }))
+    }
+}
+
+impl AudioDecoder for WavDecoder {
+    fn next_frame(&mut self) -> Option<Result<Frame, Error>> {
+        let spec = self.reader.spec();
+        let len = SAMPLES_PER_FRAME * spec.channels as usize;
+
+        let samples = match spec.sample_format {
+            hound::SampleFormat::Float => self.reader
+                .samples::<f32>()
+                .take(len)
+                .collect::<Result<Vec<f32>, _>>(),
+            hound::SampleFormat::Int => {
+                let scale = 1.0 / (1u64 << (spec.bits_per_sample - 1)) as f32;
+
+                self.reader
+                    .samples::<i32>()
+                    .take(len)
+                    .map(|s| s.map(|s| s as f32 * scale))
+                    .collect::<Result<Vec<f32>, _>>()
+            }
+        };
+
+        match samples {
+            Ok(samples) if samples.is_empty() => None,
+            Ok(samples) => Some(Ok(Frame { samples, sample_rate: spec.sample_rate, channels: spec.channels })),
+            Err(_) => Some(Err(Error::CannotDecodeAudio))
+        }
+    }
+
+    fn duration(&self) -> Option<f64> {
+        Some(self.reader.duration() as f64 / self.reader.spec().sample_rate as f64)
+    }
+}
+
+/// Decodes FLAC files using the `claxon` crate
+struct FlacDecoder {
+    reader: claxon::FlacReader<BufReader<File>>,
+    buffer: Vec<i32>
+}
+
+impl FlacDecoder {
+    fn open(file: File) -> Result<Box<dyn AudioDecoder>, Error> {
+        Ok(Box::new(Self {
+            reader: or_return!(
+                claxon::FlacReader::new(BufReader::new(file)).ok(),
+                Err(Error::CannotDecodeAudio)
+            ),
+            buffer: Vec::new()
+        }))
+    }
+}
+
+impl AudioDecoder for FlacDecoder {
+    fn next_frame(&mut self) -> Option<Result<Frame, Error>> {
+        let info = self.reader.streaminfo();
+        let scale = 1.0 / (1u64 << (info.bits_per_sample - 1)) as f32;
+
+        let buffer = std::mem::take(&mut self.buffer);
+
+        let block = match self.reader.blocks().read_next_or_eof(buffer) {
+            Ok(Some(block)) => block,
+            Ok(None) => return None,
+            Err(_) => return Some(Err(Error::CannotDecodeAudio))
+        };
+
+        let mut samples = Vec::with_capacity(block.len() as usize);
+
+        for i in 0..block.duration() {
+            for ch in 0..block.channels() {
+                samples.push(block.sample(ch, i) as f32 * scale);
+            }
+        }
+
+        self.buffer = block.into_buffer();
+
+        Some(Ok(Frame { samples, sample_rate: info.sample_rate, channels: info.channels as u16 }))
+    }
+
+    fn duration(&self) -> Option<f64> {
+        let info = self.reader.streaminfo();
+
+        info.samples.map(|s| s as f64 / info.sample_rate as f64)
+    }
+
+    fn comments(&self) -> Vec<(String, String)> {
+        self.reader.tags().map(|(k, v)| (k.to_uppercase(), v.to_string())).collect()
+    }
+}
+
+/// Decodes Ogg Vorbis files using the `lewton` crate
+struct VorbisDecoder {
+    reader: lewton::inside_ogg::OggStreamReader<BufReader<File>>
+}
+
+impl VorbisDecoder {
+    fn open(file: File) -> Result<Box<dyn AudioDecoder>, Error> {
+        Ok(Box::new(Self {
+            reader: or_return!(
+                lewton::inside_ogg::OggStreamReader::new(BufReader::new(file)).ok(),
+                Err(Error::CannotDecodeAudio)
+            )
+        }))
+    }
+}
+
+impl VorbisDecoder {
+    /// Returns the Vorbis channel index of each channel in the WAVE channel order
+    fn channel_order(channels: u16) -> Option<&'static [usize]> {
+        match channels {
+            3 => Some(&[0, 2, 1]),
+            5 => Some(&[0, 2, 1, 3, 4]),
+            6 => Some(&[0, 2, 1, 5, 3, 4]),
+            7 => Some(&[0, 2, 1, 6, 5, 3, 4]),
+            8 => Some(&[0, 2, 1, 7, 5, 6, 3, 4]),
+            _ => None
+        }
+    }
+}
+
+impl AudioDecoder for VorbisDecoder {
+    fn next_frame(&mut self) -> Option<Result<Frame, Error>> {
+        let sample_rate = self.reader.ident_hdr.audio_sample_rate;
+        let channels = self.reader.ident_hdr.audio_channels as u16;
+
+        let reorder = |samples: Vec<f32>| match Self::channel_order(channels) {
+            Some(order) => samples
+                .chunks_exact(channels as usize)
+                .flat_map(|frame| order.iter().map(|&i| frame[i]))
+                .collect(),
+            None => samples
+        };
+
+        loop {
+            return match self.reader.read_dec_packet_generic::<lewton::samples::InterleavedSamples<f32>>() {
+                Ok(Some(packet)) if packet.samples.is_empty() => continue,
+                Ok(Some(packet)) => Some(Ok(Frame { samples: reorder(packet.samples), sample_rate, channels })),
+                Ok(None) => None,
+                Err(_) => Some(Err(Error::CannotDecodeAudio))
+            }
+        }
+    }
+
+    fn comments(&self) -> Vec<(String, String)> {
+        self.reader.comment_hdr.comment_list
+            .iter()
+            .map(|(k, v)| (k.to_uppercase(), v.clone()))
+            .collect()
+    }
+}
+
+/// Decodes Ogg Opus files using the `ogg` and `audiopus` crates
+#[cfg(feature = "opus")]
+struct OpusDecoder {
+    reader: ogg::PacketReader<BufReader<File>>,
+    decoder: audiopus::coder::Decoder,
+    channels: u16,
+    /// The number of samples per channel still to be discarded from the start of the stream
+    pre_skip: usize,
+    buffer: Vec<f32>,
+    comments: Vec<(String, String)>
+}
+
+#[cfg(feature = "opus")]
+impl OpusDecoder {
+    /// Opus always decodes to 48 kHz, regardless of the original sample rate
+    const SAMPLE_RATE: u32 = 48000;
+    /// The maximal duration of an Opus packet (120 ms) in samples per channel
+    const MAX_PACKET_SAMPLES: usize = 5760;
+
+    fn open(file: File) -> Result<Box<dyn AudioDecoder>, Error> {
+        let mut reader = ogg::PacketReader::new(BufReader::new(file));
+
+        let head = or_return!(
+            reader.read_packet().ok().flatten(),
+            Err(Error::CannotDecodeAudio)
+        ).data;
+
+        return_unless!(head.starts_with(b"OpusHead") && head.len() >= 19, Err(Error::CannotDecodeAudio));
+
+        let channels = match head[9] {
+            1 => audiopus::Channels::Mono,
+            2 => audiopus::Channels::Stereo,
+            _ => return Err(Error::UnsupportedAudioFormat) // Multistream files are not supported
+        };
+        let pre_skip = u16::from_le_bytes([head[10], head[11]]) as usize;
+
+        // The second packet holds the comments
+        let comments = or_return!(reader.read_packet().ok().flatten(), Err(Error::CannotDecodeAudio)).data;
+        let comments = comments
+            .strip_prefix(b"OpusTags")
+            .map(tags::parse_vorbis_comments)
+            .unwrap_or_default();
+
+        let decoder = or_return!(
+            audiopus::coder::Decoder::new(audiopus::SampleRate::Hz48000, channels).ok(),
+            Err(Error::CannotDecodeAudio)
+        );
+
+        Ok(Box::new(Self {
+            reader,
+            decoder,
+            channels: head[9] as u16,
+            pre_skip,
+            buffer: vec![0.0; Self::MAX_PACKET_SAMPLES * head[9] as usize],
+            comments
+        }))
+    }
+}
+
+#[cfg(feature = "opus")]
+impl AudioDecoder for OpusDecoder {
+    fn next_frame(&mut self) -> Option<Result<Frame, Error>> {
+        use std::convert::TryFrom;
+
+        loop {
+            let packet = match self.reader.read_packet() {
+                Ok(Some(packet)) => packet,
+                Ok(None) => return None,
+                Err(_) => return Some(Err(Error::CannotDecodeAudio))
+            };
+
+            let decoded = match audiopus::packet::Packet::try_from(packet.data.as_slice())
+                .and_then(|p| {
+                    let output = audiopus::MutSignals::try_from(self.buffer.as_mut_slice())?;
+                    self.decoder.decode_float(Some(p), output, false)
+                }) {
+                Ok(decoded) => decoded,
+                Err(_) => return Some(Err(Error::CannotDecodeAudio))
+            };
+
+            let skipped = self.pre_skip.min(decoded);
+            self.pre_skip -= skipped;
+
+            if skipped == decoded { continue; }
+
+            let channels = self.channels as usize;
+
+            return Some(Ok(Frame {
+                samples: self.buffer[skipped * channels..decoded * channels].to_vec(),
+                sample_rate: Self::SAMPLE_RATE,
+                channels: self.channels
+            }));
+        }
+    }
+
+    fn comments(&self) -> Vec<(String, String)> {
+        self.comments.clone()
+    }
+}
@@ -11,6 +11,7 @@ pub enum Error {
     BodyTooLarge,
     ConfigFileCannotBeRead,
     InvalidConfigFile,
+    UnsupportedConfigVersion,
     DatabaseDirectoryCannotBeRead,
     DatabaseFileCannotBeRead,
     InvalidDatabaseFile,
@@ -35,7 +36,14 @@ pub enum Error {
     CannotSetExitHandler,
     ProcessInterrupted,
     EventQueueFileCannotBeRead,
-    SourceChannelsNotMultipleOfTwo
+    SourceChannelsNotMultipleOfTwo,
+    UnsupportedAudioFormat,
+    CannotDecodeAudio,
+    CannotMeasureLoudness,
+    InvalidSink,
+    UnknownZone,
+    UnknownTimeZone,
+    InvalidTimeZoneFile
 }
 
 impl std::fmt::Display for Error {
@@ -50,6 +58,7 @@ impl std::fmt::Display for Error {
             Error::UnsupportedMethod => "unsupported HTTP method",
             Error::BodyTooLarge => "request body too large",
             Error::InvalidConfigFile => "invalid configuration file",
+            Error::UnsupportedConfigVersion => "configuration file of a newer version",
             Error::DatabaseDirectoryCannotBeRead => "cannot read database directory",
             Error::DatabaseFileCannotBeRead => "cannot read database file",
             Error::InvalidDatabaseFile => "invalid database file",
@@ -75,7 +84,14 @@ impl std::fmt::Display for Error {
             Error::CannotSetExitHandler => "cannot set exit handler",
             Error::ProcessInterrupted => "process interrupted",
             Error::EventQueueFileCannotBeRead => "cannot read event queue file",
-            Error::SourceChannelsNotMultipleOfTwo => "source channels not multiple of two"
+            Error::SourceChannelsNotMultipleOfTwo => "source channels not multiple of two",
+            Error::UnsupportedAudioFormat => "unsupported audio format",
+            Error::CannotDecodeAudio => "cannot decode audio",
+            Error::CannotMeasureLoudness => "cannot measure loudness",
+            Error::InvalidSink => "invalid output sink",
+            Error::UnknownZone => "unknown output zone",
+            Error::UnknownTimeZone => "unknown time zone",
+            Error::InvalidTimeZoneFile => "invalid time zone file"
         })
     }
 }
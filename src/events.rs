@@ -2,23 +2,16 @@ use std::collections::VecDeque;
 use std::hash::{DefaultHasher, Hash, Hasher};
 use std::num::{NonZeroU16, NonZeroU64};
 use std::path::{Path, PathBuf};
-use cpal::Device;
+use crate::sink::Sink;
 use cpal::traits::HostTrait;
-use crate::{logln, or_return, Error, EVENT_FILES_DIR, EVENT_QUEUE_FILE_NAME};
+use crate::{decoders, logln, or_return, Error, EVENT_FILES_DIR, EVENT_QUEUE_FILE_NAME};
 use crate::csv::CsvObject;
-use crate::songs::play_mp3;
+use crate::time::DateTime;
+use crate::songs::{display_duration, play_file};
+use crate::playback::{PlaybackControl, PlaybackSettings};
 
-fn is_leap_year(y: u64) -> bool {
-    (y % 4 == 0 && y % 100 != 0) || y % 400 == 0
-}
-fn days_in_month(y: u64, m: u64) -> Option<u64> {
-    match m {
-        1 | 3 | 5 | 7 | 8 | 10 | 12 => Some(31u64),
-        4 | 6 | 9 | 11 => Some(30u64),
-        2 => Some(if is_leap_year(y) { 29u64 } else { 28u64 }),
-        _ => None
-    }
-}
+/// The extension of event files whose format is not detected, which all event files had before formats were
+const DEFAULT_EXTENSION: &str = "mp3";
 
 /// An event for playing arbitrary sounds
 #[derive(Debug, Clone)]
@@ -32,7 +25,8 @@ pub struct Event {
 }
 
 impl Event {
-    /// Creates a new event with a name and an optional trigger. Saves the specified file contents.
+    /// Creates a new event with a name and an optional trigger. Saves the specified file contents
+    /// with the extension of their detected format, replacing the file of an earlier event of this name.
     pub fn new(
         trigger: Option<ScheduledTrigger>,
         name: Box<str>,
@@ -41,32 +35,19 @@ impl Event {
     ) -> Result<Self, Error> {
         let event = Self { trigger, name/*, file_name: OsString::from("").into_boxed_os_str()*/ };
 
+        let _ = std::fs::remove_file(PathBuf::from(EVENT_FILES_DIR).join(event.obtain_filename().as_ref()));
+
+        let extension = decoders::detect(file_contents.as_ref()).map_or(DEFAULT_EXTENSION, |b| b.extensions[0]);
+        let file_name = format!("{}.{extension}", event.file_stem());
+
         or_return!(
-            std::fs::write(PathBuf::from(EVENT_FILES_DIR).join(event.obtain_filename().as_ref()), file_contents).ok(),
+            std::fs::write(PathBuf::from(EVENT_FILES_DIR).join(file_name), file_contents).ok(),
             Err(Error::CannotWriteFile)
         );
 
         Ok(event)
     }
 
-    /// Triggers this event and returns whether it is necessary to remove it
-    pub fn trigger_event(&mut self, device: &Device) -> Result<bool, Error> {
-        let last_trigger = self.update_trigger_time();
-
-        play_mp3(
-            self.obtain_filename().as_ref(),
-            &device,
-            |rms, scale_factor, duration_secs|
-                logln!(
-                    "Playing \"{}\" ({:.1} seconds, RMS = {rms}, α={scale_factor})",
-                    self.name,
-                    duration_secs
-                )
-        )?;
-
-        Ok(last_trigger)
-    }
-
     /// Updates this event's trigger time and returns whether it is necessary to remove it
     pub fn update_trigger_time(&mut self) -> bool {
         let mut last_trigger = false;
@@ -113,10 +94,22 @@ impl Event {
         Self { name, trigger }
     }
 
+    /// Returns the name of this event's file in the `events` directory, whose extension is the one of its format
     pub fn obtain_filename(&self) -> Box<str> {
+        let stem = self.file_stem();
+
+        decoders::supported_extensions()
+            .map(|extension| format!("{stem}.{extension}"))
+            .find(|file_name| Path::new(EVENT_FILES_DIR).join(file_name).exists())
+            .unwrap_or_else(|| format!("{stem}.{DEFAULT_EXTENSION}"))
+            .into_boxed_str()
+    }
+
+    /// Returns the name of this event's file without its extension
+    fn file_stem(&self) -> String {
         let mut hasher = DefaultHasher::new();
         self.name.hash(&mut hasher);
-        format!("{:016x}.mp3", hasher.finish()).into_boxed_str()
+        format!("{:016x}", hasher.finish())
     }
 
     pub fn name(&self) -> &str {
@@ -124,6 +117,40 @@ impl Event {
     }
 }
 
+/// Plays events from a directory one after another with their target loudness (in LUFS),
+/// until the playback is stopped
+pub fn play_events(
+    events: Vec<(Event, f32)>,
+    event_files_path: impl AsRef<Path>,
+    sink: &Sink,
+    control: &PlaybackControl
+) -> Result<(), Error> {
+    for (event, target_loudness) in events {
+        let path = event_files_path.as_ref().join(event.obtain_filename().as_ref());
+
+        play_file(
+            &path,
+            event.name(),
+            sink,
+            None,
+            &PlaybackSettings::new(target_loudness),
+            control,
+            |loudness, scale_factor, duration_secs|
+                logln!(
+                    "Playing \"{}\" ({}, {loudness:.1} LUFS, α={scale_factor})",
+                    event.name(),
+                    display_duration(duration_secs)
+                )
+        )?;
+
+        logln!("Finished");
+
+        if control.is_stopped() { break; }
+    }
+
+    Ok(())
+}
+
 impl Ord for Event {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
         self.trigger.cmp(&other.trigger)
@@ -206,82 +233,19 @@ impl ScheduledTrigger {
         Some(ScheduledTrigger { next_trigger, trigger_period, triggers_remaining, auto_delete })
     }
 
-    /// Generates the internal trigger representation from a UNIX timestamp
+    /// Generates the internal trigger representation, the seconds since 1970-01-01 00:00:00 on the wall clock,
+    /// from an ISO 8601 date and time, such as `2026-10-17T08:00`
     pub fn raw_next_trigger_from(raw: &str) -> Option<u64> {
-        macro_rules! extract_datetime {
-            ($trigger_time:ident, $range:expr) => {
-                $trigger_time.get($range)?.parse::<u64>().ok()?
-            };
-        }
-
-        let year   = extract_datetime!(raw,  0.. 4);
-        let month  = extract_datetime!(raw,  5.. 7);
-        let day    = extract_datetime!(raw,  8..10);
-        let hour   = extract_datetime!(raw, 11..13);
-        let minute = extract_datetime!(raw, 14..16);
-        let second = raw.get(17..19).and_then(|v| v.parse::<u64>().ok()).unwrap_or(0);
-
-        if !(0 < month && month <= 12) || // Check for invalid values
-            !(0 < day && month <= days_in_month(year, month).unwrap())
-            || hour >= 24 || minute >= 60 || second >= 60
-        {
-            return None
-        }
-
-        // Convert date to "days since 1970-01-01"
-        // Adapted from Howard Hinnant’s civil_from_days() inverse.
-        let y = if month <= 2 { (year as i64) - 1 } else { year as i64 };
-        let m = if month <= 2 { (month as i64) + 12 } else { month as i64 };
-        let d = day as i64;
-
-        let era = y / 400;
-        let yoe = y - era * 400;                        // [0, 399]
-        let doy = ((153*(m - 3) + 2)/5 + d - 1) as i64; // [0, 365]
-        let doe = yoe * 365 + yoe/4 - yoe/100 + doy;    // [0, 146096]
-        let days = era * 146097 + doe - 719468;         // Days since 1970-01-01
-
-        if days < 0 {
-            return None; // before epoch
-        }
-
-        let total = days as u64 * 86400 + hour * 3600 + minute * 60 + second;
-
-        Some(total)
+        u64::try_from(DateTime::from_iso(raw)?.seconds_since_epoch()).ok()
     }
 
     pub fn next_trigger_raw(&self) -> u64 {
         self.next_trigger
     }
 
-    /// Generates a UNIX timestamp from the internal trigger representation
+    /// Formats the internal trigger representation as an ISO 8601 date and time
     pub fn next_trigger(&self) -> Box<str> {
-        let mut time = self.next_trigger;
-
-        let days = time / (24 * 60 * 60);
-        let mut seconds = time % (24 * 60 * 60);
-
-        let hour = seconds / (60 * 60);
-        seconds %= 60 * 60;
-
-        let minute = seconds / 60;
-        let second = seconds % 60;
-
-        // Date calculation (proleptic Gregorian calendar)
-        // Algorithm from Howard Hinnant’s "Civil From Days" (used in C++20)
-        let z = days as i64 + 719468;                              // Days since 0000-03-01
-        let era = (if z >= 0 { z } else { z - 146096 }) / 146097;
-        let doe = z - era * 146097;                                // Day of era
-        let yoe = (doe - doe/1460 + doe/36524 - doe/146096) / 365; // Year of era
-        let mut year = yoe + era * 400;
-        let doy = doe - (365*yoe + yoe/4 - yoe/100);               // Day of year
-        let mp = (5*doy + 2)/153;                                  // Month parameter
-        let day = doy - (153*mp+2)/5 + 1;                          // Day of month
-        let month = mp + if mp < 10 {3} else {-9};                 // Month number (1–12)
-        if month <= 2 {
-            year += 1;
-        }
-
-        format!("{year:04}-{month:02}-{day:02}T{hour:02}:{minute:02}:{second:02}").into_boxed_str()
+        DateTime::from_seconds_since_epoch(self.next_trigger as i64).display().into_boxed_str()
     }
 
     pub fn trigger_period(&self) -> Option<NonZeroU64> {
@@ -0,0 +1,262 @@
+use std::f32::consts::PI;
+use std::ops::Range;
+
+use crate::channels::ChannelMixer;
+use crate::analysis::SILENCE_THRESHOLD;
+use crate::loudness::db_to_gain;
+use crate::{or_return, return_unless};
+
+/// The length of a frame in seconds, each of which yields a sub-fingerprint
+const FRAME_SECS: f64 = 0.37;
+
+/// The time between the starts of two frames in seconds
+const FRAME_STEP_SECS: f64 = FRAME_SECS / 2.0;
+
+/// The length of the audio fingerprinted after the leading silence in seconds
+const MAX_SECS: f64 = 90.0;
+
+/// The number of frequency bands, one more than the bits of a sub-fingerprint
+const BANDS: usize = 33;
+
+/// The range of the frequencies in Hz, which are split into logarithmically spaced bands
+const FREQUENCIES: Range<f32> = 300.0..2000.0;
+
+/// The number of sub-fingerprints two fingerprints are shifted against each other at most when compared
+const MAX_OFFSET: usize = 4;
+
+/// The number of sub-fingerprints two fingerprints must overlap in to be compared
+const MIN_OVERLAP: usize = 16;
+
+/// The share of differing bits, below which two fingerprints are considered to be of the same recording
+const DUPLICATE_BIT_ERROR_RATE: f32 = 0.35;
+
+/// A compact acoustic fingerprint of the start of a recording,
+/// which stays similar when it is encoded differently or its volume is changed.\
+/// Each 32-bit sub-fingerprint tells how the energy differences of neighbouring frequency bands
+/// changed since the previous frame.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fingerprint(Box<[u32]>);
+
+impl Fingerprint {
+    /// Encodes the fingerprint as hexadecimal digits
+    pub fn to_hex(&self) -> String {
+        self.0.iter().map(|word| format!("{word:08x}")).collect()
+    }
+
+    /// Decodes a fingerprint from hexadecimal digits
+    pub fn from_hex(hex: &str) -> Option<Fingerprint> {
+        if !hex.len().is_multiple_of(8) || !hex.is_ascii() { return None; }
+
+        (0..hex.len())
+            .step_by(8)
+            .map(|i| u32::from_str_radix(&hex[i..i + 8], 16).ok())
+            .collect::<Option<_>>()
+            .map(Fingerprint)
+    }
+
+    /// Returns the lowest share of bits differing between two fingerprints, while shifting them
+    /// against each other, or `None` if they are too short to be compared
+    pub fn bit_error_rate(&self, other: &Fingerprint) -> Option<f32> {
+        let offsets = -(MAX_OFFSET as isize)..=MAX_OFFSET as isize;
+
+        offsets
+            .filter_map(|offset| {
+                let (a, b) = match offset {
+                    0.. => (self.0.get(offset as usize..)?, other.0.as_ref()),
+                    _ => (self.0.as_ref(), other.0.get(offset.unsigned_abs()..)?)
+                };
+                let overlap = a.len().min(b.len());
+                return_unless!(overlap >= MIN_OVERLAP, None);
+
+                let errors: u32 = a.iter().zip(b).map(|(x, y)| (x ^ y).count_ones()).sum();
+
+                Some(errors as f32 / (overlap * u32::BITS as usize) as f32)
+            })
+            .min_by(f32::total_cmp)
+    }
+
+    /// Returns whether two fingerprints are likely of the same recording
+    pub fn is_duplicate_of(&self, other: &Fingerprint) -> bool {
+        self.bit_error_rate(other).is_some_and(|rate| rate < DUPLICATE_BIT_ERROR_RATE)
+    }
+}
+
+/// Computes the fingerprint of a stream fed to it chunk by chunk, starting after its leading silence.\
+/// The frames are transformed at the sample rate of the stream, as only the ratios of their band energies matter.
+pub struct Fingerprinter {
+    mixer: ChannelMixer,
+    /// The mono samples of the frames not processed yet
+    buffer: Vec<f32>,
+    /// The number of samples between the starts of two frames
+    step: usize,
+    /// The window of a frame, which is padded with silence to a power of two for the FFT
+    window: Box<[f32]>,
+    fft_len: usize,
+    /// The FFT bins of each band
+    bands: Box<[Range<usize>]>,
+    /// The band energies of the previous frame
+    previous: Option<[f32; BANDS]>,
+    words: Vec<u32>,
+    /// Whether the leading silence has ended
+    started: bool
+}
+
+impl Fingerprinter {
+    /// Creates a fingerprinter of a stream with the given sample rate and number of interleaved channels
+    pub fn new(sample_rate: u32, channels: u16) -> Fingerprinter {
+        let frame_len = (FRAME_SECS * sample_rate as f64) as usize;
+        let fft_len = frame_len.next_power_of_two();
+
+        let bin = |frequency: f32| (frequency * fft_len as f32 / sample_rate as f32).round() as usize;
+        let ratio = FREQUENCIES.end / FREQUENCIES.start;
+        let edge = |band: usize| bin(FREQUENCIES.start * ratio.powf(band as f32 / BANDS as f32));
+
+        Fingerprinter {
+            mixer: ChannelMixer::new(channels, 1),
+            buffer: Vec::with_capacity(frame_len * 2),
+            step: ((FRAME_STEP_SECS * sample_rate as f64) as usize).max(1),
+            window: (0..frame_len)
+                .map(|i| 0.5 - 0.5 * (2.0 * PI * i as f32 / frame_len as f32).cos())
+                .collect(),
+            fft_len,
+            bands: (0..BANDS).map(|band| edge(band)..edge(band + 1).max(edge(band) + 1)).collect(),
+            previous: None,
+            words: Vec::new(),
+            started: false
+        }
+    }
+
+    /// Feeds the next interleaved samples of the stream
+    pub fn feed(&mut self, samples: &[f32], channels: u16) {
+        if self.is_full() { return; }
+
+        let mut samples = samples;
+
+        if !self.started {
+            let threshold = db_to_gain(SILENCE_THRESHOLD);
+            let first = or_return!(samples.iter().position(|s| s.abs() > threshold));
+
+            samples = &samples[first - first % channels.max(1) as usize..];
+            self.started = true;
+        }
+
+        self.buffer.extend(self.mixer.process(samples, channels));
+
+        while self.buffer.len() >= self.window.len() && !self.is_full() {
+            self.process_frame();
+            self.buffer.drain(..self.step);
+        }
+    }
+
+    /// Returns whether enough of the stream has been fed
+    pub fn is_full(&self) -> bool {
+        self.words.len() as f64 >= MAX_SECS / FRAME_STEP_SECS
+    }
+
+    /// Returns the fingerprint of the fed stream
+    pub fn finish(self) -> Fingerprint {
+        Fingerprint(self.words.into_boxed_slice())
+    }
+
+    fn process_frame(&mut self) {
+        let mut re: Vec<f32> = self.buffer.iter().zip(self.window.iter()).map(|(s, w)| s * w).collect();
+        re.resize(self.fft_len, 0.0);
+        let mut im = vec![0.0; self.fft_len];
+
+        fft(&mut re, &mut im);
+
+        let energies: [f32; BANDS] = std::array::from_fn(|band| {
+            self.bands[band].clone().map(|k| re[k] * re[k] + im[k] * im[k]).sum()
+        });
+
+        if let Some(previous) = self.previous {
+            let word = (0..BANDS - 1)
+                .filter(|&b| energies[b] - energies[b + 1] > previous[b] - previous[b + 1])
+                .fold(0, |word, b| word | 1 << b);
+
+            self.words.push(word);
+        }
+
+        self.previous = Some(energies);
+    }
+}
+
+/// Transforms a signal in place with an iterative radix-2 FFT.
+/// The length of the signal must be a power of two.
+fn fft(re: &mut [f32], im: &mut [f32]) {
+    let n = re.len();
+
+    // Reorder the samples by their bit-reversed indices
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+
+    let mut len = 2;
+    while len <= n {
+        let angle = -2.0 * std::f64::consts::PI / len as f64;
+        let twiddles: Vec<(f32, f32)> = (0..len / 2)
+            .map(|k| {
+                let (sin, cos) = (angle * k as f64).sin_cos();
+                (cos as f32, sin as f32)
+            })
+            .collect();
+
+        for start in (0..n).step_by(len) {
+            for (k, &(w_re, w_im)) in twiddles.iter().enumerate() {
+                let (a, b) = (start + k, start + k + len / 2);
+
+                let t_re = re[b] * w_re - im[b] * w_im;
+                let t_im = re[b] * w_im + im[b] * w_re;
+
+                re[b] = re[a] - t_re;
+                im[b] = im[a] - t_im;
+                re[a] += t_re;
+                im[a] += t_im;
+            }
+        }
+
+        len <<= 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns a fingerprint of pseudo-random words, which differs from the ones of other seeds
+    fn random_fingerprint(seed: u32, len: usize) -> Fingerprint {
+        let mut state = seed;
+
+        Fingerprint((0..len).map(|_| {
+            state ^= state << 13;
+            state ^= state >> 17;
+            state ^= state << 5;
+            state
+        }).collect())
+    }
+
+    #[test]
+    fn hex_round_trip_and_shifted_duplicate() {
+        let fingerprint = random_fingerprint(1, 64);
+        assert_eq!(Fingerprint::from_hex(&fingerprint.to_hex()), Some(fingerprint.clone()));
+
+        // A recording starting 2 sub-fingerprints later still matches
+        let shifted = Fingerprint(fingerprint.0[2..].into());
+        assert_eq!(fingerprint.bit_error_rate(&shifted), Some(0.0));
+        assert!(fingerprint.is_duplicate_of(&shifted));
+        assert!(shifted.is_duplicate_of(&fingerprint));
+
+        assert!(!fingerprint.is_duplicate_of(&random_fingerprint(2, 64)));
+    }
+}
@@ -0,0 +1,191 @@
+use crate::time::{Date, DateTime, Time};
+use crate::timezone::TimeZone;
+use crate::{or_continue, return_unless};
+
+/// The number of days of a week in iCalendar durations
+const DAYS_PER_WEEK: i32 = 7;
+
+/// An event of an iCalendar file, which spans whole days
+#[derive(Debug, Clone)]
+pub struct CalendarEvent {
+    /// The summary of the event, or an empty string if it has none
+    pub summary: String,
+    /// The first day of the event
+    pub first: Date,
+    /// The last day of the event, which is `first` for events of a single day
+    pub last: Date
+}
+
+/// Reads the events of an iCalendar (RFC 5545) file.\
+/// Timed events span every day they touch in the given time zone, which their UTC times and the ones
+/// of a `TZID` are converted to, and recurrences are ignored, so a repeating event only counts once.
+/// Events without a valid start are skipped.
+pub fn read_events(text: &str, zone: &TimeZone) -> Vec<CalendarEvent> {
+    let mut events = Vec::new();
+
+    let mut in_event = false;
+    let mut summary = String::new();
+    let mut start: Option<(Date, bool)> = None;
+    let mut end: Option<(Date, bool)> = None;
+    let mut days: Option<i32> = None;
+
+    for line in unfold(text) {
+        let (name, parameters, value) = or_continue!(split_content_line(&line));
+
+        match name.to_ascii_uppercase().as_str() {
+            "BEGIN" if value.eq_ignore_ascii_case("VEVENT") => {
+                in_event = true;
+                summary.clear();
+                (start, end, days) = (None, None, None);
+            },
+            "END" if value.eq_ignore_ascii_case("VEVENT") && in_event => {
+                in_event = false;
+                let (first, _) = or_continue!(start);
+
+                // All-day events end before the day of their end, and timed ones before their end
+                let last = match (end, days) {
+                    (Some((end, true)), _) => end,
+                    (Some((end, false)), _) => end.plus_days(-1),
+                    (None, Some(days)) => first.plus_days(days - 1),
+                    (None, None) => first
+                };
+
+                events.push(CalendarEvent {
+                    summary: std::mem::take(&mut summary),
+                    first,
+                    last: last.max(first)
+                });
+            },
+            "SUMMARY" if in_event => summary = unescape_text(value),
+            "DTSTART" if in_event => start = parse_date(value, parameters, zone),
+            "DTEND" if in_event => end = parse_date(value, parameters, zone),
+            "DURATION" if in_event => days = parse_duration_days(value),
+            _ => {}
+        }
+    }
+
+    events
+}
+
+/// Joins the lines continued on the next line starting with a space or a tab
+fn unfold(text: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+
+    for line in text.lines() {
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(continuation), Some(last)) => last.push_str(continuation),
+            _ => lines.push(line.to_string())
+        }
+    }
+
+    lines
+}
+
+/// Splits a content line into its name, its parameters separated by semicolons, and its value
+fn split_content_line(line: &str) -> Option<(&str, &str, &str)> {
+    // The value starts at the first colon outside the quoted parameter values
+    let mut in_quotes = false;
+    let colon = line.find(|c| {
+        if c == '"' { in_quotes = !in_quotes; }
+        c == ':' && !in_quotes
+    })?;
+
+    let (name, parameters) = line[..colon].split_once(';').unwrap_or((&line[..colon], ""));
+    Some((name, parameters, &line[(colon + 1)..]))
+}
+
+/// Returns the value of a parameter without its quotes, or `None` if it is missing
+fn parameter<'a>(parameters: &'a str, name: &str) -> Option<&'a str> {
+    parameters
+        .split(';')
+        .filter_map(|p| p.split_once('='))
+        .find(|(n, _)| n.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.trim_matches('"'))
+}
+
+/// Parses a `DATE` or `DATE-TIME` value into its date in a time zone,
+/// and whether it is the date-time of a moment after the start of the day.\
+/// UTC times and the ones of a known `TZID` are converted to the zone, while other times are taken as its own.
+fn parse_date(value: &str, parameters: &str, zone: &TimeZone) -> Option<(Date, bool)> {
+    return_unless!(value.contains('T'), Some((Date::from_iso(value)?, false)));
+
+    let (date_time, offset) = DateTime::from_iso_with_offset(value)?;
+    let unix_time = match offset {
+        Some(offset) => Some(date_time.to_unix_time(offset)),
+        None => parameter(parameters, "TZID")
+            .and_then(|name| TimeZone::load(name).ok())
+            .map(|source| source.unix_time_of(date_time))
+    };
+
+    let date_time = unix_time.map_or(date_time, |t| zone.local_time(t));
+    Some((date_time.date(), date_time.time() != Time::from_seconds(0)))
+}
+
+/// Returns the number of days a `DURATION` value spans, counting a started day as a whole one
+fn parse_duration_days(value: &str) -> Option<i32> {
+    let value = value.strip_prefix('+').unwrap_or(value).strip_prefix('P')?;
+    let (date_part, time_part) = value.split_once('T').unwrap_or((value, ""));
+
+    let days = if let Some(weeks) = date_part.strip_suffix('W') {
+        weeks.parse::<i32>().ok()? * DAYS_PER_WEEK
+    } else if let Some(days) = date_part.strip_suffix('D') {
+        days.parse::<i32>().ok()?
+    } else {
+        return_unless!(date_part.is_empty(), None);
+        0
+    };
+
+    Some(days + !time_part.is_empty() as i32)
+}
+
+/// Replaces the escaped characters of a `TEXT` value, and line breaks with spaces
+fn unescape_text(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    let mut chars = value.chars();
+
+    while let Some(c) = chars.next() {
+        result.push(match c {
+            '\\' => match chars.next() {
+                Some('n' | 'N') => ' ',
+                Some(escaped) => escaped,
+                None => break
+            },
+            c => c
+        });
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Wraps the lines of an event into a calendar
+    fn calendar(event: &str) -> String {
+        format!("BEGIN:VCALENDAR\r\nBEGIN:VEVENT\r\nSUMMARY:Autumn break\r\n{event}END:VEVENT\r\nEND:VCALENDAR\r\n")
+    }
+
+    #[test]
+    fn utc_start_is_converted_to_zone() {
+        let text = calendar("DTSTART:20261022T220000Z\r\nDTEND:20261101T220000Z\r\n");
+        let events = read_events(&text, &TimeZone::fixed(2 * 3600));
+
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].first, Date::new(2026, 10, 23).unwrap());
+        assert_eq!(events[0].last, Date::new(2026, 11, 1).unwrap());
+    }
+
+    #[test]
+    fn floating_and_all_day_dates_are_kept() {
+        let zone = TimeZone::fixed(-5 * 3600);
+
+        let timed = read_events(&calendar("DTSTART:20261022T000000\r\nDTEND:20261024T120000\r\n"), &zone);
+        assert_eq!(timed[0].first, Date::new(2026, 10, 22).unwrap());
+        assert_eq!(timed[0].last, Date::new(2026, 10, 24).unwrap());
+
+        let all_day = read_events(&calendar("DTSTART;VALUE=DATE:20261022\r\nDTEND;VALUE=DATE:20261024\r\n"), &zone);
+        assert_eq!(all_day[0].first, Date::new(2026, 10, 22).unwrap());
+        assert_eq!(all_day[0].last, Date::new(2026, 10, 23).unwrap());
+    }
+}
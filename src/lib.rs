@@ -13,17 +13,28 @@ use std::convert::Infallible;
 use std::io::Write;
 use std::net::{TcpListener, ToSocketAddrs};
 use std::path::Path;
-use std::time::SystemTime;
-use cpal::traits::HostTrait;
-use crate::time::{Time, Day};
+use crate::time::DateTime;
 
 pub mod songs;
+pub mod playback;
+pub mod sink;
+mod mixer;
+pub mod decoders;
+pub mod ringbuffer;
+pub mod resample;
+pub mod channels;
+pub mod loudness;
+pub mod analysis;
+pub mod tags;
+pub mod fingerprint;
 mod macros;
 pub mod database;
 pub mod config;
 pub mod webserver;
 pub mod csv;
+pub mod ical;
 pub mod time;
+pub mod timezone;
 pub mod logging;
 pub mod events;
 mod error;
@@ -31,6 +42,7 @@ mod error;
 pub mod generated { include!(concat!(env!("OUT_DIR"), "/generated.rs")); }
 
 pub use crate::error::Error;
+use crate::config::EventPriority;
 use crate::events::EventQueue;
 
 /// The package version from `Cargo.toml`
@@ -40,6 +52,7 @@ pub static EVENT_FILES_DIR: &str = "./events/";
 pub static CONFIG_FILE_PATH: &str = "./config.musiq";
 pub static DATABASE_FILE_NAME: &str = "db.csv";
 pub static EVENT_QUEUE_FILE_NAME: &str = "queue.bin";
+/// The number of songs played in a break with `BreakStrategy::Single`
 pub const PLAYLIST_LENGTH: usize = 1;
 pub const TIMEOUT: Option<core::time::Duration> = Some(core::time::Duration::from_secs(1));
 
@@ -118,7 +131,17 @@ pub fn main<A: ToSocketAddrs, P: AsRef<Path> + Clone + Send + 'static, F: FnMut(
             .join(EVENT_QUEUE_FILE_NAME)
     )?;
 
-    let mut play_thread: Option<std::thread::JoinHandle<_>> = None;
+    let mut players = playback::ZonePlayers::new();
+    // The scheduled playbacks, one per zone they play in
+    let mut play_threads: Vec<std::thread::JoinHandle<_>> = Vec::new();
+    // The controls of the playbacks started for a break, which are stopped when the break ends
+    let mut music_controls: Vec<playback::PlaybackControl> = Vec::new();
+    // The triggered events of normal priority, which wait until nothing else is playing
+    let mut pending_events: Vec<events::Event> = Vec::new();
+    // The playbacks of events playing over the others, with their priorities
+    let mut priority_threads: Vec<(EventPriority, std::thread::JoinHandle<Result<(), Error>>)> = Vec::new();
+
+    let mut song_analyzer = analysis::SongAnalyzer::new();
 
     #[allow(unused_labels)]
     '_main: loop {
@@ -133,6 +156,8 @@ pub fn main<A: ToSocketAddrs, P: AsRef<Path> + Clone + Send + 'static, F: FnMut(
                 &mut database,
                 &mut configs,
                 &mut event_queue,
+                &mut players,
+                &mut song_analyzer
             );
 
             let _ = stream.write_all(response.as_bytes().as_slice());
@@ -144,75 +169,186 @@ pub fn main<A: ToSocketAddrs, P: AsRef<Path> + Clone + Send + 'static, F: FnMut(
             let _ = event_queue.save_to_file(event_files_path.as_ref().join(EVENT_QUEUE_FILE_NAME));
         }
 
-        let timestamp = { // TODO: separate this into a function
-            let mut timestamp = SystemTime::now()
-                .duration_since(SystemTime::UNIX_EPOCH)
-                .expect("The system time is before the UNIX epoch.")
-                .as_secs();
+        if song_analyzer.update(&mut database) {
+            let _ = database.save_to_file();
+        }
 
-            let offset = configs.utc_offset() as i64;
+        // Events are scheduled on the wall clock, which follows the daylight saving time of the time zone
+        let local_now = DateTime::now(configs.time_zone());
+        let timestamp = local_now.seconds_since_epoch().max(0) as u64;
+        let now = local_now.time();
 
-            match offset {
-                i64::MIN..0 => timestamp -= offset.abs() as u64 * 3600,
-                0..=i64::MAX => timestamp += offset as u64 * 3600
-            }
+        pending_events.extend(event_queue.trigger_events(timestamp));
 
-            timestamp
-        };
+        // Events above the normal priority don't wait for the music
+        let (urgent_events, waiting_events): (Vec<_>, Vec<_>) = pending_events
+            .drain(..)
+            .partition(|e| configs.event_priority(e.name()) > EventPriority::Normal);
+        pending_events = waiting_events;
 
-        let now = Time::now(configs.utc_offset());
+        if urgent_events.iter().any(|e| configs.event_priority(e.name()) == EventPriority::Emergency) {
+            players.stop_all();
+            music_controls.clear();
+            logln!("Emergency event at {}, stopping every playback", now);
+        }
 
-        let events_to_trigger = event_queue.trigger_events(timestamp);
+        priority_threads.retain(|(_, t)| !t.is_finished());
 
-        let helper = |configs: &mut config::Configs, database: &mut database::SongDatabase| {
-            if let Some(action) = configs.timetable().action(
-                &now,
-                &Day::today(configs.utc_offset())
-            ) {
-                if !action { return None; }
-                let playlist = or_return!(songs::compose_playlist(PLAYLIST_LENGTH, database), None);
+        for priority in [EventPriority::Overlay, EventPriority::Duck, EventPriority::Interrupt, EventPriority::Emergency] {
+            let events = urgent_events
+                .iter()
+                .filter(|e| configs.event_priority(e.name()) == priority)
+                .cloned()
+                .collect::<Vec<_>>();
 
-                logln!("Scheduled play started at {}", now);
-                Some(std::thread::spawn(move || songs::play_playlist(&playlist)))
-            } else if events_to_trigger.len() > 0 {
+            for (zone, events) in group_events_by_zone(events, &configs) {
                 let event_files_path = event_files_path.clone();
+                let sink = configs.open_zone_sink(zone.as_deref());
+                let zone_suffix = display_zone(zone.as_deref());
+
+                // Lower the music before the event joins the playbacks of the zone
+                let ducks = priority
+                    .music_gain()
+                    .and_then(|gain| Some(players.get(zone.as_deref())?.duck(gain)))
+                    .unwrap_or_default();
+
+                let (thread, _) = players.get_mut(zone.as_deref()).spawn_over(move |control| {
+                    // Restore the music when the event ends, even if it fails
+                    let _ducks = ducks;
+                    let sink = sink?;
+
+                    logln!("Scheduled {} event started at {}{zone_suffix}", priority.name(), now);
+                    events::play_events(events, &event_files_path, &sink, control)
+                });
+                priority_threads.push((priority, thread));
+            }
+        }
+
+        // The calendar chooses the timetable of each day, and days off have no breaks
+        let today = local_now.date();
+        let break_action = match configs.holiday_on(today) {
+            Some(_) => None,
+            None => configs.timetable_on(today).break_action(&now, &today.weekday())
+        };
+
+        if let Some((_, false)) = break_action
+            && !music_controls.is_empty()
+            && play_threads.iter().any(|t| !t.is_finished())
+        {
+            for control in music_controls.drain(..) {
+                control.stop();
+            }
+            logln!("Break ended at {}, stopping playback", now);
+        }
+
+        let mut helper = |configs: &mut config::Configs, database: &mut database::SongDatabase| {
+            if let Some((break_index, action)) = break_action {
+                if !action { return Vec::new(); }
+                let timetable = configs.timetable_on(today);
+                let break_secs = or_return!(timetable.break_duration(break_index), Vec::new()).as_secs_f64();
+                let playlist = or_return!(
+                    songs::compose_break_playlist(break_secs, timetable.break_strategy(break_index), database),
+                    Vec::new()
+                );
+                let settings = configs.break_playback_settings(timetable, break_index);
+                let playlist_secs: f64 = playlist.iter().filter_map(|s| s.played_duration()).sum();
+
+                // A break without zones plays on the default output
+                let zones = match timetable.break_zones(break_index) {
+                    [] => vec![None],
+                    zones => zones.iter().map(|z| Some(z.as_ref())).collect()
+                };
+                let mut threads = Vec::new();
+
+                for zone in zones {
+                    let sink = match configs.open_zone_sink(zone) {
+                        Ok(sink) => sink,
+                        Err(e) => {
+                            logln!("Scheduled play cannot be started at {}{}: {e}", now, display_zone(zone));
+                            continue;
+                        }
+                    };
+
+                    logln!(
+                        "Scheduled play started at {}{} ({} songs, {playlist_secs:.0} seconds in a {break_secs:.0} second break)",
+                        now,
+                        display_zone(zone),
+                        playlist.len()
+                    );
+                    let playlist = playlist.clone();
+                    let (thread, control) = players.get_mut(zone).spawn(
+                        move |control| songs::play_playlist(&playlist, &sink, &settings, control)
+                    );
+                    threads.push(thread);
+                    music_controls.push(control);
+                }
 
-                // TODO: make this into a separate function in events.rs
-                Some(std::thread::spawn(move || {
-                    let host = cpal::default_host();
-                    let device = or_return!(host.default_output_device(), Err(Error::NoOutputDevice));
-
-                    logln!("Scheduled event started at {}", now);
-                    for event in events_to_trigger {
-                        let path = event_files_path.as_ref().join(event.obtain_filename().as_ref());
-
-                        songs::play_mp3(
-                            &path,
-                            &device,
-                            |rms, scale_factor, duration_secs|
-                                logln!(
-                                    "Playing \"{}\" ({:.1} seconds, RMS = {rms}, α={scale_factor})",
-                                    event.name(),
-                                    duration_secs
-                                )
-                        )?;
-
-                        logln!("Finished")
-                    }
-
-                    Ok(())
-                }))
+                threads
+            } else if !pending_events.is_empty() {
+                music_controls.clear();
+
+                group_events_by_zone(std::mem::take(&mut pending_events), configs)
+                    .into_iter()
+                    .map(|(zone, events)| {
+                        let event_files_path = event_files_path.clone();
+                        let sink = configs.open_zone_sink(zone.as_deref());
+                        let zone_suffix = display_zone(zone.as_deref());
+
+                        players.get_mut(zone.as_deref()).spawn(move |control| {
+                            let sink = sink?;
+
+                            logln!("Scheduled event started at {}{zone_suffix}", now);
+                            events::play_events(events, &event_files_path, &sink, control)
+                        }).0
+                    })
+                    .collect()
             } else {
-                None
+                Vec::new()
             }
         };
 
-        match &play_thread {
-            Some(t) if t.is_finished() => play_thread = helper(&mut configs, &mut database),
-            None => play_thread = helper(&mut configs, &mut database),
-            Some(_) => ()
+        // Breaks start while events play over the music, as they are mixed together,
+        // but nothing starts during emergencies, and waiting events wait for every other one
+        let events_playing = priority_threads.iter().any(|(_, t)| !t.is_finished());
+        let emergency_playing = priority_threads
+            .iter()
+            .any(|(p, t)| *p == EventPriority::Emergency && !t.is_finished());
+        let break_starts = matches!(break_action, Some((_, true)));
+
+        if play_threads.iter().all(|t| t.is_finished()) && !emergency_playing && (break_starts || !events_playing) {
+            play_threads = helper(&mut configs, &mut database);
+        }
+    }
+}
+
+/// Events with their target loudness, grouped by the zones they play in
+type ZoneEvents = Vec<(Option<Box<str>>, Vec<(events::Event, f32)>)>;
+
+/// Groups events by the zones they play in, keeping their order within each zone
+fn group_events_by_zone(events: Vec<events::Event>, configs: &config::Configs) -> ZoneEvents {
+    let mut zone_events: ZoneEvents = Vec::new();
+
+    for event in events {
+        let target_loudness = configs.event_target_loudness(event.name());
+        let zones = match configs.event_zones(event.name()) {
+            [] => vec![None],
+            zones => zones.iter().cloned().map(Some).collect()
+        };
+
+        for zone in zones {
+            match zone_events.iter_mut().find(|(z, _)| *z == zone) {
+                Some((_, events)) => events.push((event.clone(), target_loudness)),
+                None => zone_events.push((zone, vec![(event.clone(), target_loudness)]))
+            }
         }
     }
+
+    zone_events
+}
+
+/// Describes the zone a playback is in for logging, which is nothing for the default output
+fn display_zone(zone: Option<&str>) -> String {
+    zone.map_or_else(String::new, |zone| format!(" in zone \"{zone}\""))
 }
 
 pub fn enable_all<P: AsRef<Path> + Clone, F: FnMut(&songs::Song) -> bool>(
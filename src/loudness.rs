@@ -0,0 +1,340 @@
+use std::collections::VecDeque;
+use std::f64::consts::PI;
+
+use crate::channels::{layout, Speaker};
+use crate::resample::{blackman_harris, sinc};
+use crate::tags;
+
+/// The loudness (in LUFS) ReplayGain 2.0 track gains are relative to
+const REPLAYGAIN_REFERENCE: f32 = -18.0;
+
+/// The loudness (in LUFS) the R128 gains of Opus files are relative to
+const R128_REFERENCE: f32 = -23.0;
+
+/// The blocks quieter than this (in LUFS) are left out of the integrated loudness
+const ABSOLUTE_GATE: f64 = -70.0;
+
+/// The blocks quieter than the ungated loudness by this much (in LU) are left out of the integrated loudness
+const RELATIVE_GATE: f64 = -10.0;
+
+/// The number of 100 ms steps in a 400 ms measurement block
+const BLOCK_STEPS: usize = 4;
+
+/// The loudness (in LUFS) songs are normalized to unless configured otherwise
+pub const DEFAULT_TARGET_LOUDNESS: f32 = -20.0;
+
+/// The highest level (in dBTP) the limiter lets through
+pub const TRUE_PEAK_CEILING: f32 = -1.0;
+
+/// The number of interpolated values per sample used to estimate true peaks
+const OVERSAMPLING: usize = 4;
+
+/// The number of samples the true peak interpolator is computed from
+const INTERPOLATOR_TAPS: usize = 16;
+
+/// The time (in seconds) the limiter sees peaks before they are played
+const LIMITER_LOOKAHEAD_SECS: f64 = 0.005;
+
+/// The time (in seconds) in which the limiter's gain recovers after a peak
+const LIMITER_RELEASE_SECS: f64 = 0.2;
+
+/// Converts a level in decibels to a linear gain
+pub fn db_to_gain(db: f32) -> f32 {
+    10f32.powf(db / 20.0)
+}
+
+
+/// A second-order IIR filter in transposed direct form II
+#[derive(Clone)]
+struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+    z: [f64; 2]
+}
+
+impl Biquad {
+    fn process(&mut self, x: f64) -> f64 {
+        let y = self.b[0] * x + self.z[0];
+        self.z[0] = self.b[1] * x - self.a[0] * y + self.z[1];
+        self.z[1] = self.b[2] * x - self.a[1] * y;
+        y
+    }
+
+    /// The high shelf of the K-weighting filter modelling the acoustic effect of the head
+    fn k_shelf(sample_rate: u32) -> Biquad {
+        let (f0, gain, q) = (1681.974450955533, 3.999843853973347, 0.7071752369554196);
+
+        let k = (PI * f0 / sample_rate as f64).tan();
+        let vh = 10f64.powf(gain / 20.0);
+        let vb = vh.powf(0.4996667741545416);
+        let a0 = 1.0 + k / q + k * k;
+
+        Biquad {
+            b: [(vh + vb * k / q + k * k) / a0, 2.0 * (k * k - vh) / a0, (vh - vb * k / q + k * k) / a0],
+            a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+            z: [0.0; 2]
+        }
+    }
+
+    /// The high-pass of the K-weighting filter
+    fn k_high_pass(sample_rate: u32) -> Biquad {
+        let (f0, q) = (38.13547087602444, 0.5003270373238773);
+
+        let k = (PI * f0 / sample_rate as f64).tan();
+        let a0 = 1.0 + k / q + k * k;
+
+        Biquad {
+            b: [1.0, -2.0, 1.0],
+            a: [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+            z: [0.0; 2]
+        }
+    }
+}
+
+/// Measures the integrated loudness of a stream as specified by ITU-R BS.1770 (EBU R128)
+pub struct LoudnessMeter {
+    channels: usize,
+    /// The weight of each channel by its position
+    weights: Box<[f64]>,
+    /// The K-weighting filters of each channel
+    filters: Box<[(Biquad, Biquad)]>,
+    /// The number of frames in a 100 ms step
+    step_len: usize,
+    /// The weighted sum of squares in the current step
+    step_sum: f64,
+    /// The number of frames in the current step
+    step_frames: usize,
+    /// The mean weighted squares of the last steps
+    recent_steps: VecDeque<f64>,
+    /// The mean weighted squares of every completed 400 ms block
+    blocks: Vec<f64>
+}
+
+impl LoudnessMeter {
+    /// Creates a meter for interleaved samples with the given sample rate and number of channels
+    pub fn new(sample_rate: u32, channels: u16) -> LoudnessMeter {
+        let weights = layout(channels.max(1))
+            .into_iter()
+            .map(|speaker| match speaker {
+                Speaker::LowFrequency => 0.0,
+                Speaker::BackLeft | Speaker::BackRight | Speaker::BackCenter |
+                Speaker::SideLeft | Speaker::SideRight => 1.41,
+                _ => 1.0
+            })
+            .collect::<Box<[f64]>>();
+
+        LoudnessMeter {
+            channels: weights.len(),
+            filters: weights
+                .iter()
+                .map(|_| (Biquad::k_shelf(sample_rate), Biquad::k_high_pass(sample_rate)))
+                .collect(),
+            weights,
+            step_len: (sample_rate as usize / 10).max(1),
+            step_sum: 0.0,
+            step_frames: 0,
+            recent_steps: VecDeque::with_capacity(BLOCK_STEPS),
+            blocks: Vec::new()
+        }
+    }
+
+    /// Measures the next chunk of the stream
+    pub fn feed(&mut self, samples: &[f32]) {
+        for frame in samples.chunks_exact(self.channels) {
+            for ((sample, (shelf, high_pass)), weight) in frame.iter().zip(self.filters.iter_mut()).zip(self.weights.iter()) {
+                let y = high_pass.process(shelf.process(*sample as f64));
+                self.step_sum += weight * y * y;
+            }
+
+            self.step_frames += 1;
+
+            if self.step_frames == self.step_len {
+                if self.recent_steps.len() == BLOCK_STEPS {
+                    self.recent_steps.pop_front();
+                }
+                self.recent_steps.push_back(self.step_sum / self.step_len as f64);
+
+                // The blocks overlap by 75%, so one ends with every step
+                if self.recent_steps.len() == BLOCK_STEPS {
+                    self.blocks.push(self.recent_steps.iter().sum::<f64>() / BLOCK_STEPS as f64);
+                }
+
+                self.step_sum = 0.0;
+                self.step_frames = 0;
+            }
+        }
+    }
+
+    /// Returns the gated loudness (in LUFS) of the stream measured so far,
+    /// or `None` if it had no block above the absolute gate.
+    pub fn integrated(&self) -> Option<f32> {
+        let to_lufs = |mean_square: f64| -0.691 + 10.0 * mean_square.log10();
+        let from_lufs = |lufs: f64| 10f64.powf((lufs + 0.691) / 10.0);
+
+        let mean_above = |threshold: f64| {
+            let (sum, count) = self.blocks
+                .iter()
+                .filter(|&&b| b > threshold)
+                .fold((0.0, 0usize), |(sum, count), b| (sum + b, count + 1));
+
+            if count == 0 { None } else { Some(sum / count as f64) }
+        };
+
+        let absolute = from_lufs(ABSOLUTE_GATE);
+        let ungated = mean_above(absolute)?;
+        let relative = ungated * 10f64.powf(RELATIVE_GATE / 10.0);
+        let gated = mean_above(relative.max(absolute))?;
+
+        Some(to_lufs(gated) as f32)
+    }
+}
+
+/// Parses a gain tag formatted like `-6.54 dB`
+fn parse_gain(value: &str) -> Option<f32> {
+    let value = value.trim();
+    let value = value
+        .strip_suffix("dB")
+        .or_else(|| value.strip_suffix("db"))
+        .unwrap_or(value);
+
+    value.trim().trim_start_matches('+').parse().ok()
+}
+
+/// Returns the loudness (in LUFS) stated by the ReplayGain or R128 tags of a file
+pub fn tagged_loudness(comments: &[(String, String)]) -> Option<f32> {
+    if let Some(gain) = tags::find(comments, "REPLAYGAIN_TRACK_GAIN").and_then(parse_gain) {
+        return Some(REPLAYGAIN_REFERENCE - gain);
+    }
+
+    // Stored as a Q7.8 fixed-point number
+    let gain = tags::find(comments, "R128_TRACK_GAIN")?.trim().parse::<i32>().ok()?;
+
+    Some(R128_REFERENCE - gain as f32 / 256.0)
+}
+
+
+/// Keeps the true peaks of interleaved samples below a ceiling by looking ahead
+/// and smoothly lowering the gain before them.\
+/// The output is delayed by the lookahead, so the state is kept between calls.
+pub struct Limiter {
+    channels: usize,
+    /// The highest allowed sample value
+    ceiling: f32,
+    /// The interpolation filters of the positions between two samples
+    phases: Box<[[f32; INTERPOLATOR_TAPS]]>,
+    /// The last `INTERPOLATOR_TAPS` interleaved input frames
+    history: VecDeque<f32>,
+    /// The interleaved input frames waiting to be played
+    delay: VecDeque<f32>,
+    /// The number of frames in `delay`
+    lookahead: usize,
+    /// The decreasing peaks of the frames in the lookahead window with their frame index
+    peaks: VecDeque<(u64, f32)>,
+    /// The index of the next input frame
+    frame: u64,
+    gain: f32,
+    attack: f32,
+    release: f32
+}
+
+impl Limiter {
+    /// Creates a limiter for the given sample rate and number of interleaved channels
+    pub fn new(sample_rate: u32, channels: u16) -> Limiter {
+        let channels = channels.max(1) as usize;
+        let lookahead = ((LIMITER_LOOKAHEAD_SECS * sample_rate as f64) as usize).max(1);
+
+        let half = INTERPOLATOR_TAPS / 2;
+
+        let phases = (1..OVERSAMPLING)
+            .map(|p| {
+                let offset = p as f64 / OVERSAMPLING as f64;
+                let mut taps = [0.0; INTERPOLATOR_TAPS];
+
+                for (k, tap) in taps.iter_mut().enumerate() {
+                    let x = k as f64 - (half - 1) as f64 - offset;
+                    *tap = (sinc(x) * blackman_harris(x / half as f64)) as f32;
+                }
+
+                // Keep the gain at DC at one
+                let sum: f32 = taps.iter().sum();
+                taps.iter_mut().for_each(|t| *t /= sum);
+
+                taps
+            })
+            .collect();
+
+        Limiter {
+            channels,
+            ceiling: db_to_gain(TRUE_PEAK_CEILING),
+            phases,
+            history: vec![0.0; INTERPOLATOR_TAPS * channels].into(),
+            delay: VecDeque::with_capacity((lookahead + 1) * channels),
+            lookahead,
+            peaks: VecDeque::new(),
+            frame: 0,
+            gain: 1.0,
+            // Reach the needed gain within the lookahead
+            attack: 1.0 - 0.001f32.powf(1.0 / lookahead as f32),
+            release: 1.0 - (-1.0 / (LIMITER_RELEASE_SECS * sample_rate as f64)).exp() as f32
+        }
+    }
+
+    /// Limits the next chunk of a stream, returning the same amount of samples delayed by the lookahead
+    pub fn process(&mut self, input: &[f32]) -> Vec<f32> {
+        let mut output = Vec::with_capacity(input.len());
+
+        // The interpolated peaks lag behind the input by half of the interpolator
+        let window = (self.lookahead + INTERPOLATOR_TAPS / 2 + 1) as u64;
+
+        for frame in input.chunks_exact(self.channels) {
+            self.history.drain(..self.channels);
+            self.history.extend(frame);
+            self.delay.extend(frame);
+
+            let mut peak = frame.iter().fold(0f32, |max, s| max.max(s.abs()));
+
+            for ch in 0..self.channels {
+                for taps in self.phases.iter() {
+                    let value: f32 = taps
+                        .iter()
+                        .enumerate()
+                        .map(|(k, t)| t * self.history[k * self.channels + ch])
+                        .sum();
+
+                    peak = peak.max(value.abs());
+                }
+            }
+
+            while self.peaks.back().is_some_and(|&(_, p)| p <= peak) {
+                self.peaks.pop_back();
+            }
+            self.peaks.push_back((self.frame, peak));
+
+            while self.peaks.front().is_some_and(|&(i, _)| i + window <= self.frame) {
+                self.peaks.pop_front();
+            }
+
+            self.frame += 1;
+
+            if self.delay.len() <= self.lookahead * self.channels { continue; }
+
+            let max_peak = self.peaks.front().map_or(0.0, |&(_, p)| p);
+            let required = if max_peak > self.ceiling { self.ceiling / max_peak } else { 1.0 };
+
+            let coefficient = if required < self.gain { self.attack } else { self.release };
+            self.gain += (required - self.gain) * coefficient;
+
+            for sample in self.delay.drain(..self.channels) {
+                output.push((sample * self.gain).clamp(-self.ceiling, self.ceiling));
+            }
+        }
+
+        output
+    }
+
+    /// Returns the samples still held back by the lookahead of a finished stream
+    pub fn flush(&mut self) -> Vec<f32> {
+        let tail = vec![0.0; self.lookahead * self.channels];
+        self.process(&tail)
+    }
+}
@@ -1,19 +1,17 @@
-use musiq::{songs, generated};
+use musiq::{songs, generated, decoders};
 
 use musiq::or_return;
 
-/// The supported file extensions by the song playing function
-static SUPPORTED_EXTENSIONS: &[&str] = &["mp3"];
-
+/// Checks whether a song's extension belongs to one of the registered decoders
 fn has_allowed_extension(s: &songs::Song) -> bool {
-    SUPPORTED_EXTENSIONS.contains(
-        &or_return!(
+    decoders::is_supported_extension(
+        or_return!(
             or_return!(
                 s.filename().to_str(),
                 false
             ).rsplit_once('.'),
             false
-        ).1.to_lowercase().as_str()
+        ).1
     )
 }
 
@@ -0,0 +1,310 @@
+use std::sync::{Arc, Mutex, MutexGuard, TryLockError};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{sync_channel, SyncSender};
+use std::time::{Duration, Instant};
+
+use cpal::traits::StreamTrait;
+
+use crate::playback::PlaybackControl;
+use crate::ringbuffer::Consumer;
+use crate::sink::{build_device_stream, open_wav, OutputConfig, Sink, StreamRenderer, HEADLESS_SAMPLE_RATE};
+use crate::{logln, or_return, return_unless};
+use crate::Error;
+
+/// The time an output is kept open without any sources, so consecutive playbacks share it
+const OUTPUT_IDLE_TIME: Duration = Duration::from_secs(1);
+
+/// The time the output thread of a device waits between checks whether its output is idle
+const OUTPUT_POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// The time a playback waits after its last sample was handed to the output, so it can be heard
+const OUTPUT_DRAIN_TIME: Duration = Duration::from_millis(100);
+
+/// The length of the blocks sinks without a device render at a time
+const HEADLESS_BLOCK_TIME: Duration = Duration::from_millis(10);
+
+/// The mixers of the sinks played on, and the ones set up for playbacks about to start
+static MIXERS: Mutex<Vec<Mixer>> = Mutex::new(Vec::new());
+
+/// A stream played through a mixer, and the sender signalling when it has finished
+struct Source {
+    renderer: StreamRenderer,
+    finished: SyncSender<()>
+}
+
+struct Shared {
+    sink: Sink,
+    config: OutputConfig,
+    sources: Mutex<Vec<Source>>,
+    /// Whether the output is open, which is only changed while holding `sources`
+    running: AtomicBool,
+    /// Whether the device reported an error, after which its stream no longer plays
+    failed: AtomicBool,
+    /// Held while the output is open, so a reopened output waits until the closing one has let go of the sink
+    output: Mutex<()>
+}
+
+/// Sums the streams of the playbacks on a sink into a single output, which is opened
+/// when the first of them starts and closed when none of them have been playing for a while.
+/// Clones of it mix into the same output.
+#[derive(Clone)]
+pub(crate) struct Mixer {
+    shared: Arc<Shared>
+}
+
+impl Mixer {
+    /// Returns the mixer of the output a sink plays on.
+    /// If there is none yet, sets one up in the output format closest to the given source format.
+    pub(crate) fn for_sink(sink: &Sink, source_channels: u16, source_sample_rate: u32) -> Result<Mixer, Error> {
+        // Panic so that panics cascade over threads
+        let mut mixers = MIXERS.lock().expect("Mixers were poisoned");
+
+        // Forget the mixers that are neither playing nor about to, and the ones of failed devices,
+        // so the sinks opened after them play on the next available device
+        mixers.retain(|m| {
+            !m.shared.failed.load(Ordering::Acquire)
+                && (Arc::strong_count(&m.shared) > 1 || m.shared.running.load(Ordering::Acquire))
+        });
+
+        if let Some(mixer) = mixers.iter().find(|m| m.shared.sink.same_output(sink)) {
+            return Ok(mixer.clone());
+        }
+
+        let mixer = Mixer {
+            shared: Arc::new(Shared {
+                sink: sink.clone(),
+                config: sink.select_config(source_channels, source_sample_rate)?,
+                sources: Mutex::new(Vec::new()),
+                running: AtomicBool::new(false),
+                failed: AtomicBool::new(false),
+                output: Mutex::new(())
+            })
+        };
+        mixers.push(mixer.clone());
+
+        Ok(mixer)
+    }
+
+    /// Returns the format the sources of the mixer are written in
+    pub(crate) fn config(&self) -> &OutputConfig {
+        &self.shared.config
+    }
+
+    fn sources_lock(&self) -> MutexGuard<'_, Vec<Source>> {
+        // Panic so that panics cascade over threads
+        self.shared.sources.lock().expect("Mixer sources were poisoned")
+    }
+
+    /// Returns the sources, or `None` if another thread is holding them
+    fn try_sources_lock(&self) -> Option<MutexGuard<'_, Vec<Source>>> {
+        match self.shared.sources.try_lock() {
+            Ok(sources) => Some(sources),
+            Err(TryLockError::WouldBlock) => None,
+            // Panic so that panics cascade over threads
+            Err(TryLockError::Poisoned(_)) => panic!("Mixer sources were poisoned")
+        }
+    }
+
+    /// Mixes the samples of a stream into the output with the other sources, and reports the tracks
+    /// queued in `control` as they are reached.
+    /// Returns when the producer of the stream is finished, and every sample has been played,
+    /// or when the playback was stopped and has faded out.
+    pub(crate) fn play(&self, consumer: Consumer, control: &PlaybackControl, fade_out: Duration) -> Result<(), Error> {
+        let (sender, finished) = sync_channel(1);
+        let renderer = StreamRenderer::new(consumer, &self.shared.config, control, fade_out);
+
+        let start = {
+            let mut sources = self.sources_lock();
+            return_unless!(!self.shared.failed.load(Ordering::Acquire), Err(Error::StreamCannotBePlayed));
+            sources.push(Source { renderer, finished: sender });
+
+            !self.shared.running.swap(true, Ordering::AcqRel)
+        };
+
+        if start && let Err(e) = self.start_output() {
+            let mut sources = self.sources_lock();
+            sources.clear();
+            self.shared.running.store(false, Ordering::Release);
+
+            return Err(e);
+        }
+
+        // The sender is dropped without a signal if the output failed or its device was lost
+        or_return!(finished.recv().ok(), Err(Error::StreamCannotBePlayed));
+
+        // Let the output play the last handed over samples
+        std::thread::sleep(OUTPUT_DRAIN_TIME);
+
+        Ok(())
+    }
+
+    /// Opens the output on a new thread, and waits until it has started
+    fn start_output(&self) -> Result<(), Error> {
+        let (started_sender, started) = sync_channel(1);
+        let mixer = self.clone();
+
+        std::thread::spawn(move || mixer.run_output(started_sender));
+
+        started.recv().unwrap_or(Err(Error::StreamCannotBePlayed))
+    }
+
+    /// Plays the output until it is idle, and reports whether it could be opened through `started`
+    fn run_output(&self, started: SyncSender<Result<(), Error>>) {
+        // Panic so that panics cascade over threads
+        let _output = self.shared.output.lock().expect("Mixer output was poisoned");
+        let mut idle_since = None;
+
+        match (&self.shared.sink, &self.shared.config) {
+            (Sink::Device { device, .. }, OutputConfig::Device(config, pair)) => {
+                let mixer = self.clone();
+                let shared = self.shared.clone();
+                let mut scratch = Vec::new();
+                // The device would drop out while waiting for a playback joining or leaving the output,
+                // so the callback renders silence instead
+                let render_output = move |buf: &mut [f32]| match mixer.try_sources_lock() {
+                    Some(mut sources) => { render(&mut sources, buf, &mut scratch); },
+                    None => buf.fill(0.0)
+                };
+                let on_error = move |e| {
+                    logln!("Output device failed: {e}");
+                    shared.failed.store(true, Ordering::Release);
+                };
+
+                let stream = build_device_stream(device, config, *pair, render_output, on_error)
+                    .and_then(|stream| match stream.play() {
+                        Ok(()) => Ok(stream),
+                        Err(_) => Err(Error::StreamCannotBePlayed)
+                    });
+
+                let stream = match stream {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        let _ = started.send(Err(e));
+                        return;
+                    }
+                };
+                let _ = started.send(Ok(()));
+
+                while !self.stop_if_failed() && !self.stop_if_idle(&mut idle_since) {
+                    std::thread::sleep(OUTPUT_POLL_INTERVAL);
+                }
+
+                drop(stream);
+            },
+            (Sink::Wav(path), OutputConfig::Headless) => {
+                let mut writer = match open_wav(path) {
+                    Ok(writer) => writer,
+                    Err(e) => {
+                        let _ = started.send(Err(e));
+                        return;
+                    }
+                };
+                let _ = started.send(Ok(()));
+
+                // Only the blocks with sources are written, so the file holds no idle silence
+                let mut failed = false;
+                self.run_headless(&mut idle_since, |samples| {
+                    for &sample in samples {
+                        failed |= writer.write_sample(sample).is_err();
+                    }
+                });
+
+                if failed || writer.finalize().is_err() {
+                    logln!("Writing the output file failed");
+                }
+            },
+            (Sink::Null, OutputConfig::Headless) => {
+                let _ = started.send(Ok(()));
+
+                self.run_headless(&mut idle_since, |_| ());
+            },
+            _ => {
+                let _ = started.send(Err(Error::NoOutputDeviceConfigs));
+            }
+        }
+    }
+
+    /// Renders the output block by block at the pace of a device until it is idle,
+    /// and hands each block with sources to `write`
+    fn run_headless(&self, idle_since: &mut Option<Instant>, mut write: impl FnMut(&[f32])) {
+        let channels = self.shared.config.channels() as usize;
+        let block_frames = (HEADLESS_BLOCK_TIME.as_secs_f64() * HEADLESS_SAMPLE_RATE as f64) as usize;
+        let mut buf = vec![0.0; block_frames * channels];
+        let mut scratch = Vec::new();
+        let start = Instant::now();
+
+        for block in 1.. {
+            if render(&mut self.sources_lock(), &mut buf, &mut scratch) {
+                write(&buf);
+            }
+
+            if self.stop_if_idle(idle_since) { break; }
+
+            if let Some(wait) = (start + HEADLESS_BLOCK_TIME * block).checked_duration_since(Instant::now()) {
+                std::thread::sleep(wait);
+            }
+        }
+    }
+
+    /// Drops the sources once the device has failed, as it no longer renders them,
+    /// so their playbacks end with an error. Marks the output as closed, and returns whether it was closed.
+    fn stop_if_failed(&self) -> bool {
+        return_unless!(self.shared.failed.load(Ordering::Acquire), false);
+
+        // Dropping the senders of the sources ends their playbacks
+        let mut sources = self.sources_lock();
+        sources.clear();
+        self.shared.running.store(false, Ordering::Release);
+
+        true
+    }
+
+    /// Marks the output as closed once it has had no sources for `OUTPUT_IDLE_TIME`,
+    /// and returns whether it was closed
+    fn stop_if_idle(&self, idle_since: &mut Option<Instant>) -> bool {
+        let sources = self.sources_lock();
+
+        if !sources.is_empty() {
+            *idle_since = None;
+            return false;
+        }
+
+        let since = *idle_since.get_or_insert_with(Instant::now);
+        return_unless!(since.elapsed() >= OUTPUT_IDLE_TIME, false);
+
+        // Sources are only added while holding the lock, so none can join a closing output
+        self.shared.running.store(false, Ordering::Release);
+
+        true
+    }
+}
+
+/// Fills `buf` with the sum of the next samples of every source, and removes the finished ones.
+/// Returns whether there were any sources.
+fn render(sources: &mut Vec<Source>, buf: &mut [f32], scratch: &mut Vec<f32>) -> bool {
+    buf.fill(0.0);
+    scratch.resize(buf.len(), 0.0);
+
+    return_unless!(!sources.is_empty(), false);
+
+    sources.retain_mut(|source| {
+        let finished = source.renderer.render(scratch);
+
+        for (out, sample) in buf.iter_mut().zip(scratch.iter()) {
+            *out += sample;
+        }
+
+        if finished {
+            let _ = source.finished.try_send(());
+        }
+
+        !finished
+    });
+
+    // Each source is limited on its own, but their sum can still clip
+    for sample in buf.iter_mut() {
+        *sample = sample.clamp(-1.0, 1.0);
+    }
+
+    true
+}
@@ -0,0 +1,481 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use crate::loudness::DEFAULT_TARGET_LOUDNESS;
+use crate::songs::SONG_PLAYING_GATE;
+use crate::{or_return, return_unless};
+
+/// The settings a file is played with
+#[derive(Debug, Copy, Clone)]
+pub struct PlaybackSettings {
+    /// The loudness (in LUFS) the file is normalized to
+    pub target_loudness: f32,
+    /// The time over which the file fades in at its start
+    pub fade_in: Duration,
+    /// The time over which the playback fades out when it is stopped
+    pub fade_out: Duration,
+    /// Whether the files of a playlist follow each other directly,
+    /// instead of after a short pause with each of them fading in
+    pub gapless: bool,
+    /// The time over which the files of a playlist overlap, fading into each other
+    pub crossfade: Duration
+}
+
+impl PlaybackSettings {
+    /// Creates settings without fading or pauses for the given target loudness
+    pub fn new(target_loudness: f32) -> PlaybackSettings {
+        PlaybackSettings {
+            target_loudness,
+            fade_in: Duration::ZERO,
+            fade_out: Duration::ZERO,
+            gapless: true,
+            crossfade: Duration::ZERO
+        }
+    }
+}
+
+impl Default for PlaybackSettings {
+    fn default() -> Self {
+        Self::new(DEFAULT_TARGET_LOUDNESS)
+    }
+}
+
+/// The state of the track a playback is playing
+#[derive(Debug, Clone)]
+pub struct NowPlaying {
+    pub name: Box<str>,
+    /// The played time of the track in seconds
+    pub position: f64,
+    /// The length of the track in seconds if it is known
+    pub duration: Option<f64>,
+    pub paused: bool
+}
+
+#[derive(Debug)]
+struct Track {
+    name: Box<str>,
+    duration: Option<f64>,
+    /// The sample rate of the output, in which the played frames are counted
+    sample_rate: u32
+}
+
+/// A gain shared between threads, which is 1 by default
+#[derive(Debug)]
+struct AtomicGain(AtomicU32);
+
+impl Default for AtomicGain {
+    fn default() -> Self {
+        AtomicGain(AtomicU32::new(1.0f32.to_bits()))
+    }
+}
+
+impl AtomicGain {
+    fn load(&self) -> f32 {
+        f32::from_bits(self.0.load(Ordering::Relaxed))
+    }
+
+    fn store(&self, gain: f32) {
+        self.0.store(gain.to_bits(), Ordering::Relaxed);
+    }
+}
+
+/// The state shared between the clones of a `PlaybackControl`
+#[derive(Debug, Default)]
+struct Shared {
+    stopped: AtomicBool,
+    skipped: AtomicBool,
+    /// Whether the skipped track has faded out, so the output waits for the next one
+    skip_faded: AtomicBool,
+    paused: AtomicBool,
+    finished: AtomicBool,
+    /// The requested position in seconds
+    seek: Mutex<Option<f64>>,
+    /// The number of frames of the current track handed over to the output
+    played_frames: AtomicU64,
+    track: Mutex<Option<Track>>,
+    /// The tracks written to the output buffer after the current one, with the buffer positions they start at
+    queued: Mutex<VecDeque<(usize, Track)>>,
+    /// The gate held while playing, or `None` for `SONG_PLAYING_GATE`
+    gate: Option<Arc<Mutex<()>>>,
+    /// The gains the playback is lowered to by the playbacks playing over it
+    ducks: Mutex<Vec<f32>>,
+    /// The gain the playback is mixed into the output with
+    volume: AtomicGain
+}
+
+/// A handle for controlling a playback from other threads.
+/// Clones of it control the same playback.
+#[derive(Debug, Clone, Default)]
+pub struct PlaybackControl {
+    shared: Arc<Shared>
+}
+
+impl PlaybackControl {
+    pub fn new() -> PlaybackControl {
+        Self::default()
+    }
+
+    /// Creates a control whose playback waits for the ones holding the given gate instead of `SONG_PLAYING_GATE`
+    fn with_gate(gate: Option<Arc<Mutex<()>>>) -> PlaybackControl {
+        PlaybackControl { shared: Arc::new(Shared { gate, ..Shared::default() }) }
+    }
+
+    /// Requests the playback to fade out and stop.
+    /// Returns `false` if it was already requested before.
+    pub fn stop(&self) -> bool {
+        !self.shared.stopped.swap(true, Ordering::Relaxed)
+    }
+
+    /// Returns whether the playback was requested to stop
+    pub fn is_stopped(&self) -> bool {
+        self.shared.stopped.load(Ordering::Relaxed)
+    }
+
+    /// Requests the current track to fade out, so the playback continues with the next one.
+    /// Returns `false` if no track is playing.
+    pub fn skip(&self) -> bool {
+        return_unless!(self.track_lock().is_some(), false);
+
+        self.shared.skipped.store(true, Ordering::Relaxed);
+
+        true
+    }
+
+    /// Returns whether the current track was requested to be skipped
+    pub fn is_skipped(&self) -> bool {
+        self.shared.skipped.load(Ordering::Relaxed)
+    }
+
+    /// Pauses the playback, which keeps its output silent until it is resumed
+    pub fn pause(&self) {
+        self.shared.paused.store(true, Ordering::Relaxed);
+    }
+
+    pub fn resume(&self) {
+        self.shared.paused.store(false, Ordering::Relaxed);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.shared.paused.load(Ordering::Relaxed)
+    }
+
+    /// Requests the current track to continue from the given position in seconds
+    pub fn seek(&self, position: f64) {
+        *self.shared.seek.lock().expect("Seek request was poisoned") = Some(position.max(0.0));
+    }
+
+    /// Returns whether the playback has ended
+    pub fn is_finished(&self) -> bool {
+        self.shared.finished.load(Ordering::Acquire)
+    }
+
+    /// Returns the state of the current track if one is playing
+    pub fn now_playing(&self) -> Option<NowPlaying> {
+        let track = self.track_lock();
+        let track = track.as_ref()?;
+
+        Some(NowPlaying {
+            name: track.name.clone(),
+            position: self.shared.played_frames.load(Ordering::Relaxed) as f64 / track.sample_rate as f64,
+            duration: track.duration,
+            paused: self.is_paused()
+        })
+    }
+
+    fn track_lock(&self) -> MutexGuard<'_, Option<Track>> {
+        // Panic so that panics cascade over threads
+        self.shared.track.lock().expect("Track state was poisoned")
+    }
+
+    /// Waits until no other playback sharing the gate of this one is playing, and holds the gate
+    pub(crate) fn lock_output(&self) -> MutexGuard<'_, ()> {
+        let gate = self.shared.gate.as_deref().unwrap_or(&SONG_PLAYING_GATE);
+
+        // Panic so that panics cascade over threads
+        gate.lock().expect("Song playing guard was poisoned")
+    }
+
+    /// Sets the gain the playback is mixed into the output with, from 0 (silent) to 1 (full volume)
+    pub fn set_volume(&self, gain: f32) {
+        self.shared.volume.store(gain.clamp(0.0, 1.0));
+    }
+
+    pub fn volume(&self) -> f32 {
+        self.shared.volume.load()
+    }
+
+    /// Lowers the volume of the playback to the given gain until the returned guard is dropped.
+    /// While it is lowered to silence, the playback holds its position.
+    pub fn duck(&self, gain: f32) -> Duck {
+        let gain = gain.clamp(0.0, 1.0);
+        self.ducks_lock().push(gain);
+
+        Duck { control: self.clone(), gain }
+    }
+
+    fn ducks_lock(&self) -> MutexGuard<'_, Vec<f32>> {
+        self.shared.ducks.lock().expect("Ducking state was poisoned")
+    }
+
+    /// Returns the gain the playback is lowered to, or `None` if it cannot be read without blocking
+    pub(crate) fn duck_gain(&self) -> Option<f32> {
+        let ducks = self.shared.ducks.try_lock().ok()?;
+
+        Some(ducks.iter().copied().fold(1.0, f32::min))
+    }
+
+    fn queue_lock(&self) -> MutexGuard<'_, VecDeque<(usize, Track)>> {
+        self.shared.queued.lock().expect("Track queue was poisoned")
+    }
+
+    /// Marks the start of a track at the given position of the output buffer,
+    /// whose played frames are counted in the given sample rate
+    pub(crate) fn queue_track(&self, name: &str, duration: Option<f64>, sample_rate: u32, start: usize) {
+        self.queue_lock().push_back((start, Track { name: name.into(), duration, sample_rate }));
+    }
+
+    /// Returns whether a track was written to the output buffer, which is not playing yet
+    pub(crate) fn has_queued_track(&self) -> bool {
+        !self.queue_lock().is_empty()
+    }
+
+    /// Marks the end of the playback, and clears the tracks and the requests concerning them
+    pub(crate) fn end_track(&self) {
+        *self.track_lock() = None;
+        self.queue_lock().clear();
+        self.finish_skip();
+        self.take_seek();
+    }
+
+    /// Marks the skipped track as faded out
+    pub(crate) fn set_skip_faded(&self) {
+        self.shared.skip_faded.store(true, Ordering::Release);
+    }
+
+    /// Returns whether the skipped track has faded out
+    pub(crate) fn is_skip_faded(&self) -> bool {
+        self.shared.skip_faded.load(Ordering::Acquire)
+    }
+
+    /// Clears a skip request once the skipped track was removed from the output buffer
+    pub(crate) fn finish_skip(&self) {
+        self.queue_lock().clear();
+        self.shared.skipped.store(false, Ordering::Relaxed);
+        self.shared.skip_faded.store(false, Ordering::Release);
+    }
+
+    /// Returns and clears the requested position in seconds
+    pub(crate) fn take_seek(&self) -> Option<f64> {
+        self.shared.seek.lock().expect("Seek request was poisoned").take()
+    }
+
+    /// Returns whether a seek was requested
+    pub(crate) fn is_seeking(&self) -> bool {
+        self.shared.seek.lock().expect("Seek request was poisoned").is_some()
+    }
+
+    /// Counts frames as handed over to the output, and switches to the queued tracks
+    /// whose start the output has reached at the given position of the buffer
+    pub(crate) fn advance(&self, frames: u64, position: usize, channels: u16) {
+        self.shared.played_frames.fetch_add(frames, Ordering::Relaxed);
+
+        // Don't block the output, the tracks are switched on a later call instead
+        let mut queued = or_return!(self.shared.queued.try_lock().ok());
+        if queued.front().is_none_or(|&(start, _)| start > position) { return; }
+        let mut current = or_return!(self.shared.track.try_lock().ok());
+
+        while let Some(&(start, _)) = queued.front() && start <= position {
+            let Some((_, track)) = queued.pop_front() else { break };
+            let frames = (position - start) / channels.max(1) as usize;
+
+            self.shared.played_frames.store(frames as u64, Ordering::Relaxed);
+            *current = Some(track);
+        }
+    }
+
+    /// Sets the position of the current track in seconds
+    pub(crate) fn set_position(&self, position: f64) {
+        let track = self.track_lock();
+        let sample_rate = or_return!(track.as_ref()).sample_rate;
+
+        self.shared.played_frames.store((position * sample_rate as f64) as u64, Ordering::Relaxed);
+    }
+}
+
+/// Keeps a playback lowered, and restores its volume when dropped
+#[derive(Debug)]
+#[must_use = "The playback is restored when the guard is dropped"]
+pub struct Duck {
+    control: PlaybackControl,
+    gain: f32
+}
+
+impl Drop for Duck {
+    fn drop(&mut self) {
+        let mut ducks = self.control.ducks_lock();
+
+        if let Some(index) = ducks.iter().position(|gain| *gain == self.gain) {
+            ducks.swap_remove(index);
+        }
+    }
+}
+
+/// Marks a playback as finished when it is dropped, even if its thread panics
+struct FinishGuard(PlaybackControl);
+
+impl Drop for FinishGuard {
+    fn drop(&mut self) {
+        self.0.shared.finished.store(true, Ordering::Release);
+    }
+}
+
+/// Keeps track of the running playbacks, so they can be controlled together.
+/// Playbacks wait for each other, so at most one of them is playing a track at a time,
+/// unless they are started to play over the others.
+#[derive(Debug, Default)]
+pub struct Player {
+    playbacks: Vec<PlaybackControl>,
+    /// The gate the playbacks hold while playing, or `None` for `SONG_PLAYING_GATE`
+    gate: Option<Arc<Mutex<()>>>
+}
+
+impl Player {
+    /// Creates a player, whose playbacks also wait for the ones started outside of players
+    pub fn new() -> Player {
+        Self::default()
+    }
+
+    /// Creates a player, whose playbacks only wait for each other
+    pub fn independent() -> Player {
+        Player { playbacks: Vec::new(), gate: Some(Arc::default()) }
+    }
+
+    /// Runs a playback on a new thread, and returns its handle and control
+    pub fn spawn<T: Send + 'static>(
+        &mut self,
+        playback: impl FnOnce(&PlaybackControl) -> T + Send + 'static
+    ) -> (JoinHandle<T>, PlaybackControl) {
+        let gate = self.gate.clone();
+
+        self.spawn_with_gate(gate, playback)
+    }
+
+    /// Runs a playback on a new thread like `spawn`, which plays over the other playbacks
+    /// instead of waiting for them
+    pub fn spawn_over<T: Send + 'static>(
+        &mut self,
+        playback: impl FnOnce(&PlaybackControl) -> T + Send + 'static
+    ) -> (JoinHandle<T>, PlaybackControl) {
+        self.spawn_with_gate(Some(Arc::default()), playback)
+    }
+
+    fn spawn_with_gate<T: Send + 'static>(
+        &mut self,
+        gate: Option<Arc<Mutex<()>>>,
+        playback: impl FnOnce(&PlaybackControl) -> T + Send + 'static
+    ) -> (JoinHandle<T>, PlaybackControl) {
+        self.playbacks.retain(|p| !p.is_finished());
+
+        let control = PlaybackControl::with_gate(gate);
+        self.playbacks.push(control.clone());
+
+        let guard = FinishGuard(control.clone());
+        let handle = std::thread::spawn(move || playback(&guard.0));
+
+        (handle, control)
+    }
+
+    /// Returns the control of the playback currently playing a track
+    pub fn current(&self) -> Option<&PlaybackControl> {
+        self.playbacks
+            .iter()
+            .find(|p| !p.is_finished() && p.track_lock().is_some())
+    }
+
+    /// Stops every running playback, and returns whether there were any
+    pub fn stop(&self) -> bool {
+        let mut stopped = false;
+
+        for playback in self.playbacks.iter().filter(|p| !p.is_finished()) {
+            playback.stop();
+            stopped = true;
+        }
+
+        stopped
+    }
+
+    /// Returns the state of the track currently playing if there is one
+    pub fn now_playing(&self) -> Option<NowPlaying> {
+        self.current()?.now_playing()
+    }
+
+    /// Sets the volume of every running playback, and returns whether there were any
+    pub fn set_volume(&self, gain: f32) -> bool {
+        let mut set = false;
+
+        for playback in self.playbacks.iter().filter(|p| !p.is_finished()) {
+            playback.set_volume(gain);
+            set = true;
+        }
+
+        set
+    }
+
+    /// Lowers the volume of every running playback to the given gain until the returned guards are dropped
+    pub fn duck(&self, gain: f32) -> Vec<Duck> {
+        self.playbacks
+            .iter()
+            .filter(|p| !p.is_finished())
+            .map(|p| p.duck(gain))
+            .collect()
+    }
+}
+
+/// The player of the default output, and an independent player for each zone
+#[derive(Debug, Default)]
+pub struct ZonePlayers {
+    default: Player,
+    zones: Vec<(Box<str>, Player)>
+}
+
+impl ZonePlayers {
+    pub fn new() -> ZonePlayers {
+        Self::default()
+    }
+
+    /// Returns the player of a zone if it has played before, or the player of the default output for `None`
+    pub fn get(&self, zone: Option<&str>) -> Option<&Player> {
+        let Some(zone) = zone else { return Some(&self.default) };
+
+        self.zones.iter().find(|(name, _)| name.as_ref() == zone).map(|(_, player)| player)
+    }
+
+    /// Returns the player of a zone, or the player of the default output for `None`
+    pub fn get_mut(&mut self, zone: Option<&str>) -> &mut Player {
+        let Some(zone) = zone else { return &mut self.default };
+
+        let index = match self.zones.iter().position(|(name, _)| name.as_ref() == zone) {
+            Some(index) => index,
+            None => {
+                self.zones.push((zone.into(), Player::independent()));
+                self.zones.len() - 1
+            }
+        };
+
+        &mut self.zones[index].1
+    }
+
+    /// Stops every running playback of every zone, and returns whether there were any
+    pub fn stop_all(&self) -> bool {
+        let mut stopped = self.default.stop();
+
+        for (_, player) in &self.zones {
+            stopped |= player.stop();
+        }
+
+        stopped
+    }
+}
@@ -0,0 +1,142 @@
+use std::f64::consts::PI;
+
+/// The number of zero crossings of the sinc kernel on each side of its center
+const ZERO_CROSSINGS: usize = 16;
+
+/// The number of precomputed kernel values between two zero crossings
+const PHASES: usize = 256;
+
+/// The fraction of the lower Nyquist frequency kept by the anti-aliasing filter
+const PASSBAND: f64 = 0.97;
+
+/// Converts interleaved samples between sample rates with a windowed-sinc filter.\
+/// The state is kept between calls, so a stream can be resampled chunk by chunk.
+pub struct Resampler {
+    channels: usize,
+    /// The distance between two output samples measured in input samples
+    step: f64,
+    /// The number of input samples the kernel reaches on each side of its center
+    half_width: usize,
+    /// The precomputed kernel from its center to its edge
+    table: Box<[f32]>,
+    /// The number of table entries per input sample
+    table_density: f64,
+    /// Interleaved input samples, which are still needed for computing outputs
+    history: Vec<f32>,
+    /// The position of the next output sample in `history` measured in input samples
+    position: f64
+}
+
+impl Resampler {
+    /// Creates a resampler for the given number of interleaved channels, converting
+    /// from `source_rate` to `target_rate`.
+    pub fn new(source_rate: u32, target_rate: u32, channels: u16) -> Resampler {
+        let channels = channels.max(1) as usize;
+        let step = source_rate as f64 / target_rate as f64;
+
+        // When downsampling, the cutoff must be lowered to the target's Nyquist frequency
+        let cutoff = PASSBAND * (1.0 / step).min(1.0);
+        let half_width = (ZERO_CROSSINGS as f64 / cutoff).ceil() as usize;
+
+        let table_density = PHASES as f64 * cutoff;
+        let table_len = (half_width as f64 * table_density).ceil() as usize + 2;
+
+        let table = (0..table_len)
+            .map(|i| {
+                let x = i as f64 / table_density;
+                (cutoff * sinc(cutoff * x) * blackman_harris(x / half_width as f64)) as f32
+            })
+            .collect();
+
+        Resampler {
+            channels,
+            step,
+            half_width,
+            table,
+            table_density,
+            // The stream is preceded by silence, so the first outputs have enough history
+            history: vec![0.0; half_width * channels],
+            position: half_width as f64
+        }
+    }
+
+    /// Returns whether this resampler changes the sample rate at all
+    pub fn is_passthrough(&self) -> bool {
+        self.step == 1.0
+    }
+
+    /// Resamples the next chunk of a stream. Some of the input is kept back
+    /// until enough following samples arrive.
+    pub fn process(&mut self, input: &[f32]) -> Vec<f32> {
+        if self.is_passthrough() {
+            return input.to_vec();
+        }
+
+        self.history.extend_from_slice(input);
+
+        let frames = self.history.len() / self.channels;
+        let mut output = Vec::with_capacity(((input.len() / self.channels) as f64 / self.step) as usize * self.channels + self.channels);
+
+        while (self.position as usize) + self.half_width < frames {
+            let base = self.position as usize;
+            let frac = self.position - base as f64;
+
+            let start = output.len();
+            output.resize(start + self.channels, 0.0);
+
+            for k in (base + 1 - self.half_width)..=(base + self.half_width) {
+                let weight = self.kernel((k as f64 - base as f64 - frac).abs());
+                let frame = &self.history[k * self.channels..(k + 1) * self.channels];
+
+                for (out, sample) in output[start..].iter_mut().zip(frame) {
+                    *out += weight * sample;
+                }
+            }
+
+            self.position += self.step;
+        }
+
+        // Drop the samples that are no longer reached by the kernel
+        let consumed = (self.position as usize + 1).saturating_sub(self.half_width).min(frames);
+        self.history.drain(..consumed * self.channels);
+        self.position -= consumed as f64;
+
+        output
+    }
+
+    /// Resamples the remaining input of a finished stream
+    pub fn flush(&mut self) -> Vec<f32> {
+        if self.is_passthrough() {
+            return Vec::new();
+        }
+
+        let tail = vec![0.0; self.half_width * self.channels];
+        self.process(&tail)
+    }
+
+    /// Returns the kernel's value at the given distance from its center (in input samples)
+    fn kernel(&self, distance: f64) -> f32 {
+        let index = distance * self.table_density;
+        let i = index as usize;
+
+        if i + 1 >= self.table.len() {
+            return 0.0;
+        }
+
+        let frac = (index - i as f64) as f32;
+        self.table[i] + (self.table[i + 1] - self.table[i]) * frac
+    }
+}
+
+/// The normalized sinc function
+pub(crate) fn sinc(x: f64) -> f64 {
+    if x == 0.0 { 1.0 } else { (PI * x).sin() / (PI * x) }
+}
+
+/// The 4-term Blackman-Harris window centered at zero with a half width of one
+pub(crate) fn blackman_harris(t: f64) -> f64 {
+    if t.abs() >= 1.0 { return 0.0; }
+
+    let t = PI * t;
+    0.35875 + 0.48829 * t.cos() + 0.14128 * (2.0 * t).cos() + 0.01168 * (3.0 * t).cos()
+}
@@ -0,0 +1,168 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+use std::time::Duration;
+
+/// The time a blocked producer waits before retrying to push into a full buffer
+const PUSH_RETRY_INTERVAL: Duration = Duration::from_millis(5);
+
+/// The state shared between the two halves of a ring buffer
+struct Shared {
+    /// The samples stored as their bit representation, so they can be accessed atomically
+    data: Box<[AtomicU32]>,
+    /// The total number of samples ever read
+    read: AtomicUsize,
+    /// The total number of samples ever written
+    written: AtomicUsize,
+    /// The total number of written samples the consumer skips instead of reading
+    discarded: AtomicUsize,
+    /// Whether the producer has finished writing
+    closed: AtomicBool,
+    /// Whether the consumer has been dropped
+    abandoned: AtomicBool
+}
+
+/// Creates a single-producer single-consumer ring buffer of samples with the given capacity
+pub fn ring_buffer(capacity: usize) -> (Producer, Consumer) {
+    let shared = Arc::new(Shared {
+        data: (0..capacity.max(1)).map(|_| AtomicU32::new(0)).collect(),
+        read: AtomicUsize::new(0),
+        written: AtomicUsize::new(0),
+        discarded: AtomicUsize::new(0),
+        closed: AtomicBool::new(false),
+        abandoned: AtomicBool::new(false)
+    });
+
+    (Producer { shared: shared.clone() }, Consumer { shared })
+}
+
+/// The writing half of a ring buffer. The buffer is closed when this is dropped.
+pub struct Producer {
+    shared: Arc<Shared>
+}
+
+impl Producer {
+    /// Writes as many samples as there is free space for, and returns their number.
+    pub fn push_slice(&mut self, samples: &[f32]) -> usize {
+        let capacity = self.shared.data.len();
+        let written = self.shared.written.load(Ordering::Relaxed);
+        let read = self.shared.read.load(Ordering::Acquire);
+
+        let count = samples.len().min(capacity - (written - read));
+
+        for (i, sample) in samples[..count].iter().enumerate() {
+            self.shared.data[(written + i) % capacity].store(sample.to_bits(), Ordering::Relaxed);
+        }
+
+        self.shared.written.store(written + count, Ordering::Release);
+
+        count
+    }
+
+    /// Writes all samples, waiting for free space if necessary.
+    /// Returns `false` if the consumer was dropped before all of them could be written.
+    pub fn push_all(&mut self, samples: &[f32]) -> bool {
+        self.push_all_until(samples, || false)
+    }
+
+    /// Writes all samples, waiting for free space if necessary, unless `interrupted` returns `true`
+    /// while waiting, in which case the rest of them are dropped.
+    /// Returns `false` if the consumer was dropped before all of them could be written.
+    pub fn push_all_until(&mut self, mut samples: &[f32], interrupted: impl Fn() -> bool) -> bool {
+        while !samples.is_empty() {
+            if self.shared.abandoned.load(Ordering::Relaxed) {
+                return false;
+            }
+
+            if interrupted() {
+                return true;
+            }
+
+            let count = self.push_slice(samples);
+            samples = &samples[count..];
+
+            if count == 0 {
+                std::thread::sleep(PUSH_RETRY_INTERVAL);
+            }
+        }
+
+        true
+    }
+
+    /// Makes the consumer skip every sample written so far, which it has not read yet
+    pub fn discard(&mut self) {
+        self.shared.discarded.store(self.shared.written.load(Ordering::Relaxed), Ordering::Release);
+    }
+
+    /// Returns the total number of samples ever written
+    pub fn written(&self) -> usize {
+        self.shared.written.load(Ordering::Relaxed)
+    }
+
+    /// Returns whether the consumer was dropped
+    pub fn is_abandoned(&self) -> bool {
+        self.shared.abandoned.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for Producer {
+    fn drop(&mut self) {
+        self.shared.closed.store(true, Ordering::Release);
+    }
+}
+
+/// The reading half of a ring buffer
+pub struct Consumer {
+    shared: Arc<Shared>
+}
+
+impl Consumer {
+    /// Returns the total number of samples read or discarded
+    pub fn position(&self) -> usize {
+        self.shared.read.load(Ordering::Relaxed).max(self.shared.discarded.load(Ordering::Acquire))
+    }
+
+    /// Reads as many samples as available into `buf`, and returns their number.
+    pub fn pop_slice(&mut self, buf: &mut [f32]) -> usize {
+        let capacity = self.shared.data.len();
+        let read = self.position();
+        let written = self.shared.written.load(Ordering::Acquire);
+
+        let count = buf.len().min(written - read);
+
+        for (i, sample) in buf[..count].iter_mut().enumerate() {
+            *sample = f32::from_bits(self.shared.data[(read + i) % capacity].load(Ordering::Relaxed));
+        }
+
+        self.shared.read.store(read + count, Ordering::Release);
+
+        count
+    }
+
+    /// Returns the number of samples available for reading
+    pub fn len(&self) -> usize {
+        let read = self.position();
+
+        self.shared.written.load(Ordering::Acquire) - read
+    }
+
+    /// Returns whether there are no samples available for reading
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns whether the producer has finished writing
+    pub fn is_closed(&self) -> bool {
+        self.shared.closed.load(Ordering::Acquire)
+    }
+
+    /// Returns whether the producer has finished and every sample was read
+    pub fn is_finished(&self) -> bool {
+        self.is_closed() && self.is_empty()
+    }
+}
+
+impl Drop for Consumer {
+    fn drop(&mut self) {
+        self.shared.abandoned.store(true, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,538 @@
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use cpal::traits::{DeviceTrait, HostTrait};
+use cpal::{
+    Device, FromSample, Host, SampleFormat, SampleRate, SizedSample, Stream, StreamConfig, StreamError,
+    SupportedStreamConfig, SupportedStreamConfigRange
+};
+
+use crate::csv::CsvObject;
+use crate::playback::PlaybackControl;
+use crate::ringbuffer::Consumer;
+use crate::{logln, or_continue, or_return, return_unless};
+use crate::Error;
+
+/// The environment variable selecting the sink, which takes precedence over the config file
+pub const SINK_ENV_VAR: &str = "SINK";
+
+/// The sample formats output streams can be built with
+static SUPPORTED_SAMPLE_FORMATS: &[SampleFormat] = &[
+    SampleFormat::F32, SampleFormat::F64,
+    SampleFormat::I8, SampleFormat::I16, SampleFormat::I32,
+    SampleFormat::U8, SampleFormat::U16, SampleFormat::U32
+];
+
+/// The number of channels sinks without a device output
+const HEADLESS_CHANNELS: u16 = 2;
+
+/// The sample rate sinks without a device output
+pub(crate) const HEADLESS_SAMPLE_RATE: u32 = 48000;
+
+/// The time over which the volume of a stream moves to a new gain
+const VOLUME_RAMP_TIME: Duration = Duration::from_millis(500);
+
+/// The kind of sink playbacks write their audio to, as selected in the config file or `SINK_ENV_VAR`
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub enum SinkKind {
+    /// The default output device
+    #[default]
+    Device,
+    /// A WAV file, which every playback is appended to
+    Wav(PathBuf),
+    /// Nowhere, while still playing in real time
+    Null
+}
+
+impl SinkKind {
+    /// Parses a sink description: `device`, `null`, or `wav:` followed by the path of a file
+    pub fn from_spec(spec: &str) -> Option<SinkKind> {
+        match spec.trim() {
+            "device" => Some(SinkKind::Device),
+            "null" => Some(SinkKind::Null),
+            spec => {
+                let path = spec.strip_prefix("wav:")?;
+                return_unless!(!path.is_empty(), None);
+
+                Some(SinkKind::Wav(path.into()))
+            }
+        }
+    }
+
+    /// Returns the description this kind is parsed from
+    pub fn to_spec(&self) -> String {
+        match self {
+            SinkKind::Device => "device".to_string(),
+            SinkKind::Wav(path) => format!("wav:{}", path.display()),
+            SinkKind::Null => "null".to_string()
+        }
+    }
+
+    /// Returns the kind selected by `SINK_ENV_VAR` if it is set
+    pub fn from_env() -> Result<Option<SinkKind>, Error> {
+        let spec = or_return!(std::env::var(SINK_ENV_VAR).ok(), Ok(None));
+
+        Ok(Some(Self::from_spec(&spec).ok_or(Error::InvalidSink)?))
+    }
+
+    /// Opens a sink of this kind, which plays on the given device if it plays on one
+    pub fn open(&self, device: &DeviceSelection) -> Result<Sink, Error> {
+        Ok(match self {
+            SinkKind::Device => Sink::Device { device: device.open()?, channels: None },
+            SinkKind::Wav(path) => Sink::Wav(path.clone()),
+            SinkKind::Null => Sink::Null
+        })
+    }
+
+    /// Opens a sink of this kind for a zone, which plays on the device of the zone if it plays on one.
+    /// WAV files get the name of the zone appended to their stems, so zones write separate files.
+    pub fn open_zone(&self, zone: &Zone) -> Result<Sink, Error> {
+        Ok(match self {
+            SinkKind::Device => Sink::Device { device: zone.device.open()?, channels: zone.channels },
+            SinkKind::Wav(path) => {
+                let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+                let mut file_name = format!("{stem}-{}", zone.name);
+
+                if let Some(extension) = path.extension() {
+                    file_name = format!("{file_name}.{}", extension.to_string_lossy());
+                }
+
+                Sink::Wav(path.with_file_name(file_name))
+            },
+            SinkKind::Null => Sink::Null
+        })
+    }
+}
+
+/// A named output, whose playbacks are independent of the ones of other outputs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Zone {
+    pub name: Box<str>,
+    pub device: DeviceSelection,
+    /// The pair of device channels (counted from 0) the zone plays stereo audio on,
+    /// or `None` for every channel of the device
+    pub channels: Option<[u16; 2]>
+}
+
+/// An output device chosen by name, and the devices to fall back to if it is not available
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct DeviceSelection {
+    /// The name of the host (such as ALSA, JACK or PulseAudio), or `None` for the default one
+    pub host: Option<String>,
+    /// The names of the devices in order of preference, after which the default device is used
+    pub devices: Vec<String>
+}
+
+impl DeviceSelection {
+    /// Opens the first available device in order of preference
+    pub fn open(&self) -> Result<Device, Error> {
+        let host = match &self.host {
+            Some(name) => match find_host(name) {
+                Some(host) => host,
+                None => {
+                    logln!("Output host \"{name}\" is not available, falling back to the default one");
+                    cpal::default_host()
+                }
+            },
+            None => cpal::default_host()
+        };
+
+        for name in &self.devices {
+            let mut devices = or_continue!(host.output_devices().ok());
+
+            if let Some(device) = devices.find(|d| d.name().ok().as_deref() == Some(name)) {
+                return Ok(device);
+            }
+        }
+
+        if !self.devices.is_empty() {
+            logln!("None of the chosen output devices are available, falling back to the default one");
+        }
+
+        host.default_output_device().ok_or(Error::NoOutputDevice)
+    }
+}
+
+/// Returns the available host with the given name, ignoring the case
+fn find_host(name: &str) -> Option<Host> {
+    let id = cpal::available_hosts()
+        .into_iter()
+        .find(|id| id.name().eq_ignore_ascii_case(name))?;
+
+    cpal::host_from_id(id).ok()
+}
+
+/// Lists the output devices of every available host with their supported configs,
+/// with the rows `[host, device, is_default, channels, min_sample_rate, max_sample_rate, sample_format]`.
+/// Devices whose configs cannot be queried are listed with a single row without them.
+pub fn get_devices_csv() -> Vec<Vec<CsvObject>> {
+    let mut rows = Vec::new();
+
+    for id in cpal::available_hosts() {
+        let host = or_continue!(cpal::host_from_id(id).ok());
+        let default_name = host.default_output_device().and_then(|d| d.name().ok());
+        let devices = or_continue!(host.output_devices().ok());
+
+        for device in devices {
+            let name = or_continue!(device.name().ok());
+            let is_default = default_name.as_deref() == Some(name.as_str());
+            let device_row = || vec![
+                CsvObject::from(id.name()),
+                CsvObject::from(name.clone()),
+                CsvObject::from(is_default)
+            ];
+
+            let configs: Vec<_> = device
+                .supported_output_configs()
+                .map(|configs| configs.collect())
+                .unwrap_or_default();
+
+            if configs.is_empty() {
+                rows.push(device_row());
+            }
+
+            for config in configs {
+                let mut row = device_row();
+
+                row.extend([
+                    CsvObject::from(config.channels() as i64),
+                    CsvObject::from(config.min_sample_rate().0 as i64),
+                    CsvObject::from(config.max_sample_rate().0 as i64),
+                    CsvObject::from(config.sample_format().to_string())
+                ]);
+                rows.push(row);
+            }
+        }
+    }
+
+    rows
+}
+
+/// Where playbacks write their audio
+#[derive(Clone)]
+pub enum Sink {
+    /// An output device played through `cpal` on every channel, or on a pair of them
+    Device { device: Device, channels: Option<[u16; 2]> },
+    /// A WAV file, which every playback is appended to in 32-bit float stereo at 48 kHz
+    Wav(PathBuf),
+    /// Discards the audio, while still playing it in real time
+    Null
+}
+
+/// The format of the samples written to a sink
+#[derive(Clone)]
+pub(crate) enum OutputConfig {
+    /// The config of a device, and the pair of its channels played on if not all of them
+    Device(SupportedStreamConfig, Option<[u16; 2]>),
+    Headless
+}
+
+impl OutputConfig {
+    /// Returns the number of channels written to the sink
+    pub(crate) fn channels(&self) -> u16 {
+        match self {
+            OutputConfig::Device(_, Some(_)) => 2,
+            OutputConfig::Device(config, None) => config.channels(),
+            OutputConfig::Headless => HEADLESS_CHANNELS
+        }
+    }
+
+    pub(crate) fn sample_rate(&self) -> u32 {
+        match self {
+            OutputConfig::Device(config, _) => config.sample_rate().0,
+            OutputConfig::Headless => HEADLESS_SAMPLE_RATE
+        }
+    }
+}
+
+impl Sink {
+    /// Opens the default output device of the default host
+    pub fn default_device() -> Result<Sink, Error> {
+        SinkKind::Device.open(&DeviceSelection::default())
+    }
+
+    /// Selects the output format closest to a source's
+    pub(crate) fn select_config(&self, source_channels: u16, source_sample_rate: u32) -> Result<OutputConfig, Error> {
+        match self {
+            Sink::Device { device, channels: Some(pair) } => {
+                let required = pair[0].max(pair[1]) + 1;
+                let config = select_output_config(device, required, source_sample_rate)?;
+
+                return_unless!(config.channels() >= required, Err(Error::NoOutputDeviceConfigs));
+
+                Ok(OutputConfig::Device(config, Some(*pair)))
+            },
+            Sink::Device { device, channels: None } => Ok(OutputConfig::Device(
+                select_output_config(device, source_channels, source_sample_rate)?,
+                None
+            )),
+            Sink::Wav(_) | Sink::Null => Ok(OutputConfig::Headless)
+        }
+    }
+
+    /// Returns whether two sinks play on the same output, so their playbacks share a mixer
+    pub(crate) fn same_output(&self, other: &Sink) -> bool {
+        match (self, other) {
+            (Sink::Device { device, channels }, Sink::Device { device: other, channels: other_channels }) =>
+                channels == other_channels && device.name().ok().is_some_and(|name| other.name().ok() == Some(name)),
+            (Sink::Wav(path), Sink::Wav(other)) => path == other,
+            (Sink::Null, Sink::Null) => true,
+            _ => false
+        }
+    }
+}
+
+/// Selects the output config of a device, which has the closest number of channels
+/// (preferring more channels over less) and the closest sample rate to the source's,
+/// preferring floating-point sample formats.
+fn select_output_config(device: &Device, source_channels: u16, source_sample_rate: u32) -> Result<SupportedStreamConfig, Error> {
+    let closest_rate = |conf: &SupportedStreamConfigRange| {
+        source_sample_rate.clamp(conf.min_sample_rate().0, conf.max_sample_rate().0)
+    };
+
+    let config = or_return!(
+            or_return!(
+                device.supported_output_configs().ok(),
+                Err(Error::OutputDeviceConfigCannotBeQueried)
+            ).filter( |conf| SUPPORTED_SAMPLE_FORMATS.contains(&conf.sample_format()) )
+            .min_by_key( |conf| (
+                conf.channels() < source_channels,
+                conf.channels().abs_diff(source_channels),
+                closest_rate(conf).abs_diff(source_sample_rate),
+                conf.sample_format() != SampleFormat::F32
+            ) ),
+            Err(Error::NoOutputDeviceConfigs)
+        );
+
+    let sample_rate = closest_rate(&config);
+
+    Ok(config.with_sample_rate(SampleRate(sample_rate)))
+}
+
+/// Opens a WAV file for appending, or creates it if it does not exist yet
+pub(crate) fn open_wav(path: &Path) -> Result<hound::WavWriter<BufWriter<File>>, Error> {
+    let spec = hound::WavSpec {
+        channels: HEADLESS_CHANNELS,
+        sample_rate: HEADLESS_SAMPLE_RATE,
+        bits_per_sample: 32,
+        sample_format: hound::SampleFormat::Float
+    };
+
+    let writer = if path.exists() {
+        or_return!(hound::WavWriter::append(path).ok(), Err(Error::CannotOpenFile))
+    } else {
+        or_return!(hound::WavWriter::create(path, spec).ok(), Err(Error::CannotWriteFile))
+    };
+
+    // Samples of another format cannot be appended
+    return_unless!(writer.spec() == spec, Err(Error::UnsupportedAudioFormat));
+
+    Ok(writer)
+}
+
+/// Builds a paused output stream on a device, which plays the samples rendered by `render`
+/// on every channel of the device or on a pair of them, and reports its errors to `on_error`
+pub(crate) fn build_device_stream(
+    device: &Device,
+    config: &SupportedStreamConfig,
+    pair: Option<[u16; 2]>,
+    render: impl FnMut(&mut [f32]) + Send + 'static,
+    on_error: impl FnMut(StreamError) + Send + 'static
+) -> Result<Stream, Error> {
+    let sample_format = config.sample_format();
+    let config: StreamConfig = config.clone().into();
+
+    match sample_format {
+        SampleFormat::F32 => build_output_stream::<f32>(device, &config, pair, render, on_error),
+        SampleFormat::F64 => build_output_stream::<f64>(device, &config, pair, render, on_error),
+        SampleFormat::I8 => build_output_stream::<i8>(device, &config, pair, render, on_error),
+        SampleFormat::I16 => build_output_stream::<i16>(device, &config, pair, render, on_error),
+        SampleFormat::I32 => build_output_stream::<i32>(device, &config, pair, render, on_error),
+        SampleFormat::U8 => build_output_stream::<u8>(device, &config, pair, render, on_error),
+        SampleFormat::U16 => build_output_stream::<u16>(device, &config, pair, render, on_error),
+        SampleFormat::U32 => build_output_stream::<u32>(device, &config, pair, render, on_error),
+        _ => Err(Error::NoOutputDeviceConfigs)
+    }
+}
+
+/// Ramps the volume of a stream linearly to silence over a given time once its playback is stopped
+/// or skipped, or cuts it immediately if it is paused
+struct FadeOut {
+    control: PlaybackControl,
+    channels: usize,
+    /// The gain change per frame
+    step: f32,
+    gain: f32
+}
+
+impl FadeOut {
+    fn new(sample_rate: u32, channels: u16, duration: Duration, control: PlaybackControl) -> Self {
+        let step = (duration.as_secs_f32() * sample_rate as f32).max(1.0).recip();
+
+        Self { control, channels: channels as usize, step, gain: 1.0 }
+    }
+
+    /// Scales the samples with the current gain of the ramp if the playback was stopped or skipped,
+    /// and restores the full volume once the skipped track was replaced
+    fn apply(&mut self, samples: &mut [f32]) {
+        if !self.control.is_stopped() && !self.control.is_skipped() {
+            self.gain = 1.0;
+            return;
+        }
+
+        if self.control.is_paused() {
+            self.gain = 0.0;
+        }
+
+        for frame in samples.chunks_mut(self.channels) {
+            for sample in frame.iter_mut() {
+                *sample *= self.gain;
+            }
+            self.gain = (self.gain - self.step).max(0.0);
+        }
+    }
+
+    /// Returns whether the stream has faded out completely
+    fn is_silent(&self) -> bool {
+        self.gain <= 0.0
+    }
+}
+
+/// Ramps the volume of a stream toward the volume of its playback and the gain it is ducked to
+struct VolumeRamp {
+    control: PlaybackControl,
+    channels: usize,
+    /// The gain change per frame
+    step: f32,
+    duck_gain: f32,
+    gain: f32
+}
+
+impl VolumeRamp {
+    fn new(sample_rate: u32, channels: u16, control: PlaybackControl) -> Self {
+        let step = (VOLUME_RAMP_TIME.as_secs_f32() * sample_rate as f32).max(1.0).recip();
+
+        Self { control, channels: channels as usize, step, duck_gain: 1.0, gain: 1.0 }
+    }
+
+    /// Scales the samples with the gain moving toward the volume of the playback, lowered by ducking
+    fn apply(&mut self, samples: &mut [f32]) {
+        // Keep the previous gain if the control is busy
+        self.duck_gain = self.control.duck_gain().unwrap_or(self.duck_gain);
+        let target = self.duck_gain * self.control.volume();
+
+        if self.gain == 1.0 && target == 1.0 {
+            return;
+        }
+
+        for frame in samples.chunks_mut(self.channels) {
+            self.gain = match self.gain < target {
+                true => (self.gain + self.step).min(target),
+                false => (self.gain - self.step).max(target)
+            };
+
+            for sample in frame.iter_mut() {
+                *sample *= self.gain;
+            }
+        }
+    }
+
+    /// Returns whether the stream is ducked to silence and has faded out, so it should hold its position
+    fn is_holding(&self) -> bool {
+        self.duck_gain <= 0.0 && self.gain <= 0.0
+    }
+}
+
+/// Reads the samples of a stream for a sink unless it is paused, held by ducking or a skipped track
+/// has faded out, and fades them out as requested through its control
+pub(crate) struct StreamRenderer {
+    consumer: Consumer,
+    control: PlaybackControl,
+    fader: FadeOut,
+    volume: VolumeRamp,
+    channels: u16
+}
+
+impl StreamRenderer {
+    /// Creates a renderer of a stream written in the given config, which fades out over `fade_out`
+    /// when its playback is stopped
+    pub(crate) fn new(consumer: Consumer, config: &OutputConfig, control: &PlaybackControl, fade_out: Duration) -> Self {
+        StreamRenderer {
+            fader: FadeOut::new(config.sample_rate(), config.channels(), fade_out, control.clone()),
+            volume: VolumeRamp::new(config.sample_rate(), config.channels(), control.clone()),
+            consumer,
+            control: control.clone(),
+            channels: config.channels()
+        }
+    }
+
+    /// Fills `buf` with the next samples, and returns whether every sample was handed over,
+    /// or the stream has faded out for good
+    pub(crate) fn render(&mut self, buf: &mut [f32]) -> bool {
+        let control = &self.control;
+
+        // Reading nothing still skips the samples discarded by seeking or skipping
+        let count = if control.is_paused() || control.is_skip_faded() || self.volume.is_holding() {
+            self.consumer.pop_slice(&mut [])
+        } else {
+            self.consumer.pop_slice(buf)
+        };
+        buf[count..].fill(0.0);
+        self.fader.apply(buf);
+        self.volume.apply(buf);
+
+        // Hold the output until the decoder has replaced the skipped track
+        if control.is_skipped() && self.fader.is_silent() {
+            control.set_skip_faded();
+        }
+
+        control.advance((count / self.channels as usize) as u64, self.consumer.position(), self.channels);
+
+        self.consumer.is_finished()
+            || (self.fader.is_silent() && (control.is_stopped() || self.consumer.is_closed()))
+    }
+}
+
+/// Builds an output stream with a given sample type, which plays the samples rendered by `render`
+/// on every channel or on a pair of them, and reports its errors to `on_error`
+fn build_output_stream<T: SizedSample + FromSample<f32>>(
+    device: &Device,
+    config: &StreamConfig,
+    pair: Option<[u16; 2]>,
+    mut render: impl FnMut(&mut [f32]) + Send + 'static,
+    on_error: impl FnMut(StreamError) + Send + 'static
+) -> Result<Stream, Error> {
+    let mut buf: Vec<f32> = Vec::new();
+    let device_channels = config.channels as usize;
+    let channels = if pair.is_some() { 2 } else { device_channels };
+
+    Ok(or_return!(
+            device.build_output_stream(
+                config,
+                move |data: &mut [T], _| {
+                    let frames = data.len() / device_channels;
+                    buf.resize(frames * channels, 0.0);
+
+                    render(&mut buf);
+
+                    match pair {
+                        Some([left, right]) => {
+                            for (out, frame) in data.chunks_mut(device_channels).zip(buf.chunks(2)) {
+                                out.fill(T::EQUILIBRIUM);
+                                out[left as usize] = T::from_sample(frame[0]);
+                                out[right as usize] = T::from_sample(frame[1]);
+                            }
+                        },
+                        None => for (out, sample) in data.iter_mut().zip(&buf) {
+                            *out = T::from_sample(*sample);
+                        }
+                    }
+                },
+                on_error,
+                None,
+            ).ok(),
+            Err(Error::StreamCannotBeBuilt)
+        ))
+}
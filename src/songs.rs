@@ -1,134 +1,722 @@
 extern crate rand;
-extern crate minimp3;
-extern crate cpal;
 
+use std::collections::VecDeque;
+use std::f32::consts::FRAC_PI_2;
 use std::ffi::OsStr;
+use std::fs::File;
 use std::io::BufReader;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::Mutex;
+use std::time::Duration;
 
 use rand::{rng, seq::SliceRandom};
-use minimp3::{Decoder, Frame};
-use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
-use cpal::{SampleFormat, SampleRate, StreamConfig, Device};
 
+use crate::config::BreakStrategy;
 use crate::database::SongDatabase;
-use crate::{logln, or_return};
+use crate::decoders::{self, AudioDecoder, Frame};
+use crate::fingerprint::Fingerprint;
+use crate::ringbuffer::{ring_buffer, Consumer, Producer};
+use crate::mixer::Mixer;
+use crate::sink::Sink;
+use crate::resample::Resampler;
+use crate::channels::ChannelMixer;
+use crate::loudness::{db_to_gain, Limiter, LoudnessMeter};
+use crate::playback::{PlaybackControl, PlaybackSettings};
+use crate::tags::{self, Picture, SongTags};
+use crate::{logln, or_return, return_unless};
 use crate::Error;
-use crate::generated::TARGET_VOLUME;
 
-/// Block a thread while a song is playing with this Mutex
+/// Block a thread while a song is playing with this Mutex, so songs are not mixed together.
+/// Playbacks of independent players, such as the ones of zones, hold their own gates instead,
+/// and playbacks mixed over the others hold none.
 pub static SONG_PLAYING_GATE: Mutex<()> = Mutex::new(());
 
-/// An iterator that repeats each element a given number of times
-struct Repeater<T: Copy, I: Iterator<Item=T>> {
-    iter: I,
-    next_value: Option<T>,
-    repetitions: usize,
-    current: usize
+/// The duration of audio (in seconds) decoded before the playback starts,
+/// which is used to seed the loudness estimate
+const PREROLL_SECS: f64 = 3.0;
+
+/// The duration of audio (in seconds) the buffer between the decoder and the output can hold
+const BUFFER_SECS: f64 = 2.0;
+
+/// The duration of audio (in seconds) decoded into the buffer before the output starts
+const PREBUFFER_SECS: f64 = 0.5;
+
+/// The time the output waits between checks whether enough audio was decoded to start
+const PREBUFFER_POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+/// The highest gain the loudness normalization may apply (+20 dB)
+const MAX_GAIN: f32 = 10.0;
+
+/// The per-sample coefficient with which the applied gain approaches the estimated one
+const GAIN_SMOOTHING: f32 = 0.000_01;
+
+/// The pause (in seconds) between two songs of a playlist that are not joined gaplessly,
+/// which is also counted between them when filling breaks
+const SONG_GAP_SECS: f64 = 0.5;
+
+/// The time over which songs played from a cue point fade in and out
+const CUE_FADE: Duration = Duration::from_millis(500);
+
+/// Estimates the loudness of a stream from the samples seen so far unless it was measured before,
+/// and applies a smoothly changing gain to reach a target loudness.
+struct LoudnessEstimator {
+    meter: LoudnessMeter,
+    /// The loudness (in LUFS) of the whole stream if it is known
+    measured: Option<f32>,
+    target_loudness: f32,
+    gain: Option<f32>
 }
 
-impl<T: Copy, I: Iterator<Item=T>> Repeater<T, I> {
-    fn new(mut iter: I, repetitions: usize) -> Repeater<T, I> {
-        Self { next_value: iter.next(), iter, repetitions, current: 0 }
+impl LoudnessEstimator {
+    fn new(sample_rate: u32, channels: u16, target_loudness: f32, measured: Option<f32>) -> Self {
+        Self { meter: LoudnessMeter::new(sample_rate, channels), measured, target_loudness, gain: None }
+    }
+
+    /// Updates the estimate with new samples
+    fn feed(&mut self, samples: &[f32]) {
+        if self.measured.is_none() {
+            self.meter.feed(samples);
+        }
+    }
+
+    /// Returns the loudness (in LUFS) of the stream, or the estimate from the samples seen so far
+    fn loudness(&self) -> Option<f32> {
+        self.measured.or_else(|| self.meter.integrated())
+    }
+
+    /// Returns the gain required to reach the target loudness based on the current estimate
+    fn target_gain(&self) -> f32 {
+        match self.loudness() {
+            Some(loudness) => db_to_gain(self.target_loudness - loudness).min(MAX_GAIN),
+            None => 1.0
+        }
+    }
+
+    /// Scales the samples with the applied gain, while moving it towards the estimated one
+    fn apply(&mut self, samples: &mut [f32]) {
+        let target = self.target_gain();
+        let gain = self.gain.get_or_insert(target);
+
+        for sample in samples {
+            *gain += (target - *gain) * GAIN_SMOOTHING;
+            *sample *= *gain;
+        }
     }
 }
 
-impl<T: Copy, I: Iterator<Item=T>> Iterator for  Repeater<T, I> {
-    type Item = T;
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.current >= self.repetitions {
-            self.next_value = self.iter.next();
+/// Ramps the volume of a stream linearly from silence to full over a given time
+struct FadeIn {
+    channels: usize,
+    /// The gain change per frame
+    step: f32,
+    gain: f32
+}
+
+impl FadeIn {
+    fn new(sample_rate: u32, channels: u16, duration: Duration) -> Self {
+        let frames = duration.as_secs_f32() * sample_rate as f32;
+
+        if frames < 1.0 {
+            Self { channels: channels as usize, step: 1.0, gain: 1.0 }
+        } else {
+            Self { channels: channels as usize, step: frames.recip(), gain: 0.0 }
         }
+    }
+
+    /// Scales the samples with the current gain of the ramp
+    fn apply(&mut self, samples: &mut [f32]) {
+        if self.gain >= 1.0 { return; }
 
-        self.current += 1;
-        self.next_value
+        for frame in samples.chunks_mut(self.channels) {
+            for sample in frame.iter_mut() {
+                *sample *= self.gain;
+            }
+            self.gain = (self.gain + self.step).min(1.0);
+        }
     }
 }
 
-/// An iterator that only returns every nth element
-struct Skipper<T: Copy, I: Iterator<Item=T>> {
-    iter: I,
-    skips: usize
+/// The time the decoder waits between checks whether a skipped track has faded out
+const SKIP_POLL_INTERVAL: Duration = Duration::from_millis(5);
+
+/// A file played as a part of a playback
+struct TrackFile {
+    path: PathBuf,
+    /// The name the playback reports the file under
+    name: String,
+    /// The integrated loudness in LUFS if it was measured
+    loudness: Option<f32>,
+    /// The length in seconds if it was measured
+    duration: Option<f64>,
+    /// The part of the file played, or all of it if `None`
+    trim: Option<Trim>,
+    /// The time the part fades in and out over at its cut ends
+    trim_fade: Duration
+}
+
+/// Decodes a file, and converts its audio to the channels and the sample rate of the output,
+/// while normalizing its loudness and fading it in
+struct TrackReader {
+    path: PathBuf,
+    decoder: Box<dyn AudioDecoder>,
+    duration_secs: Option<f64>,
+    source_sample_rate: u32,
+    output_sample_rate: u32,
+    /// The number of output channels
+    channels: u16,
+    channel_mixer: ChannelMixer,
+    resampler: Resampler,
+    loudness: LoudnessEstimator,
+    fade_in: FadeIn,
+    /// The number of frames decoded from the start of the file
+    position: usize,
+    /// The position in seconds the file starts from when rewound
+    start_secs: f64,
+    /// The number of frames from the start of the file after which it ends, if it is trimmed
+    end: Option<usize>,
+    /// The number of frames before the end over which the file fades out
+    fade_out: usize,
+    /// Decoded samples, which are processed before decoding more
+    pending: Option<Vec<f32>>,
+    /// Whether the decoder has reached the end of the file
+    finished: bool
 }
 
-impl<T: Copy, I: Iterator<Item=T>> Skipper<T, I> {
-    fn new(iter: I, skips: usize) -> Skipper<T, I> {
-        Self { iter, skips }
+impl TrackReader {
+    /// Opens a file, and decodes its start to estimate its loudness
+    fn open(
+        path: PathBuf,
+        loudness: Option<f32>,
+        target_loudness: f32,
+        fade_in: Duration,
+        output: (u16, u32)
+    ) -> Result<Self, Error> {
+        let (decoder, first_frame) = open_decoder(&path)?;
+
+        Self::new(path, decoder, first_frame, loudness, target_loudness, fade_in, output)
+    }
+
+    /// Creates a reader from an opened decoder and its first frame,
+    /// which outputs the given number of channels with the given sample rate
+    fn new(
+        path: PathBuf,
+        mut decoder: Box<dyn AudioDecoder>,
+        first_frame: Frame,
+        loudness: Option<f32>,
+        target_loudness: f32,
+        fade_in: Duration,
+        (channels, output_sample_rate): (u16, u32)
+    ) -> Result<Self, Error> {
+        let source_sample_rate = first_frame.sample_rate;
+        let mut channel_mixer = ChannelMixer::new(first_frame.channels, channels);
+        let mut preroll = channel_mixer.process(&first_frame.samples, first_frame.channels);
+
+        // Decode the start of the file to estimate its loudness
+        while (preroll.len() as f64) < PREROLL_SECS * (source_sample_rate as usize * channels as usize) as f64 {
+            let Some(frame) = decoder.next_frame() else { break };
+            let Frame { samples, channels: source_channels, .. } = frame?;
+
+            preroll.extend(channel_mixer.process(&samples, source_channels));
+        }
+
+        let mut loudness = LoudnessEstimator::new(source_sample_rate, channels, target_loudness, loudness);
+        loudness.feed(&preroll);
+
+        Ok(Self {
+            path,
+            duration_secs: decoder.duration(),
+            decoder,
+            source_sample_rate,
+            output_sample_rate,
+            channels,
+            channel_mixer,
+            resampler: Resampler::new(source_sample_rate, output_sample_rate, channels),
+            loudness,
+            fade_in: FadeIn::new(source_sample_rate, channels, fade_in),
+            position: preroll.len() / channels as usize,
+            start_secs: 0.0,
+            end: None,
+            fade_out: 0,
+            pending: Some(preroll),
+            finished: false
+        })
     }
+
+    /// Decodes the next frame, and returns its samples mixed to the output channels
+    fn decode(&mut self) -> Option<Result<Vec<f32>, Error>> {
+        let Frame { samples, channels, .. } = match self.decoder.next_frame()? {
+            Ok(frame) => frame,
+            Err(e) => return Some(Err(e))
+        };
+        let samples = self.channel_mixer.process(&samples, channels);
+
+        self.position += samples.len() / self.channels as usize;
+
+        Some(Ok(samples))
+    }
+
+    /// Returns the next processed samples, or `None` after the end of the file
+    fn next_chunk(&mut self) -> Option<Result<Vec<f32>, Error>> {
+        return_unless!(!self.finished, None);
+
+        let mut samples = match self.pending.take() {
+            Some(samples) => samples,
+            None => match self.decode() {
+                Some(Ok(samples)) => {
+                    self.loudness.feed(&samples);
+                    samples
+                },
+                Some(Err(e)) => return Some(Err(e)),
+                None => {
+                    self.finished = true;
+                    return Some(Ok(self.resampler.flush()));
+                }
+            }
+        };
+
+        // The chunk ends at the position, as it is the last one decoded
+        if let Some(end) = self.end {
+            let channels = self.channels as usize;
+            let start = self.position - samples.len() / channels;
+
+            if start >= end {
+                self.finished = true;
+                return Some(Ok(self.resampler.flush()));
+            }
+
+            samples.truncate((end - start).min(samples.len() / channels) * channels);
+
+            for (frame, samples) in (start..).zip(samples.chunks_mut(channels)) {
+                let left = end - frame;
+                if left >= self.fade_out { continue; }
+
+                for sample in samples {
+                    *sample *= left as f32 / self.fade_out as f32;
+                }
+            }
+        }
+
+        self.loudness.apply(&mut samples);
+        self.fade_in.apply(&mut samples);
+
+        Some(Ok(self.resampler.process(&samples)))
+    }
+
+    /// Continues decoding from the given position in seconds, and returns the position reached
+    fn seek(&mut self, target_secs: f64) -> Result<f64, Error> {
+        let channels = self.channels as usize;
+        let target = (target_secs * self.source_sample_rate as f64) as usize;
+
+        // Decoders can only move forward, so start over to seek backwards
+        if target < self.position {
+            self.decoder = decoders::open(&self.path)?;
+            self.position = 0;
+            self.finished = false;
+        }
+
+        self.pending = None;
+
+        while self.position < target {
+            let position = self.position;
+            let Some(samples) = self.decode() else { break };
+            let mut samples = samples?;
+
+            // Keep the part of the chunk from the target, which still ends at the position
+            if self.position > target {
+                samples.drain(..(target - position) * channels);
+                self.loudness.feed(&samples);
+                self.pending = Some(samples);
+            }
+        }
+
+        self.resampler = Resampler::new(self.source_sample_rate, self.output_sample_rate, self.channels);
+
+        Ok(self.position.min(target) as f64 / self.source_sample_rate as f64)
+    }
+
+    /// Plays only the part of the file set by a trim from now on, fading it in and out over `fade`
+    fn trim(&mut self, trim: Trim, fade: Duration) -> Result<(), Error> {
+        self.start_secs = trim.start;
+        self.end = Some((trim.end * self.source_sample_rate as f64) as usize);
+        self.fade_out = (fade.as_secs_f64() * self.source_sample_rate as f64) as usize;
+
+        if trim.start > 0.0 {
+            self.seek(trim.start)?;
+        }
+
+        // Keep the fade-in if it is longer
+        let fade_in = FadeIn::new(self.source_sample_rate, self.channels, fade);
+        if fade_in.step < self.fade_in.step {
+            self.fade_in = fade_in;
+        }
+
+        Ok(())
+    }
+
+    /// Starts the file over, fading it in over the given time
+    fn rewind(&mut self, fade_in: Duration) -> Result<(), Error> {
+        self.seek(self.start_secs)?;
+        self.fade_in = FadeIn::new(self.source_sample_rate, self.channels, fade_in);
+
+        Ok(())
+    }
+}
+
+/// Opens a file with the registered decoders, and decodes its first frame
+fn open_decoder(file_path: &Path) -> Result<(Box<dyn AudioDecoder>, Frame), Error> {
+    let mut decoder = decoders::open(file_path)?;
+    let first_frame = or_return!(decoder.next_frame(), Err(Error::CannotDecodeAudio))?;
+
+    Ok((decoder, first_frame))
+}
+
+/// The end of the processing chain shared by the files of a playback, which limits their true peaks,
+/// and holds back the end of each file to mix it with the start of the next one
+struct TrackOutput<'a> {
+    producer: Producer,
+    limiter: Limiter,
+    control: &'a PlaybackControl,
+    sample_rate: u32,
+    channels: u16,
+    /// The latest samples, which are held back for a crossfade
+    tail: VecDeque<f32>,
+    /// The number of samples a crossfade spans
+    crossfade_len: usize
 }
 
-impl<T: Copy, I: Iterator<Item=T>> Iterator for Skipper<T, I> {
-    type Item = T;
-    fn next(&mut self) -> Option<Self::Item> {
-        self.iter.nth(self.skips)
+impl<'a> TrackOutput<'a> {
+    fn new(producer: Producer, control: &'a PlaybackControl, sample_rate: u32, channels: u16, crossfade: Duration) -> Self {
+        Self {
+            producer,
+            limiter: Limiter::new(sample_rate, channels),
+            control,
+            sample_rate,
+            channels,
+            tail: VecDeque::new(),
+            crossfade_len: (crossfade.as_secs_f64() * sample_rate as f64) as usize * channels as usize
+        }
+    }
+
+    /// Limits samples and writes them into the output buffer, unless a seek or a skip is requested.
+    /// Returns `false` if the output was closed.
+    fn push(&mut self, samples: &[f32]) -> bool {
+        let control = self.control;
+
+        self.producer.push_all_until(&self.limiter.process(samples), || control.is_seeking() || control.is_skipped())
+    }
+
+    /// Writes samples except for the ones held back for a crossfade.
+    /// Returns `false` if the output was closed.
+    fn write(&mut self, samples: &[f32]) -> bool {
+        self.tail.extend(samples);
+
+        let excess = self.tail.len().saturating_sub(self.crossfade_len);
+        return_unless!(excess > 0, true);
+
+        let samples: Vec<f32> = self.tail.drain(..excess).collect();
+
+        self.push(&samples)
+    }
+
+    /// Writes silence of the given length in seconds
+    fn write_silence(&mut self, secs: f64) -> bool {
+        self.write(&vec![0.0; (secs * self.sample_rate as f64) as usize * self.channels as usize])
+    }
+
+    /// Returns the start of a file mixed with the held back end of the previous one using equal-power ramps
+    fn crossfade(&mut self, start: &[f32]) -> Vec<f32> {
+        let channels = self.channels as usize;
+        let frames = (self.tail.len() / channels).max(1) as f32;
+
+        let mut mixed: Vec<f32> = self.tail
+            .drain(..)
+            .enumerate()
+            .map(|(i, end)| {
+                let t = (i / channels) as f32 / frames * FRAC_PI_2;
+
+                end * t.cos() + start.get(i).copied().unwrap_or(0.0) * t.sin()
+            })
+            .collect();
+
+        mixed.extend(start.get(mixed.len()..).unwrap_or_default());
+
+        mixed
+    }
+
+    /// Drops every sample not played yet, so the output continues with the ones written next
+    fn discard(&mut self) {
+        self.producer.discard();
+        self.limiter = Limiter::new(self.sample_rate, self.channels);
+        self.tail.clear();
+    }
+
+    /// Writes the held back samples, and the ones remaining in the limiter
+    fn flush(mut self) {
+        let tail: Vec<f32> = self.tail.drain(..).collect();
+
+        if self.push(&tail) {
+            let control = self.control;
+            let rest = self.limiter.flush();
+
+            self.producer.push_all_until(&rest, || control.is_skipped());
+        }
     }
 }
 
-/// Plays an MP3 file
+/// Plays files one after another through a single output stream, which is set up for the first of them.
+/// Each file is joined to the previous one as set in `settings`: crossfaded, directly,
+/// or after a short pause with a fade-in. While a file is playing, `control` reports it under its name.
 /// # Usage
-/// `before_play` is called with `rms`, `scale_factor`, `duration_secs`
-pub fn play_mp3(
-    file_path: impl AsRef<Path>,
-    device: &Device,
-    before_play: impl Fn(f32, f32, f64)
+/// `before_play` is called with the name of each file when its decoding starts, its (estimated) `loudness`
+/// in LUFS, the initial `scale_factor`, and `duration_secs` if its length is known
+fn play_tracks(
+    tracks: &[TrackFile],
+    sink: &Sink,
+    settings: &PlaybackSettings,
+    control: &PlaybackControl,
+    before_play: impl Fn(&str, f32, f32, Option<f64>) + Sync
 ) -> Result<(), Error> {
-    let file = or_return!(std::fs::File::open(&file_path).ok(), Err(Error::CannotReadFile));
-    let mut decoder = Decoder::new(BufReader::new(file));
-    let mut samples: Vec<f32> = Vec::new();
-
-    const OUTPUT_CHANNELS: usize = 2;
-
-    let mut source_sample_rate = 44100;
-    let mut source_channels = 2;
-
-    fn adapt_to_channels(iter: impl Iterator<Item=f32> + Sized + 'static, source_channels: usize, target_channels: usize ) -> Option<Box<dyn Iterator<Item=f32>>> {
-        if source_channels > target_channels {
-            if source_channels % 2 == 0 {
-                let skips = source_channels / target_channels - 1;
-                Some(Box::new(Skipper::new(iter, skips)))
-            } else {
-                None
+    let first = or_return!(tracks.first(), Ok(()));
+    let (decoder, first_frame) = open_decoder(&first.path)?;
+
+    let mixer = Mixer::for_sink(sink, first_frame.channels, first_frame.sample_rate)?;
+    let output_channels = mixer.config().channels();
+    let output_sample_rate = mixer.config().sample_rate();
+
+    let mut reader = TrackReader::new(
+        first.path.clone(),
+        decoder,
+        first_frame,
+        first.loudness,
+        settings.target_loudness,
+        settings.fade_in,
+        (output_channels, output_sample_rate)
+    )?;
+
+    if let Some(trim) = first.trim {
+        reader.trim(trim, first.trim_fade)?;
+    }
+
+    let (producer, consumer) = ring_buffer(
+        (BUFFER_SECS * (output_sample_rate as usize * output_channels as usize) as f64) as usize
+    );
+    let output = TrackOutput::new(producer, control, output_sample_rate, output_channels, settings.crossfade);
+
+    std::thread::scope(|scope| {
+        let feeder = scope.spawn(|| feed_tracks(tracks, reader, output, settings, &before_play));
+
+        let played = play_stream(consumer, &mixer, control, settings.fade_out);
+
+        // Panic so that panics cascade over threads
+        let decoded = feeder.join().expect("Decoder thread panicked");
+
+        played.and(decoded)
+    })
+}
+
+/// Decodes files one after another into the output, joining them as set in `settings`,
+/// and handles the seek and skip requests of the playback
+fn feed_tracks(
+    tracks: &[TrackFile],
+    mut reader: TrackReader,
+    mut output: TrackOutput<'_>,
+    settings: &PlaybackSettings,
+    before_play: &impl Fn(&str, f32, f32, Option<f64>)
+) -> Result<(), Error> {
+    let control = output.control;
+    let format = (output.channels, output.sample_rate);
+    let joined = settings.gapless || output.crossfade_len > 0;
+
+    // Whether the previous file was skipped, so the next one starts on its own
+    let mut skipped = false;
+
+    for (index, track) in tracks.iter().enumerate() {
+        if index > 0 {
+            return_unless!(!control.is_stopped(), Ok(()));
+
+            let fade_in = if joined && !skipped { Duration::ZERO } else { settings.fade_in };
+            reader = TrackReader::open(track.path.clone(), track.loudness, settings.target_loudness, fade_in, format)?;
+
+            if let Some(trim) = track.trim {
+                reader.trim(trim, track.trim_fade)?;
+            }
+
+            if !joined && !skipped {
+                return_unless!(output.write_silence(SONG_GAP_SECS), Ok(()));
             }
-        } else if source_channels == target_channels {
-            Some(Box::new(iter))
-        } else {
-            assert_eq!(source_channels, 1);
-            Some(Box::new(Repeater::new(iter, 2)))
         }
-    }
 
-    while let Ok(Frame { data, sample_rate: sr, channels: ch, .. }) = decoder.next_frame() {
-        samples.extend(adapt_to_channels(data.into_iter().map(|s| {
-            s as f32 / i16::MAX as f32
-        }), ch, OUTPUT_CHANNELS).ok_or(Error::SourceChannelsNotMultipleOfTwo)?);
-        source_sample_rate = sr;
-        source_channels = ch;
-    }
+        // Most decoders cannot tell the length without decoding the whole file
+        let duration_secs = track.duration.or(reader.duration_secs);
+
+        before_play(
+            &track.name,
+            reader.loudness.loudness().unwrap_or(f32::NEG_INFINITY),
+            reader.loudness.target_gain(),
+            duration_secs
+        );
+
+        control.queue_track(&track.name, duration_secs, output.sample_rate, output.producer.written());
 
-    let (rms, scale_factor) = normalize_samples(samples.as_mut_slice(), TARGET_VOLUME);
+        let mut starting = index > 0 && !skipped;
+        skipped = false;
 
-    let duration_secs = samples.len() as f64 / (source_sample_rate as f64 * OUTPUT_CHANNELS as f64);
+        loop {
+            // Only the file the output has reached can be seeked in, so the request waits until it does
+            if !control.has_queued_track() && let Some(target_secs) = control.take_seek() {
+                let position = reader.seek(target_secs)?;
 
-    // logln!("Playing \"{}\" ({:.1} seconds, RMS = {rms}, α={scale_factor})", self.filename.display(), duration_secs);
-    before_play(rms, scale_factor, duration_secs);
+                output.discard();
+                control.set_position(position);
+                starting = false;
+            }
 
-    play_samples(samples.into_boxed_slice(), source_sample_rate as u32, OUTPUT_CHANNELS as u16, device)?;
+            if control.is_skipped() {
+                // Wait until the output has faded out the skipped file
+                while !control.is_skip_faded() && !control.is_stopped() && !output.producer.is_abandoned() {
+                    std::thread::sleep(SKIP_POLL_INTERVAL);
+                }
 
-    // logln!("Finished");
+                return_unless!(!control.is_stopped() && !output.producer.is_abandoned(), Ok(()));
+
+                // If the output has not reached this file yet, an earlier one was skipped
+                let restart = control.has_queued_track();
+
+                output.discard();
+                control.finish_skip();
+
+                if restart {
+                    reader.rewind(settings.fade_in)?;
+                    control.queue_track(&track.name, duration_secs, output.sample_rate, output.producer.written());
+                    starting = false;
+                    continue;
+                }
+
+                skipped = true;
+                break;
+            }
+
+            let Some(samples) = reader.next_chunk() else { break };
+            let mut samples = samples?;
+
+            if starting {
+                // Decode as much of the start as the end of the previous file it is mixed with
+                while samples.len() < output.tail.len() {
+                    let Some(more) = reader.next_chunk() else { break };
+                    samples.extend(more?);
+                }
+
+                samples = output.crossfade(&samples);
+                starting = false;
+            }
+
+            return_unless!(output.write(&samples), Ok(()));
+        }
+    }
+
+    output.flush();
 
     Ok(())
 }
 
-#[derive(Debug, Eq, Clone)]
+/// Plays an MP3 file
+/// # Usage
+/// `before_play` is called with `loudness`, `scale_factor`, `duration_secs`
+#[inline]
+#[deprecated(since = "0.6.0", note = "use `play_file` instead, which supports every registered format")]
+pub fn play_mp3(
+    file_path: impl AsRef<Path>,
+    sink: &Sink,
+    before_play: impl Fn(f32, f32, Option<f64>) + Sync
+) -> Result<(), Error> {
+    let name = file_path.as_ref().file_name().unwrap_or_default().to_string_lossy().into_owned();
+
+    play_file(&file_path, &name, sink, None, &PlaybackSettings::default(), &PlaybackControl::new(), before_play)
+}
+
+/// Plays an audio file in any format supported by the registered decoders.
+/// The file is decoded while it is playing, so only a few seconds of it are held in memory.
+/// Its loudness is normalized to `target_loudness` with the given `loudness` (both in LUFS),
+/// or if it is not known, a running estimate. Its true peaks are limited afterwards.
+/// The file fades in and, when stopped or skipped through `control`, fades out as set in `settings`.
+/// While it is playing, `control` reports it under `name`, and can pause it or seek in it.
+/// # Usage
+/// `before_play` is called with the (estimated) `loudness` in LUFS, the initial `scale_factor`,
+/// and `duration_secs` if the length of the file is known
+pub fn play_file(
+    file_path: impl AsRef<Path>,
+    name: &str,
+    sink: &Sink,
+    loudness: Option<f32>,
+    settings: &PlaybackSettings,
+    control: &PlaybackControl,
+    before_play: impl Fn(f32, f32, Option<f64>) + Sync
+) -> Result<(), Error> {
+    let track = TrackFile {
+        path: file_path.as_ref().to_path_buf(),
+        name: name.to_string(),
+        loudness,
+        duration: None,
+        trim: None,
+        trim_fade: Duration::ZERO
+    };
+
+    play_tracks(
+        &[track],
+        sink,
+        settings,
+        control,
+        |_, loudness, scale_factor, duration_secs| before_play(loudness, scale_factor, duration_secs)
+    )
+}
+
+/// A part of a song, which is played instead of all of it,
+/// such as the one without its leading and trailing silence, or an excerpt between cue points
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Trim {
+    /// The position in seconds the song starts from
+    pub start: f64,
+    /// The position in seconds the song ends at
+    pub end: f64
+}
+
+impl Trim {
+    /// Returns whether the trim selects a part of a song with the given length in seconds, if it is known
+    pub fn is_valid(&self, duration: Option<f64>) -> bool {
+        self.start >= 0.0 && self.start < self.end && duration.is_none_or(|d| self.end <= d)
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct Song {
     filename: Box<OsStr>,
     metadata: u8,
+    /// The integrated loudness in LUFS if it was measured
+    loudness: Option<f32>,
+    /// The length in seconds if it was measured
+    duration: Option<f64>,
+    /// The title and the artist if the tags were read
+    tags: Option<SongTags>,
+    /// The part without silence if it was detected or set
+    trim: Option<Trim>,
+    /// The excerpt between cue points, which is played instead of the trimmed part if it is set
+    cue: Option<Trim>,
+    /// The acoustic fingerprint if it was computed
+    fingerprint: Option<Fingerprint>
 }
 
 impl Song {
     pub fn new(filename: &Path) -> Option<Self> {
-        Some(Song { filename: filename.file_name()?.into(), metadata: Self::compose_metadata(false, false) })
+        Some(Song {
+            filename: filename.file_name()?.into(),
+            metadata: Self::compose_metadata(false, false),
+            loudness: None,
+            duration: None,
+            tags: None,
+            trim: None,
+            cue: None,
+            fingerprint: None
+        })
     }
 
     fn compose_metadata(enabled: bool, was_played: bool) -> u8 {
@@ -172,6 +760,74 @@ impl Song {
         }
     }
 
+    #[inline]
+    pub fn loudness(&self) -> Option<f32> {
+        self.loudness
+    }
+
+    #[inline]
+    pub fn set_loudness(&mut self, loudness: Option<f32>) {
+        self.loudness = loudness;
+    }
+
+    #[inline]
+    pub fn duration(&self) -> Option<f64> {
+        self.duration
+    }
+
+    #[inline]
+    pub fn set_duration(&mut self, duration: Option<f64>) {
+        self.duration = duration;
+    }
+
+    #[inline]
+    pub fn tags(&self) -> Option<&SongTags> {
+        self.tags.as_ref()
+    }
+
+    #[inline]
+    pub fn set_tags(&mut self, tags: Option<SongTags>) {
+        self.tags = tags;
+    }
+
+    #[inline]
+    pub fn trim(&self) -> Option<Trim> {
+        self.trim
+    }
+
+    #[inline]
+    pub fn set_trim(&mut self, trim: Option<Trim>) {
+        self.trim = trim;
+    }
+
+    #[inline]
+    pub fn cue(&self) -> Option<Trim> {
+        self.cue
+    }
+
+    #[inline]
+    pub fn set_cue(&mut self, cue: Option<Trim>) {
+        self.cue = cue;
+    }
+
+    #[inline]
+    pub fn fingerprint(&self) -> Option<&Fingerprint> {
+        self.fingerprint.as_ref()
+    }
+
+    #[inline]
+    pub fn set_fingerprint(&mut self, fingerprint: Option<Fingerprint>) {
+        self.fingerprint = fingerprint;
+    }
+
+    /// Returns the length of the played part in seconds if it is known
+    pub fn played_duration(&self) -> Option<f64> {
+        match self.cue.or(self.trim) {
+            Some(part) => Some(part.end - part.start),
+            None => self.duration
+        }
+    }
+
     #[inline]
     #[deprecated(since = "0.3.6")]
     pub fn enable(&mut self) {
@@ -184,26 +840,47 @@ impl Song {
         self.metadata &= !2
     }
 
-    /// Plays this song
+    /// Plays this song with the given settings
     /// # Warning this method blocks until the currently playing song is done playing
-    pub fn play(&self, device: &Device) -> Result<(), Error> {
-        let file_path = Path::new(crate::SONG_FILES_DIR).join(self.filename.as_ref());
-
-        play_mp3(
-            file_path,
-            device,
-            |rms, scale_factor, duration_secs|
-                logln!(
-                    "Playing \"{}\" ({:.1} seconds, RMS = {rms}, α={scale_factor})",
-                    self.filename.display(),
-                    duration_secs
-                )
-        )?;
-
-        println!("Finished");
+    pub fn play(&self, sink: &Sink, settings: &PlaybackSettings, control: &PlaybackControl) -> Result<(), Error> {
+        play_tracks(&[self.track_file()], sink, settings, control, log_playing)?;
+
+        logln!("Finished");
 
         Ok(())
     }
+
+    /// Returns the cover art embedded in this song's file
+    pub fn artwork(&self) -> Option<Picture> {
+        let file = File::open(Path::new(crate::SONG_FILES_DIR).join(self.filename.as_ref())).ok()?;
+
+        tags::read_artwork(BufReader::new(file))
+    }
+
+    fn track_file(&self) -> TrackFile {
+        TrackFile {
+            path: Path::new(crate::SONG_FILES_DIR).join(self.filename.as_ref()),
+            name: self.filename.to_string_lossy().into_owned(),
+            loudness: self.loudness,
+            duration: self.duration,
+            trim: self.cue.or(self.trim),
+            // Cue points cut into the music, while trims only cut silence
+            trim_fade: if self.cue.is_some() { CUE_FADE } else { Duration::ZERO }
+        }
+    }
+}
+
+/// Logs the start of a song
+fn log_playing(name: &str, loudness: f32, scale_factor: f32, duration_secs: Option<f64>) {
+    logln!("Playing \"{name}\" ({}, {loudness:.1} LUFS, α={scale_factor})", display_duration(duration_secs));
+}
+
+/// Formats an optional duration for logging
+pub fn display_duration(duration_secs: Option<f64>) -> String {
+    match duration_secs {
+        Some(secs) => format!("{secs:.1} seconds"),
+        None => "unknown length".to_string()
+    }
 }
 
 impl std::fmt::Display for Song {
@@ -224,6 +901,8 @@ impl std::cmp::PartialEq for Song {
     }
 }
 
+impl std::cmp::Eq for Song {}
+
 /// Composes a playlist with the given number of elements from a database's songs.
 pub fn compose_playlist(elem_cnt: usize, database: &mut SongDatabase) -> Option<Vec<Song>> {
     if database.inner().len() == 0 {
@@ -259,91 +938,182 @@ pub fn compose_playlist(elem_cnt: usize, database: &mut SongDatabase) -> Option<
     Some(playlist)
 }
 
-/// Plays each song in a playlist sequentially.
+/// Composes a playlist for a break of the given length (in seconds) from a database's songs.
+/// The filling strategies only use songs with a measured length,
+/// and fall back to a single song if there are none, or none of them fit.
+pub fn compose_break_playlist(break_secs: f64, strategy: BreakStrategy, database: &mut SongDatabase) -> Option<Vec<Song>> {
+    let overfill = match strategy {
+        BreakStrategy::Single => return compose_playlist(crate::PLAYLIST_LENGTH, database),
+        BreakStrategy::Fill => false,
+        BreakStrategy::FillWithFade => true
+    };
+
+    return_unless!(database.inner().iter().any(|s| s.enabled()), None);
+
+    let unplayed_secs: f64 = database
+        .inner()
+        .iter()
+        .filter(|s| s.enabled() && !s.was_played())
+        .filter_map(|s| Some(s.played_duration()? + SONG_GAP_SECS))
+        .sum();
+
+    // Start over when the unplayed songs cannot fill the break
+    if unplayed_secs < break_secs {
+        database.reset_played();
+    }
+
+    let mut candidates: Vec<&mut Song> = database
+        .inner_mut()
+        .iter_mut()
+        .filter(|s| s.enabled() && !s.was_played() && s.played_duration().is_some())
+        .collect();
+
+    candidates.shuffle(&mut rng());
+
+    let length = |song: &Song| song.played_duration().unwrap_or_default() + SONG_GAP_SECS;
+
+    let mut remaining = break_secs;
+    let mut chosen = Vec::new();
+    let mut unused = Vec::new();
+
+    for song in candidates {
+        let fits = if overfill { remaining > 0.0 } else { length(song) <= remaining };
+
+        if fits {
+            remaining -= length(song);
+            chosen.push(song);
+        } else {
+            unused.push(song);
+        }
+    }
+
+    // Swap a song for the longer unused one, which brings the end of the music closest to the bell
+    if !overfill {
+        let best_swap = chosen
+            .iter()
+            .enumerate()
+            .flat_map(|(i, c)| unused.iter().enumerate().map(move |(j, u)| (i, j, length(u) - length(c))))
+            .filter(|&(.., gain)| gain > 0.0 && gain <= remaining)
+            .max_by(|(.., a), (.., b)| a.total_cmp(b));
+
+        if let Some((i, j, _)) = best_swap {
+            std::mem::swap(&mut chosen[i], &mut unused[j]);
+        }
+    }
+
+    if chosen.is_empty() {
+        return compose_playlist(crate::PLAYLIST_LENGTH, database);
+    }
+
+    Some(chosen.into_iter().map(|song| { song.set_played(true); song.clone() }).collect())
+}
+
+/// Plays the songs of a playlist one after another through a single output stream with the given settings,
+/// until the playlist ends or it is stopped through `control`.
 /// # Warning
 /// This function blocks its thread while the songs are playing.
-pub fn play_playlist(playlist: &[Song]) -> Result<(), Error> {
-    let host = cpal::default_host();
-    let device = or_return!(host.default_output_device(), Err(Error::NoOutputDevice));
+pub fn play_playlist(
+    playlist: &[Song],
+    sink: &Sink,
+    settings: &PlaybackSettings,
+    control: &PlaybackControl
+) -> Result<(), Error> {
+    let tracks: Vec<_> = playlist.iter().map(Song::track_file).collect();
 
-    for song in playlist {
-        song.play(&device)?;
-    }
+    play_tracks(&tracks, sink, settings, control, log_playing)?;
+
+    logln!("Finished");
 
     Ok(())
 }
 
-/// Plays the given samples with the given sample rate and channels on a given device
-fn play_samples(samples: Box<[f32]>, source_sample_rate: u32, source_channels: u16, device: &Device) -> Result<(), Error> {
-    let config = or_return!(
-            or_return!(
-                device.supported_output_configs().ok(),
-                Err(Error::OutputDeviceConfigCannotBeQueried)
-            ).filter( |conf| {
-                conf.channels() == source_channels &&
-                match conf.sample_format() {
-                    SampleFormat::F32 => true,
-                    _ => false
-                }
-            } )
-            .max_by_key( |conf| conf.max_sample_rate() ),
-            Err(Error::NoOutputDeviceConfigs)
-        );
+/// Mixes the samples of a stream into an output once no other playback sharing its gate is playing,
+/// and ends the tracks of `control` afterwards
+fn play_stream(
+    consumer: Consumer,
+    mixer: &Mixer,
+    control: &PlaybackControl,
+    fade_out: Duration
+) -> Result<(), Error> {
+    let _guard = control.lock_output();
 
-    let config: StreamConfig = or_return!(
-            config.try_with_sample_rate(SampleRate(source_sample_rate)),
-            Err(Error::OutputDeviceConfigCannotBeSet)
-        ).into();
+    let config = mixer.config();
+    let prebuffer = (PREBUFFER_SECS * (config.sample_rate() as usize * config.channels() as usize) as f64) as usize;
 
-    let duration_secs = samples.len() as f64 / (source_sample_rate as f64 * source_channels as f64);
+    // Let the decoder get ahead, so the output does not start with an underrun
+    while consumer.len() < prebuffer && !consumer.is_closed() && !control.is_stopped() {
+        std::thread::sleep(PREBUFFER_POLL_INTERVAL);
+    }
 
-    // Panic so that panics cascade over threads
-    let _guard = SONG_PLAYING_GATE.lock().expect("Song playing guard was poisoned");
+    let played = mixer.play(consumer, control, fade_out);
+    control.end_track();
 
-    let mut index: usize = 0;
+    played
+}
 
-    let stream = or_return!(
-            device.build_output_stream(
-                &config,
-                move |data: &mut [f32], _| {
-                    if index >= samples.len() {
-                        data.fill(0.0);
-                        return;
-                    }
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-                    let end = (index + data.len()).min(samples.len());
-                    let slice = &samples[index..end];
-                    data[..slice.len()].copy_from_slice(slice);
+    /// The sample rate of the test stream, which makes frames milliseconds
+    const SAMPLE_RATE: u32 = 1000;
 
-                    if slice.len() < data.len() {
-                        data[slice.len()..].fill(0.0);
-                    }
+    /// The number of frames per decoded chunk, which does not divide the seek targets
+    const CHUNK_FRAMES: usize = 700;
 
-                    index += data.len();
-                },
-                |e| eprintln!("Unexpected error \"{e}\". This might be a panic in future versions."),
-                None,
-            ).ok(),
-            Err(Error::StreamCannotBeBuilt)
-        );
+    /// Decodes a mono stream whose samples are their own frame numbers
+    struct CountingDecoder {
+        position: usize,
+        frames: usize
+    }
 
-    or_return!(stream.play().ok(), Err(Error::StreamCannotBePlayed));
+    impl AudioDecoder for CountingDecoder {
+        fn next_frame(&mut self) -> Option<Result<Frame, Error>> {
+            return_unless!(self.position < self.frames, None);
 
-    std::thread::sleep(std::time::Duration::from_secs_f64(duration_secs));
+            let end = (self.position + CHUNK_FRAMES).min(self.frames);
+            let samples = (self.position..end).map(|i| i as f32).collect();
+            self.position = end;
 
-    Ok(())
-}
+            Some(Ok(Frame { samples, sample_rate: SAMPLE_RATE, channels: 1 }))
+        }
+    }
+
+    fn counting_reader(frames: usize) -> TrackReader {
+        let mut decoder: Box<dyn AudioDecoder> = Box::new(CountingDecoder { position: 0, frames });
+        let first_frame = decoder.next_frame().unwrap().unwrap();
+
+        // A measured loudness at the target leaves the samples unchanged
+        TrackReader::new(PathBuf::new(), decoder, first_frame, Some(-23.0), -23.0, Duration::ZERO, (1, SAMPLE_RATE)).unwrap()
+    }
+
+    fn read_all(reader: &mut TrackReader) -> Vec<f32> {
+        let mut samples = Vec::new();
+        while let Some(chunk) = reader.next_chunk() {
+            samples.extend(chunk.unwrap());
+        }
+        samples
+    }
 
-/// Normalizes the samples to a given volume level. Returns the computed volume, and the scale factor.
-fn normalize_samples(samples: &mut [f32], target_volume: f32) -> (f32, f32) {
-    let mut sq_sum = 0.0;
+    #[test]
+    fn seek_inside_chunk_stops_at_end() {
+        let mut reader = counting_reader(10_000);
 
-    samples.iter().for_each(|sample| sq_sum += sample * sample);
+        // The preroll ends at 3500, so the start falls inside the chunk from 4200 to 4900
+        reader.trim(Trim { start: 4.55, end: 5.0 }, Duration::ZERO).unwrap();
 
-    let rms = f32::sqrt(sq_sum / samples.len() as f32);
-    let scale_factor = target_volume / rms;
+        let samples = read_all(&mut reader);
 
-    samples.iter_mut().for_each(|sample| *sample *= scale_factor);
+        assert_eq!(samples.len(), 450);
+        assert_eq!(samples.first(), Some(&4550.0));
+        assert_eq!(samples.last(), Some(&4999.0));
+    }
+
+    #[test]
+    fn seek_returns_target_inside_chunk() {
+        let mut reader = counting_reader(10_000);
 
-    (rms, scale_factor)
-}
\ No newline at end of file
+        assert_eq!(reader.seek(4.55).unwrap(), 4.55);
+        assert_eq!(read_all(&mut reader).first(), Some(&4550.0));
+    }
+}
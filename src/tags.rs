@@ -0,0 +1,421 @@
+use std::io::{Read, Seek, SeekFrom};
+
+/// The size of an ID3v2 tag's header
+const ID3V2_HEADER_LEN: usize = 10;
+
+/// The size of an ID3v1 tag, which is found at the end of a file
+const ID3V1_LEN: usize = 128;
+
+/// The ID3v2 and FLAC picture type of front covers
+const FRONT_COVER: u32 = 3;
+
+/// The FLAC metadata block type of pictures
+const FLAC_PICTURE_BLOCK: u8 = 6;
+
+/// The size of an Ogg page's header without its segment table
+const OGG_PAGE_HEADER_LEN: usize = 27;
+
+/// The largest Ogg header packet read, which keeps broken files from being read whole
+const MAX_OGG_HEADER_PACKET_LEN: usize = 16 * 1024 * 1024;
+
+/// Decodes a 28-bit "synchsafe" integer used by ID3v2
+fn synchsafe(bytes: [u8; 4]) -> usize {
+    bytes.iter().fold(0, |acc, &b| (acc << 7) | (b & 0x7F) as usize)
+}
+
+/// Decodes an ID3v2 text with the given encoding byte
+fn decode_id3_text(encoding: u8, bytes: &[u8]) -> String {
+    match encoding {
+        0 => bytes.iter().map(|&b| b as char).collect(), // ISO-8859-1
+        1 | 2 => {
+            let (big_endian, bytes) = match bytes {
+                [0xFE, 0xFF, rest @ ..] => (true, rest),
+                [0xFF, 0xFE, rest @ ..] => (false, rest),
+                _ => (encoding == 2, bytes)
+            };
+
+            let units = bytes
+                .chunks_exact(2)
+                .map(|c| if big_endian { u16::from_be_bytes([c[0], c[1]]) } else { u16::from_le_bytes([c[0], c[1]]) });
+
+            char::decode_utf16(units).map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER)).collect()
+        },
+        _ => String::from_utf8_lossy(bytes).into_owned()
+    }
+}
+
+/// Splits an ID3v2 text at its first terminator with respect to its encoding
+fn split_id3_text(encoding: u8, bytes: &[u8]) -> (&[u8], &[u8]) {
+    match encoding {
+        1 | 2 => {
+            let end = bytes
+                .chunks_exact(2)
+                .position(|c| c == [0, 0])
+                .map(|i| i * 2)
+                .unwrap_or(bytes.len());
+
+            (&bytes[..end], bytes.get(end + 2..).unwrap_or(&[]))
+        },
+        _ => {
+            let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+
+            (&bytes[..end], bytes.get(end + 1..).unwrap_or(&[]))
+        }
+    }
+}
+
+/// Calls `f` with the identifier and the contents of each frame of an ID3v2 tag at the start of a stream
+fn for_each_id3v2_frame(mut reader: impl Read, mut f: impl FnMut(&str, &[u8])) {
+    let mut header = [0u8; ID3V2_HEADER_LEN];
+
+    if reader.read_exact(&mut header).is_err() || &header[0..3] != b"ID3" {
+        return;
+    }
+
+    let version = header[3];
+    let flags = header[5];
+    let size = synchsafe([header[6], header[7], header[8], header[9]]);
+
+    let mut tag = Vec::with_capacity(size);
+    if reader.take(size as u64).read_to_end(&mut tag).is_err() {
+        return;
+    }
+
+    let mut i = 0;
+
+    // Skip the extended header
+    if flags & 0x40 != 0 && tag.len() >= 4 {
+        let ext_size = match version {
+            4 => synchsafe([tag[0], tag[1], tag[2], tag[3]]),
+            _ => u32::from_be_bytes([tag[0], tag[1], tag[2], tag[3]]) as usize + 4
+        };
+        i += ext_size;
+    }
+
+    let (id_len, header_len) = if version == 2 { (3, 6) } else { (4, 10) };
+
+    while i + header_len <= tag.len() {
+        let id = &tag[i..i + id_len];
+
+        if id[0] == 0 { break; } // Padding
+
+        let frame_size = match version {
+            2 => u32::from_be_bytes([0, tag[i + 3], tag[i + 4], tag[i + 5]]) as usize,
+            3 => u32::from_be_bytes([tag[i + 4], tag[i + 5], tag[i + 6], tag[i + 7]]) as usize,
+            _ => synchsafe([tag[i + 4], tag[i + 5], tag[i + 6], tag[i + 7]])
+        };
+
+        let Some(data) = tag.get(i + header_len..i + header_len + frame_size) else { break; };
+
+        f(&String::from_utf8_lossy(id), data);
+
+        i += header_len + frame_size;
+    }
+}
+
+/// Reads the text frames of an ID3v2 tag at the start of a stream as key-value pairs.\
+/// Standard text frames are keyed by their frame identifier (eg. `TIT2`),
+/// user-defined ones (`TXXX`) by their uppercase description.
+pub fn read_id3v2(reader: impl Read) -> Vec<(String, String)> {
+    let mut result = Vec::new();
+
+    for_each_id3v2_frame(reader, |id, data| {
+        if let ([b'T', ..], [encoding, text @ ..]) = (id.as_bytes(), data) {
+            if id == "TXXX" || id == "TXX" {
+                let (description, value) = split_id3_text(*encoding, text);
+                let (value, _) = split_id3_text(*encoding, value);
+
+                result.push((
+                    decode_id3_text(*encoding, description).to_uppercase(),
+                    decode_id3_text(*encoding, value)
+                ));
+            } else {
+                let (value, _) = split_id3_text(*encoding, text);
+
+                result.push((id.to_string(), decode_id3_text(*encoding, value)));
+            }
+        }
+    });
+
+    result
+}
+
+/// Reads the pictures (`APIC` frames) of an ID3v2 tag at the start of a stream
+fn read_id3v2_pictures(reader: impl Read) -> Vec<Picture> {
+    let mut result = Vec::new();
+
+    for_each_id3v2_frame(reader, |id, data| {
+        let (encoding, kind, rest) = match (id, data) {
+            ("APIC", [encoding, rest @ ..]) => {
+                let (_mime_type, rest) = split_id3_text(0, rest);
+                let Some((kind, rest)) = rest.split_first() else { return; };
+
+                (*encoding, *kind, rest)
+            },
+            // ID3v2.2 states the image format in 3 characters instead of a MIME type
+            ("PIC", [encoding, _, _, _, kind, rest @ ..]) => (*encoding, *kind, rest),
+            _ => return
+        };
+
+        let (_description, data) = split_id3_text(encoding, rest);
+
+        result.push(Picture { kind: kind as u32, data: data.to_vec() });
+    });
+
+    result
+}
+
+/// Reads the ID3v1 tag at the end of a stream as key-value pairs
+/// keyed by the identifiers of the matching ID3v2 frames (eg. `TIT2`)
+pub fn read_id3v1(mut reader: impl Read + Seek) -> Vec<(String, String)> {
+    let mut tag = [0u8; ID3V1_LEN];
+
+    if reader.seek(SeekFrom::End(-(ID3V1_LEN as i64))).is_err() ||
+        reader.read_exact(&mut tag).is_err() ||
+        &tag[0..3] != b"TAG"
+    {
+        return Vec::new();
+    }
+
+    [("TIT2", 3..33), ("TPE1", 33..63), ("TALB", 63..93)]
+        .into_iter()
+        .map(|(id, range)| {
+            let field = &tag[range];
+            let end = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+
+            (id.to_string(), decode_id3_text(0, &field[..end]).trim_end().to_string())
+        })
+        .filter(|(_, value)| !value.is_empty())
+        .collect()
+}
+
+/// Parses a Vorbis comment block (as found in Ogg streams after their magic signature)
+/// into key-value pairs with uppercase keys.
+pub fn parse_vorbis_comments(bytes: &[u8]) -> Vec<(String, String)> {
+    fn read_u32(bytes: &[u8], at: usize) -> Option<usize> {
+        Some(u32::from_le_bytes(bytes.get(at..at + 4)?.try_into().ok()?) as usize)
+    }
+
+    let mut result = Vec::new();
+
+    let Some(vendor_len) = read_u32(bytes, 0) else { return result; };
+    let mut i = 4 + vendor_len;
+
+    let Some(count) = read_u32(bytes, i) else { return result; };
+    i += 4;
+
+    for _ in 0..count {
+        let Some(len) = read_u32(bytes, i) else { break; };
+        let Some(comment) = bytes.get(i + 4..i + 4 + len) else { break; };
+
+        if let Some((key, value)) = String::from_utf8_lossy(comment).split_once('=') {
+            result.push((key.to_uppercase(), value.to_string()));
+        }
+
+        i += 4 + len;
+    }
+
+    result
+}
+
+/// Finds the first value of a key in a list of tags
+pub fn find<'a>(tags: &'a [(String, String)], key: &str) -> Option<&'a str> {
+    tags.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str())
+}
+
+/// The descriptive tags of a song
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SongTags {
+    pub title: Option<Box<str>>,
+    pub artist: Option<Box<str>>
+}
+
+impl SongTags {
+    /// Collects the title and the artist from ID3v2 frames or Vorbis comments
+    pub fn from_comments(comments: &[(String, String)]) -> SongTags {
+        let first = |keys: &[&str]| keys
+            .iter()
+            .find_map(|key| find(comments, key))
+            .map(clean_value)
+            .filter(|v| !v.is_empty())
+            .map(String::into_boxed_str);
+
+        SongTags {
+            title: first(&["TITLE", "TIT2", "TT2"]),
+            artist: first(&["ARTIST", "TPE1", "TP1"])
+        }
+    }
+}
+
+/// Removes the characters of a tag value, which cannot be stored in CSV
+fn clean_value(value: &str) -> String {
+    value
+        .trim()
+        .chars()
+        .filter(|c| !c.is_control())
+        .map(|c| if c == '"' { '\'' } else { c })
+        .collect()
+}
+
+/// An image embedded in an audio file
+#[derive(Debug, Clone)]
+pub struct Picture {
+    /// The ID3v2 and FLAC picture type, eg. 3 for the front cover
+    pub kind: u32,
+    pub data: Vec<u8>
+}
+
+impl Picture {
+    /// Returns the MIME type of the image recognized by its magic bytes
+    pub fn mime_type(&self) -> Option<&'static str> {
+        match self.data.as_slice() {
+            [0xFF, 0xD8, 0xFF, ..] => Some("image/jpeg"),
+            [0x89, b'P', b'N', b'G', ..] => Some("image/png"),
+            [b'G', b'I', b'F', b'8', ..] => Some("image/gif"),
+            [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => Some("image/webp"),
+            [b'B', b'M', ..] => Some("image/bmp"),
+            _ => None
+        }
+    }
+}
+
+/// Reads the cover art of an MP3 (ID3v2), FLAC or Ogg file.\
+/// The front cover is preferred over the other embedded images, and images of unknown formats are skipped.
+pub fn read_artwork(mut reader: impl Read + Seek) -> Option<Picture> {
+    let mut magic = [0u8; 4];
+    reader.read_exact(&mut magic).ok()?;
+    reader.seek(SeekFrom::Start(0)).ok()?;
+
+    let pictures = match &magic {
+        [b'I', b'D', b'3', _] => read_id3v2_pictures(reader),
+        b"fLaC" => read_flac_pictures(reader),
+        b"OggS" => read_ogg_pictures(reader),
+        _ => return None
+    };
+
+    let pictures = pictures.into_iter().filter(|p| p.mime_type().is_some()).collect::<Vec<_>>();
+    let front_cover = pictures.iter().position(|p| p.kind == FRONT_COVER).unwrap_or(0);
+
+    pictures.into_iter().nth(front_cover)
+}
+
+/// Parses a FLAC picture block, which is also used by the `METADATA_BLOCK_PICTURE` Vorbis comment
+fn parse_flac_picture(bytes: &[u8]) -> Option<Picture> {
+    fn read_u32(bytes: &[u8], at: usize) -> Option<usize> {
+        Some(u32::from_be_bytes(bytes.get(at..at + 4)?.try_into().ok()?) as usize)
+    }
+
+    let kind = read_u32(bytes, 0)? as u32;
+    let mime_len = read_u32(bytes, 4)?;
+    let description_len = read_u32(bytes, 8 + mime_len)?;
+
+    // Skip the width, height, color depth and number of colors
+    let data_at = 12 + mime_len + description_len + 16;
+    let data_len = read_u32(bytes, data_at)?;
+
+    Some(Picture { kind, data: bytes.get(data_at + 4..data_at + 4 + data_len)?.to_vec() })
+}
+
+/// Reads the picture blocks of the metadata of a FLAC stream
+fn read_flac_pictures(mut reader: impl Read) -> Vec<Picture> {
+    let mut result = Vec::new();
+    let mut magic = [0u8; 4];
+
+    if reader.read_exact(&mut magic).is_err() || &magic != b"fLaC" {
+        return result;
+    }
+
+    loop {
+        let mut header = [0u8; 4];
+        if reader.read_exact(&mut header).is_err() { break; }
+
+        let last = header[0] & 0x80 != 0;
+        let len = u32::from_be_bytes([0, header[1], header[2], header[3]]) as u64;
+
+        if header[0] & 0x7F == FLAC_PICTURE_BLOCK {
+            let mut block = Vec::new();
+            if reader.by_ref().take(len).read_to_end(&mut block).is_err() { break; }
+
+            result.extend(parse_flac_picture(&block));
+        } else if std::io::copy(&mut reader.by_ref().take(len), &mut std::io::sink()).is_err() {
+            break;
+        }
+
+        if last { break; }
+    }
+
+    result
+}
+
+/// Reads the `METADATA_BLOCK_PICTURE` comments of an Ogg Vorbis or Opus stream
+fn read_ogg_pictures(reader: impl Read) -> Vec<Picture> {
+    // The comments are in the second packet of the stream
+    let Some(packet) = read_ogg_packets(reader, 2).pop() else { return Vec::new(); };
+    let Some(comments) = packet.strip_prefix(b"\x03vorbis").or_else(|| packet.strip_prefix(b"OpusTags")) else {
+        return Vec::new();
+    };
+
+    parse_vorbis_comments(comments)
+        .into_iter()
+        .filter(|(key, _)| key == "METADATA_BLOCK_PICTURE")
+        .filter_map(|(_, value)| parse_flac_picture(&decode_base64(&value)?))
+        .collect()
+}
+
+/// Reads the first `count` packets of an Ogg stream, assuming it has a single logical stream
+fn read_ogg_packets(mut reader: impl Read, count: usize) -> Vec<Vec<u8>> {
+    let mut packets = Vec::new();
+    let mut packet = Vec::new();
+
+    while packets.len() < count {
+        let mut header = [0u8; OGG_PAGE_HEADER_LEN];
+        if reader.read_exact(&mut header).is_err() || &header[0..4] != b"OggS" { break; }
+
+        let mut segments = vec![0u8; header[26] as usize];
+        if reader.read_exact(&mut segments).is_err() { break; }
+
+        for len in segments {
+            let start = packet.len();
+            packet.resize(start + len as usize, 0);
+            if reader.read_exact(&mut packet[start..]).is_err() { return packets; }
+
+            // A packet ends with the first segment shorter than the largest one
+            if len < u8::MAX {
+                packets.push(std::mem::take(&mut packet));
+            }
+        }
+
+        if packet.len() > MAX_OGG_HEADER_PACKET_LEN { break; }
+    }
+
+    packets.truncate(count);
+    packets
+}
+
+/// Decodes standard Base64 with optional padding, ignoring whitespace
+fn decode_base64(text: &str) -> Option<Vec<u8>> {
+    let mut result = Vec::with_capacity(text.len() / 4 * 3);
+    let mut buffer = 0u32;
+    let mut bits = 0;
+
+    for c in text.bytes().filter(|c| !c.is_ascii_whitespace() && *c != b'=') {
+        let value = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' => 62,
+            b'/' => 63,
+            _ => return None
+        };
+
+        buffer = (buffer << 6) | value as u32;
+        bits += 6;
+
+        if bits >= 8 {
+            bits -= 8;
+            result.push((buffer >> bits) as u8);
+        }
+    }
+
+    Some(result)
+}
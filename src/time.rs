@@ -1,7 +1,16 @@
 use std::time::SystemTime;
+use crate::return_unless;
+use crate::timezone::TimeZone;
+
+/// Returns the number of seconds since the UNIX epoch.
+/// # Panics
+/// If the system time is before the UNIX epoch.
+pub fn unix_time() -> i64 {
+    SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).expect("The system time is before the UNIX epoch.").as_secs() as i64
+}
 
 /// Represents a time of day
-#[derive(Eq, PartialEq, Copy, Clone)]
+#[derive(Debug, Eq, PartialEq, Ord, PartialOrd, Copy, Clone)]
 pub struct Time {
     data: u32
 }
@@ -20,16 +29,11 @@ impl Time {
     /// Hours per day
     const HOURS_PER_DAY: u32 = 24;
 
-    /// Returns the current time of day with a given offset (in hours) from UTC.
+    /// Returns the current time of day in a time zone.
     /// # Panics
     /// If the system time is before the UNIX epoch.
-    pub fn now(utc_offset: i8) -> Time {
-        let timestamp = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).expect("The system time is before the UNIX epoch.").as_secs();
-
-        let value =
-            (add_offset(timestamp, utc_offset as i32 * Time::SECS_PER_HOUR as i32) % Self::SECS_PER_DAY as u64) as u32;
-
-        Time { data: value }
+    pub fn now(zone: &TimeZone) -> Time {
+        DateTime::now(zone).time()
     }
 
     /// Returns the stored time's current seconds.
@@ -81,6 +85,15 @@ impl Time {
     pub fn display(&self) -> String {
         format!("{:02}:{:02}:{:02}", self.hours(), self.minutes(), self.seconds())
     }
+
+    /// Parses an ISO 8601 time of `HH:MM:SS` or `HH:MM`, or `HHMMSS` or `HHMM` in the basic format
+    pub fn from_iso(string: &str) -> Option<Time> {
+        match iso_fields(string, ':', &[2, 2, 2])?[..] {
+            [hours, minutes] => Self::from_hms(hours as u8, minutes as u8, 0),
+            [hours, minutes, seconds] => Self::from_hms(hours as u8, minutes as u8, seconds as u8),
+            _ => None
+        }
+    }
 }
 
 impl std::fmt::Display for Time {
@@ -90,6 +103,7 @@ impl std::fmt::Display for Time {
 }
 
 /// Represents a day of a week
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
 pub enum Day {
     Monday,
     Tuesday,
@@ -130,26 +144,268 @@ impl Day {
         }
     }
 
-    /// Returns today's day with a specified offset (in hours) from UTC.
-    pub fn today(utc_offset: i8) -> Day {
-        let timestamp = SystemTime::now()
-            .duration_since(SystemTime::UNIX_EPOCH)
-            .expect("The system time is before the UNIX epoch.")
-            .as_secs();
+    /// Returns today's day in a time zone.
+    pub fn today(zone: &TimeZone) -> Day {
+        Date::today(zone).weekday()
+    }
+}
+
+/// Represents a date of the proleptic Gregorian calendar
+#[derive(Debug, Eq, PartialEq, Ord, PartialOrd, Copy, Clone)]
+pub struct Date {
+    year: i16,
+    month: u8,
+    day: u8
+}
 
-        let days_since_epoch =
-            add_offset(timestamp, utc_offset as i32 * Time::SECS_PER_HOUR as i32) / Time::SECS_PER_DAY as u64;
+impl Date {
+    /// Returns a date if the month and the day exist in the year
+    pub const fn new(year: i16, month: u8, day: u8) -> Option<Date> {
+        let days_in_month = match Self::days_in_month(year, month) {
+            Some(days) => days,
+            None => return None
+        };
+        if day == 0 || day > days_in_month { return None }
 
+        Some(Date { year, month, day })
+    }
+
+    /// Returns the number of days in a month of a year, or `None` if the month does not exist
+    pub const fn days_in_month(year: i16, month: u8) -> Option<u8> {
+        match month {
+            1 | 3 | 5 | 7 | 8 | 10 | 12 => Some(31),
+            4 | 6 | 9 | 11 => Some(30),
+            2 if Self::is_leap_year(year) => Some(29),
+            2 => Some(28),
+            _ => None
+        }
+    }
+
+    pub const fn is_leap_year(year: i16) -> bool {
+        year % 4 == 0 && (year % 100 != 0 || year % 400 == 0)
+    }
+
+    /// Returns today's date in a time zone.
+    pub fn today(zone: &TimeZone) -> Date {
+        DateTime::now(zone).date()
+    }
+
+    pub const fn year(&self) -> i16 {
+        self.year
+    }
+
+    pub const fn month(&self) -> u8 {
+        self.month
+    }
+
+    pub const fn day(&self) -> u8 {
+        self.day
+    }
+
+    /// Returns the date a number of days after 1970-01-01
+    pub const fn from_days_since_epoch(days: i32) -> Date {
+        // Shift the epoch to 0000-03-01, so leap days are at the end of the years
+        let z = days + 719468;
+        let era = z.div_euclid(146097);
+        let doe = z.rem_euclid(146097);                              // Day of era
+        let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365; // Year of era
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);            // Day of the year from March
+        let mp = (5 * doy + 2) / 153;                                 // Month from March
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = yoe + era * 400 + (month <= 2) as i32;
+
+        Date { year: year as i16, month: month as u8, day: day as u8 }
+    }
+
+    /// Returns the number of days from 1970-01-01 to this date
+    pub const fn days_since_epoch(&self) -> i32 {
+        let year = self.year as i32 - (self.month <= 2) as i32;
+        let month = self.month as i32;
+        let era = year.div_euclid(400);
+        let yoe = year.rem_euclid(400);
+        let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + self.day as i32 - 1;
+        let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+
+        era * 146097 + doe - 719468
+    }
+
+    /// Returns the date a number of days after this one, or before it if `days` is negative
+    pub const fn plus_days(&self, days: i32) -> Date {
+        Self::from_days_since_epoch(self.days_since_epoch() + days)
+    }
+
+    /// Returns the day of the week of this date
+    pub fn weekday(&self) -> Day {
         // The UNIX epoch (1970. 01. 01.) was a Thursday, so an offset is needed
+        Day::from_day_number((self.days_since_epoch() + 3).rem_euclid(7) as u8).unwrap()
+    }
+
+    /// Parses an ISO 8601 date of `YYYY-MM-DD`, or `YYYYMMDD` in the basic format
+    pub fn from_iso(string: &str) -> Option<Date> {
+        let [year, month, day] = iso_fields(string, '-', &[4, 2, 2])?.try_into().ok()?;
+
+        Self::new(year as i16, month as u8, day as u8)
+    }
+
+    /// Formats the date as `YYYY-MM-DD`
+    pub fn display(&self) -> String {
+        format!("{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+
+    pub const fn to_bytes(&self) -> [u8; 4] {
+        let year = self.year.to_be_bytes();
+
+        [year[0], year[1], self.month, self.day]
+    }
+
+    pub const fn from_bytes(bytes: [u8; 4]) -> Option<Date> {
+        Self::new(i16::from_be_bytes([bytes[0], bytes[1]]), bytes[2], bytes[3])
+    }
+}
 
-        Self::from_day_number(((days_since_epoch + 3) % 7) as u8).unwrap()
+impl std::fmt::Display for Date {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.display())
     }
 }
 
-/// Adds an offset of `i32` to a `u64`
-fn add_offset(val: u64, offset: i32) -> u64 {
-    match offset {
-        i32::MIN..0 => val - offset.abs() as u64,
-        0..=i32::MAX => val + offset as u64
+/// Represents a date and a time of day on the wall clock of a time zone
+#[derive(Debug, Eq, PartialEq, Ord, PartialOrd, Copy, Clone)]
+pub struct DateTime {
+    date: Date,
+    time: Time
+}
+
+impl DateTime {
+    pub const fn new(date: Date, time: Time) -> DateTime {
+        DateTime { date, time }
     }
-}
\ No newline at end of file
+
+    /// Returns the current date and time in a time zone.
+    /// # Panics
+    /// If the system time is before the UNIX epoch.
+    pub fn now(zone: &TimeZone) -> DateTime {
+        zone.local_time(unix_time())
+    }
+
+    /// Returns the date and time on a wall clock with an offset (in seconds) from UTC at a UNIX time
+    pub const fn from_unix_time(unix_time: i64, utc_offset: i32) -> DateTime {
+        Self::from_seconds_since_epoch(unix_time + utc_offset as i64)
+    }
+
+    /// Returns the UNIX time of this date and time on a wall clock with an offset (in seconds) from UTC
+    pub const fn to_unix_time(&self, utc_offset: i32) -> i64 {
+        self.seconds_since_epoch() - utc_offset as i64
+    }
+
+    /// Returns the date and time a number of seconds after 1970-01-01 00:00:00 on the same wall clock
+    pub const fn from_seconds_since_epoch(seconds: i64) -> DateTime {
+        DateTime {
+            date: Date::from_days_since_epoch(seconds.div_euclid(Time::SECS_PER_DAY as i64) as i32),
+            time: Time::from_seconds(seconds.rem_euclid(Time::SECS_PER_DAY as i64) as u32)
+        }
+    }
+
+    /// Returns the number of seconds from 1970-01-01 00:00:00 to this date and time on the same wall clock
+    pub const fn seconds_since_epoch(&self) -> i64 {
+        self.date.days_since_epoch() as i64 * Time::SECS_PER_DAY as i64 + self.time.elapsed_seconds() as i64
+    }
+
+    pub const fn date(&self) -> Date {
+        self.date
+    }
+
+    pub const fn time(&self) -> Time {
+        self.time
+    }
+
+    /// Parses an ISO 8601 date and time of `YYYY-MM-DDTHH:MM:SS` in the extended or the basic format,
+    /// in which the seconds may be omitted, and the `T` may be a space
+    pub fn from_iso(string: &str) -> Option<DateTime> {
+        let (date, time) = string.split_once(['T', ' '])?;
+
+        Some(DateTime { date: Date::from_iso(date)?, time: Time::from_iso(time)? })
+    }
+
+    /// Parses an ISO 8601 date and time like `from_iso`, which may be followed by its offset from UTC
+    /// as `Z`, `+HH:MM`, `+HHMM` or `+HH`, and returns the offset (in seconds) too if it is given
+    pub fn from_iso_with_offset(string: &str) -> Option<(DateTime, Option<i32>)> {
+        if let Some(local) = string.strip_suffix(['Z', 'z']) {
+            return Some((Self::from_iso(local)?, Some(0)));
+        }
+
+        // The sign of an offset is the first one after the date
+        let (date, time) = string.split_at_checked(string.find(['T', ' '])?)?;
+        let (local, offset) = match time.find(['+', '-']) {
+            Some(i) => (&string[..(date.len() + i)], Some(&time[i..])),
+            None => (string, None)
+        };
+
+        let offset = match offset {
+            Some(offset) => {
+                let sign = if offset.starts_with('-') { -1 } else { 1 };
+                let fields = iso_fields(&offset[1..], ':', &[2, 2])?;
+                let (hours, minutes) = (fields[0], fields.get(1).copied().unwrap_or(0));
+                return_unless!(hours < 24 && minutes < 60, None);
+
+                Some(sign * (hours * 60 + minutes) as i32 * 60)
+            },
+            None => None
+        };
+
+        Some((Self::from_iso(local)?, offset))
+    }
+
+    /// Formats the date and time as `YYYY-MM-DDTHH:MM:SS`
+    pub fn display(&self) -> String {
+        format!("{}T{}", self.date.display(), self.time.display())
+    }
+
+    /// Formats the date and time on a wall clock with an offset (in seconds) from UTC as `YYYY-MM-DDTHH:MM:SS+HH:MM`
+    pub fn display_with_offset(&self, utc_offset: i32) -> String {
+        let sign = if utc_offset < 0 { '-' } else { '+' };
+        let minutes = utc_offset.unsigned_abs() / 60;
+
+        format!("{}{sign}{:02}:{:02}", self.display(), minutes / 60, minutes % 60)
+    }
+}
+
+impl std::fmt::Display for DateTime {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.display())
+    }
+}
+
+/// Returns the numbers of an ISO 8601 value, whose fields have the given widths,
+/// and are either separated by `separator` in the extended format, or not separated in the basic one.
+/// Trailing fields may be omitted.
+fn iso_fields(string: &str, separator: char, widths: &[usize]) -> Option<Vec<u32>> {
+    let fields: Vec<&str> = match string.contains(separator) {
+        true => string.split(separator).collect(),
+        false => {
+            let mut fields = Vec::with_capacity(widths.len());
+            let mut rest = string;
+            for &width in widths {
+                if rest.is_empty() { break; }
+
+                let (field, after) = rest.split_at_checked(width)?;
+                fields.push(field);
+                rest = after;
+            }
+            return_unless!(rest.is_empty(), None);
+
+            fields
+        }
+    };
+    return_unless!(!fields.is_empty() && fields.len() <= widths.len(), None);
+
+    fields
+        .iter()
+        .zip(widths)
+        .map(|(field, &width)| {
+            return_unless!(field.len() == width && field.bytes().all(|b| b.is_ascii_digit()), None);
+            field.parse().ok()
+        })
+        .collect()
+}
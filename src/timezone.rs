@@ -0,0 +1,450 @@
+use std::path::PathBuf;
+use crate::time::{Date, DateTime};
+use crate::{or_return, return_unless, Error};
+
+/// The directory of the system's tz database, unless the `TZDIR` environment variable names another one
+const DEFAULT_TZDIR: &str = "/usr/share/zoneinfo";
+
+/// The environment variable overriding the directory of the tz database, as in the C library
+const TZDIR_ENV_VAR: &str = "TZDIR";
+
+/// The files listing the zones of the tz database in their third column, from the preferred one
+const ZONE_TABLES: [&str; 2] = ["zone1970.tab", "zone.tab"];
+
+/// The length of the header of TZif files
+const TZIF_HEADER_LEN: usize = 44;
+
+/// The local time of rule transitions, if a rule does not give one (02:00:00)
+const DEFAULT_RULE_TIME: i32 = 2 * SECS_PER_HOUR;
+
+/// The start and end of daylight saving time of rules without them, which are the ones of the USA
+const DEFAULT_RULE: &str = ",M3.2.0,M11.1.0";
+
+/// Seconds per hour
+const SECS_PER_HOUR: i32 = 3600;
+
+/// Seconds per day
+const SECS_PER_DAY: i64 = 86400;
+
+/// The largest offset from UTC accepted for fixed offsets (+14:00)
+pub const MAX_UTC_OFFSET: i32 = 14 * SECS_PER_HOUR;
+
+/// The smallest offset from UTC accepted for fixed offsets (-12:00)
+pub const MIN_UTC_OFFSET: i32 = -12 * SECS_PER_HOUR;
+
+/// The rules of the offsets from UTC of a place, which may change over the year and over history
+#[derive(Debug, Clone)]
+pub struct TimeZone {
+    /// The name of the zone in the tz database, or `None` for a fixed offset
+    name: Option<Box<str>>,
+    /// The UNIX times the offset changes at, with the offsets (in seconds) from them, sorted by time
+    transitions: Vec<(i64, i32)>,
+    /// The offset (in seconds) before the first transition, or at any time without transitions and a rule
+    initial_offset: i32,
+    /// The rule of the offsets after the last transition
+    rule: Option<Rule>
+}
+
+impl TimeZone {
+    /// Returns a time zone with the same offset (in seconds) from UTC at all times
+    pub fn fixed(offset: i32) -> TimeZone {
+        TimeZone { name: None, transitions: Vec::new(), initial_offset: offset, rule: None }
+    }
+
+    /// Returns a zone of the tz database, which cannot be loaded, so it uses a fixed offset (in seconds) instead
+    pub fn unavailable(name: &str, offset: i32) -> TimeZone {
+        TimeZone { name: Some(name.into()), ..Self::fixed(offset) }
+    }
+
+    /// Loads a zone, such as `Europe/Budapest`, from the system's tz database
+    pub fn load(name: &str) -> Result<TimeZone, Error> {
+        // Names are relative paths in the database without going up in it
+        return_unless!(
+            !name.is_empty() && name.split('/').all(|part| !part.is_empty() && part != "." && part != ".."),
+            Err(Error::UnknownTimeZone)
+        );
+        return_unless!(
+            name.chars().all(|c| c.is_ascii_alphanumeric() || "/_+-".contains(c)),
+            Err(Error::UnknownTimeZone)
+        );
+
+        let bytes = std::fs::read(tz_directory().join(name)).map_err(|_| Error::UnknownTimeZone)?;
+
+        Self::from_tzif(name, &bytes).ok_or(Error::InvalidTimeZoneFile)
+    }
+
+    /// Parses the contents of a TZif file (RFC 8536) of a zone
+    pub fn from_tzif(name: &str, bytes: &[u8]) -> Option<TimeZone> {
+        return_unless!(bytes.get(0..4)? == b"TZif", None);
+        let version = *bytes.get(4)?;
+
+        let (transitions, offsets, end) = read_tzif_block(bytes, 0, 4)?;
+
+        // Version 2 and later files repeat the data with 64-bit times, followed by a rule for later times
+        let (transitions, offsets, rule) = match version {
+            0 => (transitions, offsets, None),
+            _ => {
+                let (transitions, offsets, end) = read_tzif_block(bytes, end, 8)?;
+                let footer = str::from_utf8(bytes.get(end..)?).ok()?;
+                let footer = footer.strip_prefix('\n')?.split('\n').next()?;
+
+                (transitions, offsets, if footer.is_empty() { None } else { Some(Rule::parse(footer)?) })
+            }
+        };
+
+        Some(TimeZone {
+            name: Some(name.into()),
+            transitions: transitions
+                .into_iter()
+                .map(|(time, index)| Some((time, *offsets.get(index as usize)?)))
+                .collect::<Option<_>>()?,
+            initial_offset: *offsets.first()?,
+            rule
+        })
+    }
+
+    /// Returns the name of the zone in the tz database, or `None` for a fixed offset
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Returns the offset (in seconds) of the wall clock from UTC at a UNIX time
+    pub fn offset_at(&self, unix_time: i64) -> i32 {
+        let passed = self.transitions.partition_point(|&(time, _)| time <= unix_time);
+
+        match (passed, &self.rule) {
+            (0, _) if !self.transitions.is_empty() => self.initial_offset,
+            (n, Some(rule)) if n == self.transitions.len() => rule.offset_at(unix_time),
+            (0, None) => self.initial_offset,
+            (n, _) => self.transitions[n - 1].1
+        }
+    }
+
+    /// Returns the date and time on the wall clock at a UNIX time
+    pub fn local_time(&self, unix_time: i64) -> DateTime {
+        DateTime::from_unix_time(unix_time, self.offset_at(unix_time))
+    }
+
+    /// Returns the UNIX time of a date and time on the wall clock.\
+    /// Times skipped or repeated by a transition may be shifted by the change of the offset.
+    pub fn unix_time_of(&self, local_time: DateTime) -> i64 {
+        let seconds = local_time.seconds_since_epoch();
+        let offset = self.offset_at(seconds - self.offset_at(seconds) as i64);
+        local_time.to_unix_time(offset)
+    }
+}
+
+/// Reads the transitions and the offsets of the local time types of the data block of a TZif file,
+/// which starts with its header at `start` and stores times in `time_len` bytes.
+/// Returns the index of the end of the block too.
+#[allow(clippy::type_complexity)]
+fn read_tzif_block(bytes: &[u8], start: usize, time_len: usize) -> Option<(Vec<(i64, u8)>, Vec<i32>, usize)> {
+    let header = bytes.get(start..(start + TZIF_HEADER_LEN))?;
+    return_unless!(&header[0..4] == b"TZif", None);
+
+    let count = |i: usize| u32::from_be_bytes(header[(20 + 4 * i)..(24 + 4 * i)].try_into().unwrap()) as usize;
+    let [is_ut_count, is_std_count, leap_count, time_count, type_count, char_count] = std::array::from_fn(count);
+
+    let times_start = start + TZIF_HEADER_LEN;
+    let indices_start = times_start + time_count * time_len;
+    let types_start = indices_start + time_count;
+    let end = types_start + type_count * 6 + char_count + leap_count * (time_len + 4) + is_std_count + is_ut_count;
+    return_unless!(end <= bytes.len() && type_count > 0, None);
+
+    let transitions = bytes[times_start..indices_start]
+        .chunks_exact(time_len)
+        .map(|t| match time_len {
+            4 => i32::from_be_bytes(t.try_into().unwrap()) as i64,
+            _ => i64::from_be_bytes(t.try_into().unwrap())
+        })
+        .zip(bytes[indices_start..types_start].iter().copied())
+        .collect();
+
+    let offsets = bytes[types_start..(types_start + type_count * 6)]
+        .chunks_exact(6)
+        .map(|t| i32::from_be_bytes([t[0], t[1], t[2], t[3]]))
+        .collect();
+
+    Some((transitions, offsets, end))
+}
+
+/// Returns the directory of the system's tz database
+fn tz_directory() -> PathBuf {
+    std::env::var_os(TZDIR_ENV_VAR).map_or_else(|| PathBuf::from(DEFAULT_TZDIR), PathBuf::from)
+}
+
+/// Returns the names of the zones of the system's tz database in alphabetical order, or none if they cannot be listed
+pub fn available_zones() -> Vec<String> {
+    let directory = tz_directory();
+    let table = or_return!(
+        ZONE_TABLES.iter().find_map(|t| std::fs::read_to_string(directory.join(t)).ok()),
+        Vec::new()
+    );
+
+    let mut zones: Vec<String> = table
+        .lines()
+        .filter(|line| !line.starts_with('#'))
+        .filter_map(|line| line.split('\t').nth(2))
+        .map(str::to_string)
+        .chain(["UTC".to_string()])
+        .collect();
+
+    zones.sort();
+    zones.dedup();
+    zones
+}
+
+/// A rule of the offsets from UTC given by a POSIX TZ string, such as `CET-1CEST,M3.5.0,M10.5.0/3`
+#[derive(Debug, Clone)]
+struct Rule {
+    /// The offset (in seconds) of standard time from UTC
+    std_offset: i32,
+    /// The offset of daylight saving time, and the days and times it starts and ends on
+    dst: Option<(i32, RuleDate, RuleDate)>
+}
+
+impl Rule {
+    fn parse(s: &str) -> Option<Rule> {
+        let mut rest = skip_abbreviation(s)?;
+        // POSIX offsets are positive west of Greenwich, unlike the ones of the tz database
+        let std_offset = -parse_hms(&mut rest)?;
+
+        if rest.is_empty() {
+            return Some(Rule { std_offset, dst: None });
+        }
+
+        rest = skip_abbreviation(rest)?;
+        let dst_offset = match rest.starts_with(',') || rest.is_empty() {
+            true => std_offset + SECS_PER_HOUR,
+            false => -parse_hms(&mut rest)?
+        };
+
+        if rest.is_empty() {
+            rest = DEFAULT_RULE;
+        }
+
+        let mut dates = rest.strip_prefix(',')?.split(',');
+        let start = RuleDate::parse(dates.next()?)?;
+        let end = RuleDate::parse(dates.next()?)?;
+        return_unless!(dates.next().is_none(), None);
+
+        Some(Rule { std_offset, dst: Some((dst_offset, start, end)) })
+    }
+
+    fn offset_at(&self, unix_time: i64) -> i32 {
+        let (dst_offset, start, end) = match &self.dst {
+            Some(dst) => dst,
+            None => return self.std_offset
+        };
+
+        // The transitions are given on the wall clock, in standard time for the start and in daylight saving time for the end
+        let year = DateTime::from_unix_time(unix_time, self.std_offset).date().year();
+        let start = start.local_seconds(year) - self.std_offset as i64;
+        let end = end.local_seconds(year) - *dst_offset as i64;
+
+        let in_dst = match start < end {
+            true => (start..end).contains(&unix_time),
+            // On the southern hemisphere, daylight saving time spans the turn of the year
+            false => !(end..start).contains(&unix_time)
+        };
+
+        if in_dst { *dst_offset } else { self.std_offset }
+    }
+}
+
+/// A day of a year and a time on it, on which a rule changes between standard and daylight saving time
+#[derive(Debug, Clone)]
+struct RuleDate {
+    day: RuleDay,
+    /// The time on the day in seconds, which may be negative or more than a day
+    time: i32
+}
+
+#[derive(Debug, Clone)]
+enum RuleDay {
+    /// The day of the year from 1, not counting February 29 (`Jn`)
+    Julian(u16),
+    /// The day of the year from 0, counting February 29 (`n`)
+    Zero(u16),
+    /// The day of a week of a month (`Mm.w.d`), the week being 1 to 4 or 5 for the last one,
+    /// and the day being 0 for Sunday to 6 for Saturday
+    Month { month: u8, week: u8, weekday: u8 }
+}
+
+impl RuleDate {
+    fn parse(s: &str) -> Option<RuleDate> {
+        let (day, time) = match s.split_once('/') {
+            Some((day, mut time)) => (day, parse_hms(&mut time).filter(|_| time.is_empty())?),
+            None => (s, DEFAULT_RULE_TIME)
+        };
+
+        let day = if let Some(day) = day.strip_prefix('J') {
+            RuleDay::Julian(day.parse().ok().filter(|d| (1..=365).contains(d))?)
+        } else if let Some(day) = day.strip_prefix('M') {
+            let mut parts = day.split('.').map(|p| p.parse::<u8>().ok());
+            let (month, week, weekday) = (parts.next()??, parts.next()??, parts.next()??);
+            return_unless!(parts.next().is_none(), None);
+            return_unless!((1..=12).contains(&month) && (1..=5).contains(&week) && weekday <= 6, None);
+
+            RuleDay::Month { month, week, weekday }
+        } else {
+            RuleDay::Zero(day.parse().ok().filter(|d| *d <= 365)?)
+        };
+
+        Some(RuleDate { day, time })
+    }
+
+    /// Returns the seconds from 1970-01-01 00:00:00 to the change in a year on the wall clock
+    fn local_seconds(&self, year: i16) -> i64 {
+        let jan_1 = Date::new(year, 1, 1).unwrap().days_since_epoch();
+
+        let days = match self.day {
+            RuleDay::Julian(day) => jan_1 + day as i32 - 1 + (Date::is_leap_year(year) && day >= 60) as i32,
+            RuleDay::Zero(day) => jan_1 + day as i32,
+            RuleDay::Month { month, week, weekday } => {
+                let first = Date::new(year, month, 1).unwrap();
+                // The weekdays of rules start on Sunday, but the ones of `Day` start on Monday
+                let first_weekday = (first.weekday().as_day_number() + 1) % 7;
+                let mut day = 1 + (weekday + 7 - first_weekday) % 7 + (week - 1) * 7;
+
+                let days_in_month = Date::days_in_month(year, month).unwrap();
+                while day > days_in_month {
+                    day -= 7;
+                }
+
+                first.days_since_epoch() + day as i32 - 1
+            }
+        };
+
+        days as i64 * SECS_PER_DAY + self.time as i64
+    }
+}
+
+/// Returns the rest of a POSIX TZ string after the abbreviation at its start,
+/// which is either alphabetic or quoted between `<` and `>`
+fn skip_abbreviation(s: &str) -> Option<&str> {
+    let rest = match s.strip_prefix('<') {
+        Some(quoted) => quoted.split_once('>')?.1,
+        None => s.trim_start_matches(|c: char| c.is_ascii_alphabetic())
+    };
+
+    return_unless!(s.len() - rest.len() >= 3, None);
+    Some(rest)
+}
+
+/// Parses a signed time of `[+-]hh[:mm[:ss]]` at the start of a string into seconds,
+/// and moves the string past it
+fn parse_hms(s: &mut &str) -> Option<i32> {
+    let (sign, rest) = match s.as_bytes().first()? {
+        b'-' => (-1, &s[1..]),
+        b'+' => (1, &s[1..]),
+        _ => (1, *s)
+    };
+
+    let len = rest.find(|c: char| !c.is_ascii_digit() && c != ':').unwrap_or(rest.len());
+    let parts: Vec<&str> = rest[..len].split(':').collect();
+    return_unless!(len > 0 && parts.len() <= 3, None);
+
+    let mut seconds = 0;
+    for (part, unit) in parts.into_iter().zip([SECS_PER_HOUR, 60, 1]) {
+        let value: i32 = part.parse().ok()?;
+        return_unless!(unit == SECS_PER_HOUR || value < 60, None);
+
+        seconds += value * unit;
+    }
+
+    *s = &rest[len..];
+    Some(sign * seconds)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The rule of Europe/Budapest
+    const BUDAPEST_RULE: &str = "CET-1CEST,M3.5.0,M10.5.0/3";
+
+    /// The rule of Australia/Sydney, whose daylight saving time spans the turn of the year
+    const SYDNEY_RULE: &str = "AEST-10AEDT,M10.1.0,M4.1.0/3";
+
+    /// The changes of Europe/Budapest in 2025, at 2025-03-30 01:00 and 2025-10-26 01:00 UTC
+    const BUDAPEST_2025: [(i64, u8); 2] = [(1743296400, 1), (1761440400, 0)];
+
+    /// Builds a version 2 TZif file with transitions to the given offsets, and a footer rule
+    fn tzif(transitions: &[(i64, u8)], offsets: &[i32], footer: &str) -> Vec<u8> {
+        let mut bytes = Vec::new();
+
+        for time_len in [4, 8] {
+            bytes.extend(b"TZif2");
+            bytes.extend([0; 15]);
+            for count in [0, 0, 0, transitions.len(), offsets.len(), 0] {
+                bytes.extend((count as u32).to_be_bytes());
+            }
+
+            for &(time, _) in transitions {
+                match time_len {
+                    4 => bytes.extend((time as i32).to_be_bytes()),
+                    _ => bytes.extend(time.to_be_bytes())
+                }
+            }
+            bytes.extend(transitions.iter().map(|&(_, index)| index));
+            for &offset in offsets {
+                bytes.extend(offset.to_be_bytes());
+                bytes.extend([0, 0]);
+            }
+        }
+
+        bytes.extend(format!("\n{footer}\n").bytes());
+        bytes
+    }
+
+    /// Asserts the offsets just before a change and at it
+    fn assert_change(offset_at: impl Fn(i64) -> i32, time: i64, before: i32, after: i32) {
+        assert_eq!((offset_at(time - 1), offset_at(time)), (before, after), "at {time}");
+    }
+
+    #[test]
+    fn tzif_transitions_then_footer_rule() {
+        let zone = TimeZone::from_tzif("Europe/Budapest", &tzif(&BUDAPEST_2025, &[3600, 7200], BUDAPEST_RULE)).unwrap();
+        let offset_at = |time| zone.offset_at(time);
+
+        assert_eq!(zone.name(), Some("Europe/Budapest"));
+        assert_change(offset_at, BUDAPEST_2025[0].0, 3600, 7200);
+        assert_change(offset_at, BUDAPEST_2025[1].0, 7200, 3600);
+
+        // After the last transition, the footer rule gives 2026-03-29 01:00 and 2026-10-25 01:00 UTC
+        assert_change(offset_at, 1774746000, 3600, 7200);
+        assert_change(offset_at, 1792890000, 7200, 3600);
+    }
+
+    #[test]
+    fn southern_rule_wraps_new_year() {
+        let rule = Rule::parse(SYDNEY_RULE).unwrap();
+        let offset_at = |time| rule.offset_at(time);
+
+        // 2026-01-15 00:00 UTC
+        assert_eq!(offset_at(1768435200), 11 * SECS_PER_HOUR);
+        // 2026-04-05 03:00 AEDT, and 2026-10-04 02:00 AEST
+        assert_change(offset_at, 1775318400, 11 * SECS_PER_HOUR, 10 * SECS_PER_HOUR);
+        assert_change(offset_at, 1791043200, 10 * SECS_PER_HOUR, 11 * SECS_PER_HOUR);
+    }
+
+    #[test]
+    fn rule_dates() {
+        let day_seconds = |year, month, day| {
+            Date::new(year, month, day).unwrap().days_since_epoch() as i64 * SECS_PER_DAY
+        };
+        let local_seconds = |date: &str, year| RuleDate::parse(date).unwrap().local_seconds(year);
+
+        // Julian days skip February 29, and zero-based ones count it
+        assert_eq!(local_seconds("J60", 2028), day_seconds(2028, 3, 1) + DEFAULT_RULE_TIME as i64);
+        assert_eq!(local_seconds("J60", 2027), day_seconds(2027, 3, 1) + DEFAULT_RULE_TIME as i64);
+        assert_eq!(local_seconds("59/0", 2028), day_seconds(2028, 2, 29));
+        // The fifth week is the last one, and times may be negative
+        assert_eq!(local_seconds("M3.5.0/-1", 2026), day_seconds(2026, 3, 29) - SECS_PER_HOUR as i64);
+
+        let rule = Rule::parse("<+0330>-3:30").unwrap();
+        assert_eq!((rule.std_offset, rule.dst.is_none()), (3 * SECS_PER_HOUR + 1800, true));
+        assert!(Rule::parse("CET-1CEST,M3.5.0").is_none());
+    }
+}
@@ -6,12 +6,17 @@ use std::mem::MaybeUninit;
 use std::num::{NonZeroU16, NonZeroU64};
 use std::path::Path;
 
-use crate::{generated, logln, or_continue, or_return, songs, time, events};
-use crate::config::Configs;
+use crate::{generated, logln, or_continue, or_return, return_unless, songs, time, events};
+use crate::config::{Configs, EventPriority, MAX_FADE_SECS};
 use crate::csv::{CsvObject, DEFAULT_SEPARATOR, DEFAULT_STR_MARKER};
 use crate::Error;
+use crate::analysis::SongAnalyzer;
 use crate::events::{EventQueue, ScheduledTrigger};
-use crate::songs::Song;
+use crate::songs::{Song, Trim};
+use crate::time::{unix_time, Date, DateTime};
+use crate::timezone;
+use crate::playback::ZonePlayers;
+use crate::sink::{self, DeviceSelection, SinkKind};
 use crate::generated::{Encoding, ENCODING};
 
 const MAX_BODY_SIZE: usize = 500_000_000;
@@ -156,6 +161,36 @@ impl Uri {
     pub fn without_query_parameters(&self) -> &str {
         self.0.splitn(2, '?').next().unwrap()
     }
+
+    /// Returns the percent-decoded value of the first query parameter with the given name
+    pub fn query_parameter(&self, name: &str) -> Option<String> {
+        let (_, query) = self.0.split_once('?')?;
+
+        query
+            .split('&')
+            .filter_map(|pair| pair.split_once('=').or(Some((pair, ""))))
+            .find(|(key, _)| percent_decode(key).as_deref() == Some(name))
+            .and_then(|(_, value)| percent_decode(value))
+    }
+}
+
+/// Decodes `%XX` escapes and `+` as a space, failing on invalid escapes or UTF-8
+fn percent_decode(s: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut iter = s.bytes();
+
+    while let Some(b) = iter.next() {
+        bytes.push(match b {
+            b'%' => {
+                let hex = [iter.next()?, iter.next()?];
+                u8::from_str_radix(str::from_utf8(&hex).ok()?, 16).ok()?
+            },
+            b'+' => b' ',
+            b => b
+        });
+    }
+
+    String::from_utf8(bytes).ok()
 }
 
 pub struct Response {
@@ -224,6 +259,15 @@ impl Response {
         Self::ok(Vec::new())
     }
 
+    pub fn accepted(body: Body) -> Response {
+        Response {
+            status_code: Self::store_status_code(202).unwrap(),
+            reason: "Accepted".into(),
+            headers: Vec::new(),
+            body
+        }
+    }
+
     pub fn not_found() -> Response {
         Response {
             status_code: Self::store_status_code(404).unwrap(),
@@ -326,7 +370,9 @@ pub fn handle_request(
     request: Result<Request, Error>,
     database: &mut Database,
     configs: &mut Configs,
-    event_queue: &mut EventQueue
+    event_queue: &mut EventQueue,
+    players: &mut ZonePlayers,
+    analyzer: &mut SongAnalyzer
 ) -> Response {
     let request = match request {
         Ok(r) => r,
@@ -342,8 +388,8 @@ pub fn handle_request(
     };
 
     match request {
-        Request::Get { uri, headers } => handle_get(uri, headers, database, configs, event_queue),
-        Request::Post { uri, headers, body } => handle_post(uri, headers, body, database, configs, event_queue),
+        Request::Get { uri, headers } => handle_get(uri, headers, database, configs, event_queue, players, analyzer),
+        Request::Post { uri, headers, body } => handle_post(uri, headers, body, database, configs, event_queue, players, analyzer),
         // _ => return Response::not_implemented()
     }
 }
@@ -353,7 +399,9 @@ fn handle_get(
     _headers: Headers,
     database: &Database,
     configs: &Configs,
-    event_queue: &mut EventQueue
+    event_queue: &mut EventQueue,
+    players: &ZonePlayers,
+    analyzer: &SongAnalyzer
 ) -> Response {
     let content_type: &'static str;
     let content_encoding: Option<&'static str>;
@@ -386,12 +434,16 @@ fn handle_get(
                 content_encoding = embedded_encoding;
                 generated::embedded_files::FAVICON_SVG
             },
+            // Both act on the timetable given by a `timetable` query parameter, or on the first one without it
             #[allow(unused_parens)]
             "/data/timetable.csv" => break 'match_uri ({
                 content_type = "text/csv";
                 content_encoding = None;
                 CsvObject::serialize(
-                    configs.get_timetable_csv(),
+                    or_return!(
+                        configs.get_timetable_csv(uri.query_parameter("timetable").as_deref()),
+                        Response::not_found()
+                    ),
                     DEFAULT_SEPARATOR,
                     DEFAULT_STR_MARKER
                 ).into_bytes()
@@ -401,11 +453,81 @@ fn handle_get(
                 content_type = "text/csv";
                 content_encoding = None;
                 CsvObject::serialize(
-                    configs.get_breaks_csv(),
+                    or_return!(
+                        configs.get_breaks_csv(uri.query_parameter("timetable").as_deref()),
+                        Response::not_found()
+                    ),
+                    DEFAULT_SEPARATOR,
+                    DEFAULT_STR_MARKER
+                ).into_bytes()
+            }),
+            #[allow(unused_parens)]
+            "/data/timetables.csv" => break 'match_uri ({
+                content_type = "text/csv";
+                content_encoding = None;
+                CsvObject::serialize(
+                    configs.get_timetables_csv(),
+                    DEFAULT_SEPARATOR,
+                    DEFAULT_STR_MARKER
+                ).into_bytes()
+            }),
+            #[allow(unused_parens)]
+            "/data/weekday-timetables.csv" => break 'match_uri ({
+                content_type = "text/csv";
+                content_encoding = None;
+                CsvObject::serialize(
+                    configs.get_weekday_timetables_csv(),
                     DEFAULT_SEPARATOR,
                     DEFAULT_STR_MARKER
                 ).into_bytes()
             }),
+            #[allow(unused_parens)]
+            "/data/calendar.csv" => break 'match_uri ({
+                content_type = "text/csv";
+                content_encoding = None;
+                CsvObject::serialize(
+                    configs.get_calendar_csv(),
+                    DEFAULT_SEPARATOR,
+                    DEFAULT_STR_MARKER
+                ).into_bytes()
+            }),
+            #[allow(unused_parens)]
+            "/data/holidays.csv" => break 'match_uri ({
+                content_type = "text/csv";
+                content_encoding = None;
+                CsvObject::serialize(
+                    configs.get_holidays_csv(),
+                    DEFAULT_SEPARATOR,
+                    DEFAULT_STR_MARKER
+                ).into_bytes()
+            }),
+            // The timetable used on the date given by a `date` query parameter, or today without it,
+            // whether it was chosen by the calendar or by the weekday,
+            // and the name of the days off the date is in, when no breaks are played
+            "/data/timetable-on.csv" => {
+                let date = match uri.query_parameter("date") {
+                    Some(date) => or_return!(Date::from_iso(&date), Response::bad_request()),
+                    None => Date::today(configs.time_zone())
+                };
+                let source = match configs.calendar_timetable(date) {
+                    Some(_) => "calendar",
+                    None => "weekday"
+                };
+
+                content_type = "text/csv";
+                content_encoding = None;
+                break 'match_uri CsvObject::serialize(
+                    vec![vec![
+                        date.display().into(),
+                        configs.timetable_on(date).name().into(),
+                        source.into(),
+                        configs.holiday_on(date).map_or(CsvObject::Null, |h| h.name().into())
+                    ]],
+                    DEFAULT_SEPARATOR,
+                    DEFAULT_STR_MARKER
+                ).into_bytes()
+            },
+            // The current offset from UTC in whole hours
             "/data/utc-offset.bin" => return Response::new(
                 200,
                 "OK",
@@ -414,9 +536,33 @@ fn handle_get(
                     "Content-Length: 1".into()
                 ],
                 vec![
-                    configs.utc_offset() as u8
+                    (configs.time_zone().offset_at(unix_time()) / 3600) as i8 as u8
                 ]
             ).unwrap(),
+            // The name of the time zone, or null for a fixed offset, and the current offset from UTC in minutes
+            #[allow(unused_parens)]
+            "/data/time-zone.csv" => break 'match_uri ({
+                content_type = "text/csv";
+                content_encoding = None;
+                CsvObject::serialize(
+                    vec![vec![
+                        configs.time_zone().name().map_or(CsvObject::Null, CsvObject::from),
+                        CsvObject::Int((configs.time_zone().offset_at(unix_time()) / 60) as i64)
+                    ]],
+                    DEFAULT_SEPARATOR,
+                    DEFAULT_STR_MARKER
+                ).into_bytes()
+            }),
+            #[allow(unused_parens)]
+            "/data/time-zones.csv" => break 'match_uri ({
+                content_type = "text/csv";
+                content_encoding = None;
+                CsvObject::serialize(
+                    timezone::available_zones().into_iter().map(|zone| vec![zone.as_str().into()]).collect(),
+                    DEFAULT_SEPARATOR,
+                    DEFAULT_STR_MARKER
+                ).into_bytes()
+            }),
             #[allow(unused_parens)]
             "/data/songs.csv" => break 'match_uri ({
                 content_type = "text/csv";
@@ -427,26 +573,191 @@ fn handle_get(
                     DEFAULT_STR_MARKER
                 ).into_bytes()
             }),
+            // Each row holds an uploaded song, which is added once it is analyzed,
+            // or the songs it was rejected for sounding like
+            #[allow(unused_parens)]
+            "/data/uploads.csv" => break 'match_uri ({
+                content_type = "text/csv";
+                content_encoding = None;
+                CsvObject::serialize(
+                    analyzer
+                        .uploads()
+                        .iter()
+                        .filter_map(|u| {
+                            let mut row = vec![u.filename().to_str()?.into()];
+
+                            match u.duplicates() {
+                                Some(duplicates) => {
+                                    row.push("duplicate".into());
+                                    row.extend(duplicates.iter().filter_map(|d| d.to_str()).map(CsvObject::from));
+                                },
+                                None => row.push("analyzing".into())
+                            }
+
+                            Some(row)
+                        })
+                        .collect(),
+                    DEFAULT_SEPARATOR,
+                    DEFAULT_STR_MARKER
+                ).into_bytes()
+            }),
+            // Each row lists the songs that are likely the same recording
+            #[allow(unused_parens)]
+            "/data/duplicates.csv" => break 'match_uri ({
+                content_type = "text/csv";
+                content_encoding = None;
+                CsvObject::serialize(
+                    database
+                        .duplicate_clusters()
+                        .into_iter()
+                        .map(|cluster| cluster
+                            .into_iter()
+                            .filter_map(|s| s.filename().to_str())
+                            .map(CsvObject::from)
+                            .collect())
+                        .collect(),
+                    DEFAULT_SEPARATOR,
+                    DEFAULT_STR_MARKER
+                ).into_bytes()
+            }),
             #[allow(unused_parens)]
             "/data/events.csv" => break 'match_uri ({
                 content_type = "text/csv";
                 content_encoding = None;
                 CsvObject::serialize(
-                    event_queue.get_queue_csv(),
+                    event_queue
+                        .get_queue_csv()
+                        .into_iter()
+                        .map(|mut row| {
+                            let name = row.first().and_then(|name| name.as_string()).unwrap_or_default();
+                            let loudness = configs.event_target_loudness_override(name);
+                            let priority = configs.event_priority(name);
+                            let zones = configs.event_zones(name).iter().map(|zone| CsvObject::from(zone.as_ref()));
+
+                            row.push(loudness.map_or(CsvObject::Null, CsvObject::from));
+                            row.push(priority.name().into());
+                            row.extend(zones.collect::<Vec<_>>());
+                            row
+                        })
+                        .collect(),
+                    DEFAULT_SEPARATOR,
+                    DEFAULT_STR_MARKER
+                ).into_bytes()
+            }),
+            "/data/target-loudness" => {
+                let body = configs.target_loudness().to_string().into_bytes();
+                return Response::new(200, "OK", vec![
+                    "Content-Type: text/plain".into(),
+                    format!("Content-Length: {}", body.len()),
+                ], body).unwrap()
+            },
+            #[allow(unused_parens)]
+            "/data/devices" => break 'match_uri ({
+                content_type = "text/csv";
+                content_encoding = None;
+                CsvObject::serialize(
+                    sink::get_devices_csv(),
+                    DEFAULT_SEPARATOR,
+                    DEFAULT_STR_MARKER
+                ).into_bytes()
+            }),
+            #[allow(unused_parens)]
+            "/data/output-device.csv" => break 'match_uri ({
+                content_type = "text/csv";
+                content_encoding = None;
+                CsvObject::serialize(
+                    configs.get_output_device_csv(),
+                    DEFAULT_SEPARATOR,
+                    DEFAULT_STR_MARKER
+                ).into_bytes()
+            }),
+            #[allow(unused_parens)]
+            "/data/zones.csv" => break 'match_uri ({
+                content_type = "text/csv";
+                content_encoding = None;
+                CsvObject::serialize(
+                    configs.get_zones_csv(),
+                    DEFAULT_SEPARATOR,
+                    DEFAULT_STR_MARKER
+                ).into_bytes()
+            }),
+            "/data/sink" => {
+                let body = configs.sink().to_spec().into_bytes();
+                return Response::new(200, "OK", vec![
+                    "Content-Type: text/plain".into(),
+                    format!("Content-Length: {}", body.len()),
+                ], body).unwrap()
+            },
+            #[allow(unused_parens)]
+            "/data/fades.csv" => break 'match_uri ({
+                content_type = "text/csv";
+                content_encoding = None;
+                CsvObject::serialize(
+                    configs.get_fades_csv(),
+                    DEFAULT_SEPARATOR,
+                    DEFAULT_STR_MARKER
+                ).into_bytes()
+            }),
+            #[allow(unused_parens)]
+            "/data/now-playing" => break 'match_uri ({
+                content_type = "text/csv";
+                content_encoding = None;
+                CsvObject::serialize(
+                    players
+                        .get(uri.query_parameter("zone").as_deref())
+                        .and_then(|player| player.now_playing())
+                        .map(|track| vec![
+                            CsvObject::from(track.name.as_ref()),
+                            CsvObject::from(track.position),
+                            track.duration.map_or(CsvObject::Null, CsvObject::from),
+                            CsvObject::from(track.paused)
+                        ])
+                        .into_iter()
+                        .collect(),
                     DEFAULT_SEPARATOR,
                     DEFAULT_STR_MARKER
                 ).into_bytes()
             }),
-            // "/data/server-time" => return Response::ok(format!("{}", time::Time::now(configs.utc_offset())).into_bytes()),
+            "/data/artwork" => {
+                let song = or_return!(uri.query_parameter("song"), Response::bad_request());
+                let song = or_return!(
+                    database.inner().iter().find(|s| s.filename() == OsStr::new(&song)),
+                    Response::not_found()
+                );
+
+                match song.artwork().and_then(|artwork| Some((artwork.mime_type()?, artwork.data))) {
+                    Some((mime_type, data)) => {
+                        content_type = mime_type;
+                        content_encoding = None;
+                        break 'match_uri data
+                    },
+                    None => {
+                        content_type = "image/svg+xml";
+                        content_encoding = embedded_encoding;
+                        generated::embedded_files::FAVICON_SVG
+                    }
+                }
+            },
+            // "/data/server-time" => return Response::ok(format!("{}", time::Time::now(configs.time_zone())).into_bytes()),
             "/data/server-time" => {
-                let body = time::Time::now(configs.utc_offset()).display().as_bytes().to_vec();
+                let body = time::Time::now(configs.time_zone()).display().as_bytes().to_vec();
+                return Response::new(200, "OK", vec![
+                    "Content-Type: text/plain".into(),
+                    format!("Content-Length: {}", body.len()),
+                ], body).unwrap()
+            },
+            // The current date and time with the offset from UTC, such as `2026-10-17T08:00:00+02:00`
+            "/data/server-datetime" => {
+                let unix_time = unix_time();
+                let offset = configs.time_zone().offset_at(unix_time);
+                let body = DateTime::from_unix_time(unix_time, offset).display_with_offset(offset).into_bytes();
                 return Response::new(200, "OK", vec![
                     "Content-Type: text/plain".into(),
                     format!("Content-Length: {}", body.len()),
                 ], body).unwrap()
             },
             "/data/server-time-seconds" => {
-                let body = time::Time::now(configs.utc_offset()).elapsed_seconds().to_string().as_bytes().to_vec();
+                let body = time::Time::now(configs.time_zone()).elapsed_seconds().to_string().as_bytes().to_vec();
                 return Response::new(200, "OK", vec![
                     "Content-Type: text/plain".into(),
                     format!("Content-Length: {}", body.len()),
@@ -498,17 +809,29 @@ macro_rules! or_bad_request {
     };
 }
 
+#[allow(clippy::too_many_arguments)]
 fn handle_post(
     uri: Uri,
     _headers: Headers,
     body: Body,
     database: &mut Database,
     configs: &mut Configs,
-    event_queue: &mut EventQueue
+    event_queue: &mut EventQueue,
+    players: &mut ZonePlayers,
+    analyzer: &mut SongAnalyzer
 ) -> Response {
+    // The zone the playback endpoints act on, which is the default output without one
+    let zone = uri.query_parameter("zone");
+    let zone = zone.as_deref();
+    // The timetable the timetable endpoints act on, which is the first one without one
+    let timetable = uri.query_parameter("timetable");
+    let timetable = timetable.as_deref();
+
     match uri.without_query_parameters() {
         "/api/set-timetable" => {
+            return_unless!(configs.timetable(timetable).is_some(), Response::not_found());
             or_return!(configs.set_timetable_from_csv(
+                timetable,
                 csv_from_utf8_or_return!(body.as_slice(), Response::bad_request())
                 ),
                 Response::bad_request()
@@ -517,8 +840,10 @@ fn handle_post(
             Response::ok("Timetable successfully set".into())
         },
         "/api/set-breaks" => {
+            return_unless!(configs.timetable(timetable).is_some(), Response::not_found());
             or_return!(
                 configs.set_breaks_from_csv(
+                    timetable,
                     csv_from_utf8_or_return!(body.as_slice(), Response::bad_request())
                 ),
                 Response::bad_request()
@@ -526,16 +851,251 @@ fn handle_post(
 
             Response::ok("Timetable successfully set".into())
         },
+        "/api/add-timetable" => {
+            let decoded = csv_from_utf8_or_return!(body.as_slice(), Response::bad_request());
+            let mut names = or_bad_request!(decoded.first()).iter().filter_map(|v| v.as_string());
+            let name = or_bad_request!(names.next());
+
+            match configs.add_timetable(name, names.next()) {
+                Some(()) => Response::ok("Timetable successfully added".into()),
+                None => Response::unprocessable_request()
+            }
+        },
+        "/api/rename-timetable" => {
+            let decoded = csv_from_utf8_or_return!(body.as_slice(), Response::bad_request());
+            let mut names = or_bad_request!(decoded.first()).iter().filter_map(|v| v.as_string());
+            let (name, new_name) = (or_bad_request!(names.next()), or_bad_request!(names.next()));
+
+            match configs.rename_timetable(name, new_name) {
+                Some(()) => Response::ok("Timetable successfully renamed".into()),
+                None => Response::unprocessable_request()
+            }
+        },
+        "/api/remove-timetable" => {
+            let decoded = csv_from_utf8_or_return!(body.as_slice(), Response::bad_request());
+            let mut names = or_bad_request!(decoded.first()).iter().filter_map(|v| v.as_string());
+
+            match configs.remove_timetable(or_bad_request!(names.next())) {
+                Some(()) => Response::ok("Timetable successfully removed".into()),
+                None => Response::unprocessable_request()
+            }
+        },
+        // Each row is a first date, and an optional last date and name of a range of days off
+        "/api/set-holidays" => {
+            match configs.set_holidays_from_csv(csv_from_utf8_or_return!(body.as_slice(), Response::bad_request())) {
+                Some(()) => Response::ok("Holidays successfully set".into()),
+                None => Response::unprocessable_request()
+            }
+        },
+        "/api/add-holiday" => {
+            let decoded = csv_from_utf8_or_return!(body.as_slice(), Response::bad_request());
+
+            match configs.add_holiday_from_csv(or_bad_request!(decoded.first())) {
+                Some(()) => Response::ok("Holiday successfully added".into()),
+                None => Response::unprocessable_request()
+            }
+        },
+        // Takes an iCalendar file, the events of which are added as days off
+        "/api/import-holidays" => {
+            let text = or_bad_request!(str::from_utf8(body.as_slice()).ok());
+            let imported = configs.import_holidays_from_ics(text);
+
+            Response::ok(format!("Imported {imported} holidays").into())
+        },
+        "/api/set-weekday-timetables" => {
+            match configs.set_weekday_timetables_from_csv(csv_from_utf8_or_return!(body.as_slice(), Response::bad_request())) {
+                Some(()) => Response::ok("Weekday timetables successfully set".into()),
+                None => Response::unprocessable_request()
+            }
+        },
+        "/api/set-calendar" => {
+            match configs.set_calendar_from_csv(csv_from_utf8_or_return!(body.as_slice(), Response::bad_request())) {
+                Some(()) => Response::ok("Calendar successfully set".into()),
+                None => Response::unprocessable_request()
+            }
+        },
+        // Takes a date, or null for today, and the timetable used on it, or null for the one of its weekday
+        "/api/switch-timetable" => {
+            let decoded = csv_from_utf8_or_return!(body.as_slice(), Response::bad_request());
+            let line = or_bad_request!(decoded.first());
+
+            let date = match line.first().and_then(|x| x.as_string()) {
+                Some(date) => or_bad_request!(Date::from_iso(date)),
+                None => Date::today(configs.time_zone())
+            };
+
+            match configs.set_calendar_timetable(date, line.get(1).and_then(|x| x.as_string())) {
+                Some(()) => Response::ok("Timetable successfully switched".into()),
+                None => Response::unprocessable_request()
+            }
+        },
+        // Takes the offset in hours, which may be fractional for offsets such as +05:30
         "/api/set-utc-offset" => {
-            match str::from_utf8(body.as_slice()).ok().and_then(|s| str::parse::<i8>(s).ok()) {
-                Some(n @ -12..12) => {
-                    unsafe { configs.set_utc_offset_unchecked(n); }
-                    Response::ok("UTC offset successfully set".into())
+            match str::from_utf8(body.as_slice()).ok().and_then(|s| str::parse::<f32>(s.trim()).ok()) {
+                Some(hours) => match configs.set_utc_offset((hours * 3600.0).round() as i32) {
+                    Some(()) => Response::ok("UTC offset successfully set".into()),
+                    None => Response::unprocessable_request()
+                },
+                None => Response::bad_request()
+            }
+        },
+        // Takes the name of a zone of the tz database, such as `Europe/Budapest`
+        "/api/set-time-zone" => {
+            match str::from_utf8(body.as_slice()).ok() {
+                Some(name) => match configs.set_time_zone(name.trim()) {
+                    Some(()) => Response::ok("Time zone successfully set".into()),
+                    None => Response::unprocessable_request()
                 },
-                Some(_) => Response::unprocessable_request(),
                 None => Response::bad_request()
             }
         },
+        "/api/set-target-loudness" => {
+            match str::from_utf8(body.as_slice()).ok().and_then(|s| str::parse::<f32>(s.trim()).ok()) {
+                Some(loudness) => match configs.set_target_loudness(loudness) {
+                    Some(()) => Response::ok("Target loudness successfully set".into()),
+                    None => Response::unprocessable_request()
+                },
+                None => Response::bad_request()
+            }
+        },
+        "/api/set-sink" => {
+            match str::from_utf8(body.as_slice()).ok().and_then(SinkKind::from_spec) {
+                Some(sink) => match configs.set_sink(sink) {
+                    Some(()) => Response::ok("Sink successfully set".into()),
+                    None => Response::unprocessable_request()
+                },
+                None => Response::bad_request()
+            }
+        },
+        "/api/set-output-device" => {
+            let decoded = csv_from_utf8_or_return!(body.as_slice(), Response::bad_request());
+            let line = decoded.first().map(Vec::as_slice).unwrap_or_default();
+
+            // An empty or missing host selects the default one
+            let host = line.first().and_then(|x| x.as_string()).filter(|h| !h.is_empty());
+            let devices = line
+                .iter()
+                .skip(1)
+                .filter_map(|x| x.as_string())
+                .filter(|d| !d.is_empty())
+                .map(str::to_string)
+                .collect();
+
+            match configs.set_output_device(DeviceSelection { host: host.map(str::to_string), devices }) {
+                Some(()) => Response::ok("Output device successfully set".into()),
+                None => Response::unprocessable_request()
+            }
+        },
+        "/api/set-zones" => {
+            match configs.set_zones_from_csv(csv_from_utf8_or_return!(body.as_slice(), Response::bad_request())) {
+                Some(()) => Response::ok("Zones successfully set".into()),
+                None => Response::unprocessable_request()
+            }
+        },
+        "/api/set-fades" => {
+            let decoded = csv_from_utf8_or_return!(body.as_slice(), Response::bad_request());
+            let line = or_bad_request!(decoded.first());
+
+            let fade_in = or_bad_request!(line.first().and_then(|x| x.as_float()));
+            let fade_out = or_bad_request!(line.get(1).and_then(|x| x.as_float()));
+            let crossfade = line.get(2).and_then(|x| x.as_float()).map(|x| x as f32);
+            let gapless = line.get(3).and_then(|x| x.as_bool());
+
+            // Check the crossfade beforehand, so nothing is changed if it is invalid
+            return_unless!(
+                crossfade.is_none_or(|c| (0.0..=MAX_FADE_SECS).contains(&c)),
+                Response::unprocessable_request()
+            );
+            or_return!(configs.set_fades(fade_in as f32, fade_out as f32), Response::unprocessable_request());
+
+            if let Some(crossfade) = crossfade {
+                configs.set_crossfade(crossfade);
+            }
+            if let Some(gapless) = gapless {
+                configs.set_gapless(gapless);
+            }
+
+            Response::ok("Fades successfully set".into())
+        },
+        "/api/set-event-loudness" => {
+            let decoded = csv_from_utf8_or_return!(body.as_slice(), Response::bad_request());
+
+            for line in decoded {
+                let name = or_bad_request!(line.first().and_then(|x| x.as_string()));
+                let loudness = line.get(1).and_then(|x| x.as_float()).map(|x| x as f32);
+
+                or_return!(
+                    configs.set_event_target_loudness(name, loudness),
+                    Response::unprocessable_request()
+                );
+            }
+
+            Response::ok("Event loudness successfully set".into())
+        },
+        "/api/set-event-priority" => {
+            let decoded = csv_from_utf8_or_return!(body.as_slice(), Response::bad_request());
+
+            for line in decoded {
+                let name = or_bad_request!(line.first().and_then(|x| x.as_string()));
+                let priority = or_bad_request!(
+                    line.get(1).and_then(|x| x.as_string()).and_then(EventPriority::from_name)
+                );
+
+                or_return!(configs.set_event_priority(name, priority), Response::unprocessable_request());
+            }
+
+            Response::ok("Event priority successfully set".into())
+        },
+        "/api/set-song-trims" | "/api/set-song-cues" => {
+            let decoded = csv_from_utf8_or_return!(body.as_slice(), Response::bad_request());
+            let cues = uri.without_query_parameters() == "/api/set-song-cues";
+
+            for line in decoded {
+                let name = or_bad_request!(line.first().and_then(|x| x.as_string()));
+                let song = or_return!(
+                    database.inner_mut().iter_mut().find(|song| song.filename() == OsStr::new(name)),
+                    Response::not_found()
+                );
+
+                // Without positions, the cue points are removed, or the song is trimmed at its silence again
+                // once it is detected
+                let part = match (line.get(1).and_then(|x| x.as_float()), line.get(2).and_then(|x| x.as_float())) {
+                    (Some(start), Some(end)) => Some(Trim { start, end }),
+                    (None, None) => None,
+                    _ => return Response::bad_request()
+                };
+                return_unless!(part.is_none_or(|p| p.is_valid(song.duration())), Response::unprocessable_request());
+
+                if cues {
+                    song.set_cue(part);
+                } else {
+                    song.set_trim(part);
+                }
+            }
+
+            match cues {
+                true => Response::ok("Song cue points successfully set".into()),
+                false => Response::ok("Song trims successfully set".into())
+            }
+        },
+        "/api/set-event-zones" => {
+            let decoded = csv_from_utf8_or_return!(body.as_slice(), Response::bad_request());
+
+            for line in decoded {
+                let name = or_bad_request!(line.first().and_then(|x| x.as_string()));
+                let zones = line
+                    .iter()
+                    .skip(1)
+                    .filter_map(|x| x.as_string())
+                    .filter(|z| !z.is_empty())
+                    .map(Box::from)
+                    .collect();
+
+                or_return!(configs.set_event_zones(name, zones), Response::unprocessable_request());
+            }
+
+            Response::ok("Event zones successfully set".into())
+        },
         "/api/disable-songs" => {
             let mut success: u16 = 0;
 
@@ -596,19 +1156,86 @@ fn handle_post(
             let mut songs = Vec::new();
 
             for name in first_line_from_utf8_csv_or_return!(body.as_slice(), Response::bad_request()) {
-                songs.push(or_continue!(Song::new(Path::new(name))));
+                // Prefer the database's entry, which knows the loudness of the song
+                songs.push(or_continue!(
+                    database
+                        .inner()
+                        .iter()
+                        .find(|song| song.filename() == OsStr::new(name))
+                        .cloned()
+                        .or_else(|| Song::new(Path::new(name)))
+                ));
                 success += 1;
             }
 
-            std::thread::spawn(move || songs::play_playlist(&songs));
+            let settings = configs.playback_settings();
+            let sink = match configs.open_zone_sink(zone) {
+                Ok(sink) => sink,
+                Err(Error::UnknownZone) => return Response::not_found(),
+                Err(_) => return Response::internal_server_error()
+            };
+
+            players.get_mut(zone).spawn(move |control| songs::play_playlist(&songs, &sink, &settings, control));
 
             if success == 0 {
                 Response::new(404, "Not Found", Vec::new(), "All requests failed.".as_bytes().to_vec()).unwrap()
             } else {
-                logln!("Manual play started at {}", time::Time::now(configs.utc_offset()));
+                logln!("Manual play started at {}", time::Time::now(configs.time_zone()));
                 Response::ok(format!("{} successfully played", success).as_bytes().to_vec())
             }
         },
+        "/api/stop" => {
+            match players.get(zone).is_some_and(|p| p.stop()) {
+                true => Response::ok("Playback successfully stopped".into()),
+                false => Response::conflict()
+            }
+        },
+        "/api/stop-all" => {
+            match players.stop_all() {
+                true => Response::ok("Every playback successfully stopped".into()),
+                false => Response::conflict()
+            }
+        },
+        "/api/skip" => {
+            match players.get(zone).and_then(|p| p.current()).is_some_and(|p| p.skip()) {
+                true => Response::ok("Song successfully skipped".into()),
+                false => Response::conflict()
+            }
+        },
+        "/api/pause" => {
+            let current = or_return!(players.get(zone).and_then(|p| p.current()), Response::conflict());
+            current.pause();
+
+            Response::ok("Playback successfully paused".into())
+        },
+        "/api/resume" => {
+            let current = or_return!(players.get(zone).and_then(|p| p.current()), Response::conflict());
+            current.resume();
+
+            Response::ok("Playback successfully resumed".into())
+        },
+        "/api/seek" => {
+            let position = or_bad_request!(
+                str::from_utf8(body.as_slice()).ok().and_then(|s| str::parse::<f64>(s.trim()).ok())
+            );
+            return_unless!(position.is_finite() && position >= 0.0, Response::unprocessable_request());
+
+            let current = or_return!(players.get(zone).and_then(|p| p.current()), Response::conflict());
+            current.seek(position);
+
+            Response::ok("Playback successfully moved".into())
+        },
+        "/api/set-volume" => {
+            let gain = or_bad_request!(
+                str::from_utf8(body.as_slice()).ok().and_then(|s| str::parse::<f32>(s.trim()).ok())
+            );
+            return_unless!((0.0..=1.0).contains(&gain), Response::unprocessable_request());
+
+            match players.get(zone).is_some_and(|p| p.set_volume(gain)) {
+                true => Response::ok("Volume successfully changed".into()),
+                false => Response::conflict()
+            }
+        },
         "/api/delete-songs" => {
             let mut success: u16 = 0;
             let mut error: u16 = 0;
@@ -650,8 +1277,13 @@ fn handle_post(
             match std::fs::File::create_new(&file_path) {
                 Ok(mut file) => match file.write_all(file_contents) {
                     Ok(_) => {
-                        database.add_file(file_path.into_boxed_path()).realize(database, false).unwrap();
-                        Response::ok("File saved".as_bytes().to_vec())
+                        drop(file);
+
+                        // The song is added once its silence is detected and it is checked for duplicates,
+                        // so neither blocks the scheduler
+                        analyzer.add_upload(&file_path, uri.query_parameter("allow-duplicates").as_deref() == Some("true"));
+
+                        Response::accepted("File saved, and added once it is analyzed".as_bytes().to_vec())
                     },
                     Err(_) => { drop(file); let _ = std::fs::remove_file(file_path); Response::internal_server_error() }
                 },
@@ -738,6 +1370,9 @@ fn handle_post(
                 let name = or_bad_request!(line.get(0).and_then(|x| x.as_string()));
 
                 event_queue.remove_by_name(name);
+                configs.set_event_target_loudness(name, None);
+                configs.set_event_zones(name, Vec::new());
+                configs.set_event_priority(name, EventPriority::Normal);
             }
 
             Response::ok("Event successfully removed".as_bytes().to_vec())